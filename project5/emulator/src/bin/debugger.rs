@@ -0,0 +1,97 @@
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::process::ExitCode;
+
+use emulator::debugger::Debugger;
+
+const USAGE: &str = r#"Usage:
+    debugger <file.asm|file.hack> [options]
+
+Options:
+  -c <n>,    --cycles <n>               Stops `continue` after <n> instructions (default 100000000)
+  -x <file>, --commands <file>          Runs the commands in <file> before reading from standard input
+  -h,        --help                     Prints help message
+"#;
+
+fn main() -> ExitCode {
+    let mut args = std::env::args();
+    args.next();
+    let mut input_file = None;
+    let mut max_cycles = None;
+    let mut commands_file = None;
+    while let Some(arg) = args.next() {
+        let parsed = match arg.as_str() {
+            "-c" | "--cycles" => args
+                .next()
+                .and_then(|n| n.parse().ok())
+                .map(|n| max_cycles = Some(n)),
+            "-x" | "--commands" => args.next().map(|f| commands_file = Some(f)),
+            "-h" | "--help" => {
+                print!("{}", USAGE);
+                return ExitCode::SUCCESS;
+            }
+            _ => {
+                input_file = Some(arg.clone());
+                Some(())
+            }
+        };
+        if parsed.is_none() {
+            eprintln!("Invalid value for {}", arg);
+            return ExitCode::FAILURE;
+        }
+    }
+
+    let Some(input_file) = input_file else {
+        print!("{}", USAGE);
+        return ExitCode::FAILURE;
+    };
+    let program = match emulator::load_program(Path::new(&input_file)) {
+        Ok(program) => program,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let mut debugger = Debugger::new(program);
+    if let Some(n) = max_cycles {
+        debugger.max_cycles = n;
+    }
+
+    let mut script = Vec::new();
+    if let Some(path) = commands_file {
+        match std::fs::read_to_string(&path) {
+            Ok(src) => script.extend(src.lines().map(str::to_owned)),
+            Err(_) => {
+                eprintln!("Couldn't open file: {}", path);
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+
+    println!("{}", debugger.describe(debugger.cpu.pc));
+    let mut lines = script.into_iter().map(Ok).chain(io::stdin().lock().lines());
+    let mut last = String::new();
+    loop {
+        print!("(hdb) ");
+        let _ = io::stdout().flush();
+        let Some(Ok(line)) = lines.next() else {
+            println!();
+            return ExitCode::SUCCESS;
+        };
+        let line = match line.trim() {
+            "" => last.clone(),
+            line => line.to_owned(),
+        };
+        if line.is_empty() {
+            continue;
+        }
+        match line.as_str() {
+            "q" | "quit" => return ExitCode::SUCCESS,
+            _ => match debugger.execute(&line) {
+                Ok(out) => println!("{}", out),
+                Err(e) => println!("error: {}", e),
+            },
+        }
+        last = line;
+    }
+}
@@ -0,0 +1,208 @@
+// Runs a program with the Jack OS as VM code and with the native OS side by side, to find
+// where they first differ. The two take very different numbers of steps for the same OS
+// call, so they're kept in lockstep by the program's own commands instead: every OS call is
+// run to its return on both sides, and the screens are compared afterwards, along with the
+// returned value unless it's a heap address. The rest of RAM isn't compared, as the two
+// heaps hand out different addresses, but new blocks get the VM OS's contents on both
+// sides, so a program reading them before writing them goes on the same way.
+
+use vm_translator::VMInstruction;
+
+use crate::memory::{SCREEN, SCREEN_SIZE};
+use crate::os;
+use crate::vm::Vm;
+
+const SP: u16 = 0;
+
+// OS calls which return heap addresses, or might read one.
+const ADDRESS_RESULTS: &[&str] = &[
+    "Memory.alloc",
+    "Memory.peek",
+    "Array.new",
+    "String.new",
+    "String.appendChar",
+    "Keyboard.readLine",
+];
+
+// OS calls which project 12's OS doesn't have to draw like the course's, and so the native,
+// one does: `Output.moveCursor` erases the character at the new cursor position in the
+// course's OS only. A screen difference after them is skipped, the VM OS's screen being
+// copied over the native one.
+const SCREEN_DIFFERENCES: &[&str] = &["Output.moveCursor"];
+
+// OS calls returning a new block of heap memory, whose size is their only argument.
+const NEW_BLOCKS: &[&str] = &["Memory.alloc", "Array.new"];
+
+// OS calls freeing the block passed to them.
+const FREES: &[&str] = &["Memory.deAlloc", "Array.dispose"];
+
+// How many steps an OS call may take before it's given up on, like `Keyboard.readChar`
+// when nothing is ever typed.
+const OS_CALL_STEPS: u64 = 100_000_000;
+
+pub struct Comparison {
+    // How many of the program's own commands both ran.
+    pub steps: u64,
+    pub halted: bool,
+    // How many screen differences were skipped after calls in `SCREEN_DIFFERENCES`.
+    pub skipped: u64,
+    // What differed first, None if nothing did.
+    pub difference: Option<String>,
+}
+
+// Runs one command of the program, and if it calls the OS, everything up to the call's
+// return. Returns true once the program halts, which the OS does in `Sys.halt`.
+fn run_command(vm: &mut Vm, os_call: bool) -> Result<bool, String> {
+    let start = vm.pc;
+    vm.step()?;
+    let mut steps = 0;
+    // The native OS stays on the call while it waits, the VM OS runs its own functions.
+    while os_call && (vm.pc == start || os::is_os_function(vm.current_function())) {
+        if vm.is_halted() || vm.current_function() == "Sys.halt" {
+            return Ok(true);
+        }
+        if steps == OS_CALL_STEPS {
+            return Err(format!(
+                "the call didn't return within {} steps",
+                OS_CALL_STEPS
+            ));
+        }
+        vm.step()?;
+        steps += 1;
+    }
+    Ok(vm.is_halted())
+}
+
+// The first pixel which differs between the two screens, as (x, y).
+fn screen_difference(a: &Vm, b: &Vm) -> Option<(usize, usize)> {
+    let (a, b) = (a.memory.screen(), b.memory.screen());
+    let offset = (0..SCREEN_SIZE).find(|&i| a[i] != b[i])?;
+    let bit = (a[offset] ^ b[offset]).trailing_zeros() as usize;
+    Some((offset % 32 * 16 + bit, offset / 32))
+}
+
+// Compares the program in `vm_os`, which has the OS's VM code loaded, with the same program
+// in `native_os`, which runs the native OS instead, for up to `max_steps` of the program's
+// own commands. Both have to be bootstrapped already.
+pub fn compare_os(
+    vm_os: &mut Vm,
+    native_os: &mut Vm,
+    max_steps: u64,
+) -> Result<Comparison, String> {
+    let mut comparison = Comparison {
+        steps: 0,
+        halted: false,
+        skipped: 0,
+        difference: None,
+    };
+    // The blocks the program got from `NEW_BLOCKS` with the VM OS and hasn't freed, as
+    // (address, size), to catch the VM OS handing out memory still in use.
+    let mut blocks: Vec<(u16, u16)> = Vec::new();
+    while comparison.steps < max_steps {
+        if vm_os.is_halted() {
+            comparison.halted = true;
+            break;
+        }
+        let call = match vm_os.instruction() {
+            Some(VMInstruction::Call(name, _)) if os::is_os_function(name) => Some(name.clone()),
+            _ => None,
+        };
+        let caller = vm_os.current_function().to_owned();
+        let last_arg = vm_os.memory.read(vm_os.memory.read(SP).wrapping_sub(1));
+        let halted = run_command(vm_os, call.is_some())
+            .map_err(|e| format!("error with the VM OS: {} in {}", e, caller))?;
+        let native_halted = run_command(native_os, call.is_some())
+            .map_err(|e| format!("error with the native OS: {} in {}", e, caller))?;
+        comparison.steps += 1;
+
+        let during = match &call {
+            Some(name) => format!("after {} in {}", name, caller),
+            None => format!("in {}", caller),
+        };
+        if halted != native_halted {
+            comparison.difference = Some(format!(
+                "the program halts {} with the {} OS only",
+                during,
+                if halted { "VM" } else { "native" }
+            ));
+            break;
+        }
+        if halted {
+            comparison.halted = true;
+            break;
+        }
+        // Reading memory the OS left behind, like a new array's, can make the program branch
+        // differently even though every call returned the same.
+        if vm_os.pc != native_os.pc {
+            comparison.difference = Some(if call.is_none() {
+                format!("the program branches differently {}", during)
+            } else {
+                format!(
+                    "the program goes on in {} with the VM OS, but in {} with the native OS, {}",
+                    vm_os.current_function(),
+                    native_os.current_function(),
+                    during
+                )
+            });
+            break;
+        }
+        let Some(name) = call else {
+            continue;
+        };
+        let result = |vm: &Vm| vm.memory.read(vm.memory.read(SP).wrapping_sub(1));
+        if FREES.contains(&name.as_str()) {
+            blocks.retain(|&(addr, _)| addr != last_arg);
+        }
+        if NEW_BLOCKS.contains(&name.as_str()) {
+            let (from, to) = (result(vm_os), result(native_os));
+            let end = from.wrapping_add(last_arg);
+            if let Some(&(addr, size)) = blocks
+                .iter()
+                .find(|&&(addr, size)| from < addr + size && addr < end)
+            {
+                comparison.difference = Some(format!(
+                    "{} returns RAM[{}..{}] with the VM OS, which overlaps RAM[{}..{}] still in use, in {}",
+                    name,
+                    from,
+                    end,
+                    addr,
+                    addr + size,
+                    caller
+                ));
+                break;
+            }
+            blocks.push((from, last_arg));
+            for i in 0..last_arg {
+                let value = vm_os.memory.read(from.wrapping_add(i));
+                native_os.memory.write(to.wrapping_add(i), value);
+            }
+        }
+        if SCREEN_DIFFERENCES.contains(&name.as_str()) {
+            if screen_difference(vm_os, native_os).is_some() {
+                comparison.skipped += 1;
+                native_os
+                    .memory
+                    .screen_mut()
+                    .copy_from_slice(vm_os.memory.screen());
+            }
+        } else if let Some((x, y)) = screen_difference(vm_os, native_os) {
+            let word = SCREEN as usize + y * 32 + x / 16;
+            comparison.difference = Some(format!(
+                "the screen differs at ({}, {}), RAM[{}], {}",
+                x, y, word, during
+            ));
+            break;
+        }
+        if !ADDRESS_RESULTS.contains(&name.as_str()) && result(vm_os) != result(native_os) {
+            comparison.difference = Some(format!(
+                "{} returns {} with the VM OS, but {} with the native OS, in {}",
+                name,
+                result(vm_os) as i16,
+                result(native_os) as i16,
+                caller
+            ));
+            break;
+        }
+    }
+    Ok(comparison)
+}
@@ -0,0 +1,382 @@
+use crate::memory::Memory;
+
+pub const ROM_SIZE: usize = 0x8000;
+
+// The Hack CPU together with its instruction and data memory, i.e. the whole Computer chip.
+#[derive(Clone)]
+pub struct Cpu {
+    pub a: u16,
+    pub d: u16,
+    pub pc: u16,
+    pub rom: Box<[u16]>,
+    pub memory: Memory,
+    pub cycles: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    // The condition passed to `run_until` became true.
+    Condition,
+    // The program reached an `(END) @END 0;JMP` style infinite loop.
+    Halted,
+    CycleLimit,
+}
+
+impl Cpu {
+    // Loads `program` at the beginning of the ROM, the rest is filled with zeroes.
+    pub fn new(program: &[u16]) -> Cpu {
+        let mut rom = vec![0; ROM_SIZE].into_boxed_slice();
+        let len = program.len().min(ROM_SIZE);
+        rom[..len].copy_from_slice(&program[..len]);
+        Cpu {
+            a: 0,
+            d: 0,
+            pc: 0,
+            rom,
+            memory: Memory::new(),
+            cycles: 0,
+        }
+    }
+
+    // Like pressing the reset button; memory keeps its contents.
+    pub fn reset(&mut self) {
+        self.pc = 0;
+    }
+
+    pub fn instruction(&self) -> u16 {
+        self.rom[self.pc as usize % ROM_SIZE]
+    }
+
+    // Executes a single instruction.
+    pub fn step(&mut self) {
+        let instruction = self.instruction();
+        self.cycles += 1;
+        if instruction & 0x8000 == 0 {
+            self.a = instruction;
+            self.pc = self.pc.wrapping_add(1);
+            return;
+        }
+
+        let y = if instruction & 0x1000 != 0 {
+            self.memory.read(self.a)
+        } else {
+            self.a
+        };
+        let (out, zr, ng) = alu(self.d, y, (instruction >> 6) as u8 & 0x3f);
+
+        // Both the written address and the jump target are the A register before this cycle.
+        let addr = self.a;
+        if instruction & 0b001_000 != 0 {
+            self.memory.write(addr, out);
+        }
+        if instruction & 0b100_000 != 0 {
+            self.a = out;
+        }
+        if instruction & 0b010_000 != 0 {
+            self.d = out;
+        }
+
+        let jump = (instruction & 0b100 != 0 && ng)
+            || (instruction & 0b010 != 0 && zr)
+            || (instruction & 0b001 != 0 && !ng && !zr);
+        self.pc = if jump { addr } else { self.pc.wrapping_add(1) };
+    }
+
+    // Recognizes the idiomatic way of ending a Hack program: `@X` at address X
+    // followed by an unconditional jump.
+    pub fn is_halted(&self) -> bool {
+        let pc = self.pc as usize % ROM_SIZE;
+        let next = self.rom[(pc + 1) % ROM_SIZE];
+        self.rom[pc] == self.pc && self.pc & 0x8000 == 0 && next & 0xe007 == 0xe007
+    }
+
+    // Steps until `condition` holds, the program halts or `max_cycles` instructions were run.
+    pub fn run_until(
+        &mut self,
+        max_cycles: u64,
+        mut condition: impl FnMut(&Cpu) -> bool,
+    ) -> StopReason {
+        for _ in 0..max_cycles {
+            if condition(self) {
+                return StopReason::Condition;
+            }
+            if self.is_halted() {
+                return StopReason::Halted;
+            }
+            self.step();
+        }
+        StopReason::CycleLimit
+    }
+
+    pub fn run(&mut self, max_cycles: u64) -> StopReason {
+        self.run_until(max_cycles, |_| false)
+    }
+}
+
+// The Hack ALU; `control` holds the zx, nx, zy, ny, f and no bits, zx being the most significant.
+// Returns the output along with the zr and ng flags.
+pub fn alu(x: u16, y: u16, control: u8) -> (u16, bool, bool) {
+    let x = if control & 0b100000 != 0 { 0 } else { x };
+    let x = if control & 0b010000 != 0 { !x } else { x };
+    let y = if control & 0b001000 != 0 { 0 } else { y };
+    let y = if control & 0b000100 != 0 { !y } else { y };
+    let out = if control & 0b000010 != 0 {
+        x.wrapping_add(y)
+    } else {
+        x & y
+    };
+    let out = if control & 0b000001 != 0 { !out } else { out };
+    (out, out == 0, out & 0x8000 != 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::Vm;
+
+    #[test]
+    fn runs_mult() {
+        let src = std::fs::read_to_string(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../../project4/Mult.asm"
+        ))
+        .unwrap();
+        let program = assembler::assemble(&src).unwrap();
+        for (r0, r1) in [(0, 0), (1, 0), (0, 7), (6, 7), (3, 1), (127, 258)] {
+            let mut cpu = Cpu::new(&program);
+            cpu.memory.write(0, r0);
+            cpu.memory.write(1, r1);
+            assert_eq!(cpu.run(100_000), StopReason::Halted);
+            assert_eq!(cpu.memory.read(2), r0 * r1, "R0 = {}, R1 = {}", r0, r1);
+        }
+    }
+
+    // Sys.init stores the factorials of 0 to 7 at 3000, and whether they're below 1000 at
+    // 3010, through `that`.
+    const FACTORIALS: &str = "\
+function Sys.init 1
+push constant 3000
+pop pointer 1
+label LOOP
+push local 0
+call Main.factorial 1
+pop that 0
+push that 0
+push constant 1000
+lt
+pop that 10
+push pointer 1
+push constant 1
+add
+pop pointer 1
+push local 0
+push constant 1
+add
+pop local 0
+push local 0
+push constant 8
+lt
+if-goto LOOP
+label HALT
+goto HALT
+function Main.factorial 0
+push argument 0
+push constant 0
+eq
+not
+if-goto RECURSE
+push constant 1
+return
+label RECURSE
+push argument 0
+push argument 0
+push constant 1
+sub
+call Main.factorial 1
+call Main.multiply 2
+return
+function Main.multiply 1
+label LOOP
+push argument 1
+push constant 0
+gt
+not
+if-goto END
+push local 0
+push argument 0
+add
+pop local 0
+push argument 1
+push constant 1
+sub
+pop argument 1
+goto LOOP
+label END
+push local 0
+return
+";
+
+    #[test]
+    fn translated_vm_code_matches_the_vm_emulator() {
+        let files = [vm_translator::SourceFile {
+            name: "Main".to_owned(),
+            path: "Main.vm".to_owned(),
+            src: FACTORIALS.to_owned(),
+        }];
+        let mut vm = Vm::new(&[("Main".to_owned(), FACTORIALS.to_owned())]).unwrap();
+        vm.bootstrap().unwrap();
+        while !vm.is_halted() {
+            vm.step().unwrap();
+        }
+        let factorials: Vec<u16> = (3000..3008).map(|a| vm.memory.read(a)).collect();
+        assert_eq!(factorials, [1, 1, 2, 6, 24, 120, 720, 5040]);
+
+        // Every mode of the translator has to agree with the VM emulator.
+        for (optimize, shared_routines) in
+            [(false, false), (true, false), (false, true), (true, true)]
+        {
+            let mut asm = Vec::new();
+            let options = vm_translator::Options {
+                optimize,
+                shared_routines,
+                ..Default::default()
+            };
+            vm_translator::compile_program(&files, &mut asm, options).unwrap();
+            let program = assembler::assemble(&String::from_utf8(asm).unwrap()).unwrap();
+            let mut cpu = Cpu::new(&program);
+            assert_eq!(cpu.run(1_000_000), StopReason::Halted);
+            for addr in (3000..3008).chain(3010..3018) {
+                assert_eq!(
+                    cpu.memory.read(addr),
+                    vm.memory.read(addr),
+                    "RAM[{}] with -O {} and -Os {}",
+                    addr,
+                    optimize,
+                    shared_routines
+                );
+            }
+        }
+    }
+
+    // Sys.init points `this` at 4000 and `that` at 4100, then Main.mix(10, 3) stores the
+    // result of every arithmetic command at 4100, the sum of 1 to 10 at 4109, and returns
+    // 7 + 5 into static 1.
+    const EVERY_COMMAND: &str = "\
+function Sys.init 0
+push constant 4000
+pop pointer 0
+push constant 4100
+pop pointer 1
+push constant 5
+pop this 1
+push constant 10
+pop static 0
+push constant 3
+pop temp 2
+push static 0
+push temp 2
+call Main.mix 2
+pop static 1
+label HALT
+goto HALT
+function Main.mix 2
+push argument 0
+push argument 1
+sub
+pop local 0
+push local 0
+neg
+pop that 0
+push argument 0
+push argument 1
+add
+pop that 1
+push argument 0
+push argument 1
+gt
+pop that 2
+push argument 0
+push argument 1
+lt
+pop that 3
+push argument 0
+push argument 0
+eq
+pop that 4
+push argument 0
+push argument 1
+eq
+pop that 5
+push argument 0
+push argument 1
+and
+pop that 6
+push argument 0
+push argument 1
+or
+pop that 7
+push argument 1
+not
+pop that 8
+label LOOP
+push local 1
+push argument 0
+add
+pop local 1
+push argument 0
+push constant 1
+sub
+pop argument 0
+push argument 0
+if-goto LOOP
+push local 1
+pop that 9
+push pointer 1
+pop temp 0
+push local 0
+push this 1
+add
+return
+";
+
+    #[test]
+    fn optimized_builds_leave_the_same_ram() {
+        let files = [vm_translator::SourceFile {
+            name: "Main".to_owned(),
+            path: "Main.vm".to_owned(),
+            src: EVERY_COMMAND.to_owned(),
+        }];
+        let run = |optimize, shared_routines| {
+            let mut asm = Vec::new();
+            let options = vm_translator::Options {
+                optimize,
+                shared_routines,
+                ..Default::default()
+            };
+            vm_translator::compile_program(&files, &mut asm, options).unwrap();
+            let program = assembler::assemble(&String::from_utf8(asm).unwrap()).unwrap();
+            let mut cpu = Cpu::new(&program);
+            assert_eq!(cpu.run(100_000), StopReason::Halted);
+            cpu
+        };
+        let plain = run(false, false);
+        let results: Vec<i16> = (4100..4110).map(|a| plain.memory.read(a) as i16).collect();
+        assert_eq!(results, [-7, 13, -1, 0, -1, 0, 2, 11, -4, 55]);
+        assert_eq!(plain.memory.read(17), 12);
+
+        for (optimize, shared_routines) in [(true, false), (false, true), (true, true)] {
+            let cpu = run(optimize, shared_routines);
+            // The stack is left alone, only the memory the program stores into is compared.
+            for addr in (3..13).chain(16..32).chain(4000..4010).chain(4100..4110) {
+                assert_eq!(
+                    cpu.memory.read(addr),
+                    plain.memory.read(addr),
+                    "RAM[{}] with -O {} and -Os {}",
+                    addr,
+                    optimize,
+                    shared_routines
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,350 @@
+use std::collections::VecDeque;
+use std::fmt::Write;
+
+use assembler::{disasm, SymbolKind, SymbolTable};
+
+use crate::cpu::{Cpu, ROM_SIZE};
+use crate::Program;
+
+pub const HELP: &str = r#"Commands:
+  s, step [n]              Executes the next <n> instructions (default 1)
+  rs, reverse [n]          Undoes the last <n> instructions (default 1)
+  c, continue              Runs until a breakpoint, a watchpoint or the end of the program
+  b, break <addr|label>    Stops before executing the instruction at <addr>
+  w, watch <addr|symbol>   Stops after RAM[addr] changes
+  d, delete <addr|label>   Removes a breakpoint or a watchpoint
+  i, info                  Lists breakpoints and watchpoints
+  r, regs                  Prints the registers and the VM pointers
+  p, print <loc>           Prints A, D, PC, RAM[addr], a symbol or an address
+  x <addr|symbol> [n]      Prints <n> words of RAM starting at <addr> (default 8)
+  l, list [n]              Disassembles <n> instructions around PC (default 5)
+  set <loc> <value>        Sets A, D, PC or a RAM location
+  reset                    Jumps back to the first instruction, keeping RAM
+  q, quit                  Exits
+Pressing enter repeats the last command.
+"#;
+
+// How many instructions can be undone.
+pub const HISTORY_SIZE: usize = 100_000;
+
+// What's needed to undo a single instruction.
+#[derive(Debug, Clone, Copy)]
+struct Undo {
+    a: u16,
+    d: u16,
+    pc: u16,
+    // The RAM address the instruction wrote to and its previous value.
+    write: Option<(u16, u16)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stop {
+    Breakpoint(u16),
+    Watchpoint { addr: u16, old: u16, new: u16 },
+    Halted,
+    CycleLimit,
+}
+
+pub struct Debugger {
+    pub cpu: Cpu,
+    pub symbols: SymbolTable,
+    source: Vec<String>,
+    breakpoints: Vec<u16>,
+    watchpoints: Vec<u16>,
+    history: VecDeque<Undo>,
+    // Upper bound on the number of instructions a single `continue` runs.
+    pub max_cycles: u64,
+}
+
+impl Debugger {
+    pub fn new(program: Program) -> Debugger {
+        Debugger {
+            cpu: Cpu::new(&program.words),
+            symbols: program.symbols,
+            source: program.source,
+            breakpoints: Vec::new(),
+            watchpoints: Vec::new(),
+            history: VecDeque::new(),
+            max_cycles: 100_000_000,
+        }
+    }
+
+    // Executes one instruction, remembering how to undo it.
+    pub fn step(&mut self) -> Option<(u16, u16)> {
+        let instruction = self.cpu.instruction();
+        let write = (instruction & 0x8000 != 0 && instruction & 0b001_000 != 0)
+            .then(|| (self.cpu.a, self.cpu.memory.read(self.cpu.a)));
+        if self.history.len() == HISTORY_SIZE {
+            self.history.pop_front();
+        }
+        self.history.push_back(Undo {
+            a: self.cpu.a,
+            d: self.cpu.d,
+            pc: self.cpu.pc,
+            write,
+        });
+        self.cpu.step();
+        write
+    }
+
+    // Undoes the last instruction, false if there's no more history.
+    pub fn reverse(&mut self) -> bool {
+        let Some(undo) = self.history.pop_back() else {
+            return false;
+        };
+        self.cpu.a = undo.a;
+        self.cpu.d = undo.d;
+        self.cpu.pc = undo.pc;
+        if let Some((addr, val)) = undo.write {
+            self.cpu.memory.write(addr, val);
+        }
+        self.cpu.cycles -= 1;
+        true
+    }
+
+    // Runs at least one instruction, then until something worth stopping for happens.
+    pub fn cont(&mut self) -> Stop {
+        for _ in 0..self.max_cycles {
+            if self.cpu.is_halted() {
+                return Stop::Halted;
+            }
+            if let Some((addr, old)) = self.step() {
+                let new = self.cpu.memory.read(addr);
+                if new != old && self.watchpoints.contains(&addr) {
+                    return Stop::Watchpoint { addr, old, new };
+                }
+            }
+            if self.breakpoints.contains(&self.cpu.pc) {
+                return Stop::Breakpoint(self.cpu.pc);
+            }
+        }
+        Stop::CycleLimit
+    }
+
+    // The first label pointing at the given ROM address.
+    pub fn label_at(&self, addr: u16) -> Option<&str> {
+        self.symbols
+            .entries()
+            .into_iter()
+            .find(|&(_, val, kind)| kind == SymbolKind::Label && val == addr)
+            .map(|(name, _, _)| name)
+    }
+
+    // A ROM address, given as a number or a label.
+    fn rom_address(&self, s: &str) -> Result<u16, String> {
+        if let Ok(addr) = s.parse::<u16>() {
+            return ((addr as usize) < ROM_SIZE)
+                .then_some(addr)
+                .ok_or_else(|| format!("{} is past the end of the ROM", addr));
+        }
+        match self.symbols.kind(s) {
+            Some(SymbolKind::Label) => Ok(self.symbols.get(s).unwrap()),
+            _ => Err(format!("unknown label {}", s)),
+        }
+    }
+
+    // A RAM address, given as a number, `RAM[n]` or a symbol like `SP` or a variable.
+    fn ram_address(&self, s: &str) -> Result<u16, String> {
+        let s = s
+            .strip_prefix("RAM[")
+            .and_then(|s| s.strip_suffix(']'))
+            .unwrap_or(s);
+        if let Ok(addr) = s.parse::<u16>() {
+            return Ok(addr);
+        }
+        match self.symbols.kind(s) {
+            Some(SymbolKind::Predefined | SymbolKind::Variable) => Ok(self.symbols.get(s).unwrap()),
+            _ => Err(format!("unknown variable {}", s)),
+        }
+    }
+
+    // The instruction at `addr`, with its label and source line when there are any.
+    pub fn describe(&self, addr: u16) -> String {
+        let word = self.cpu.rom[addr as usize % ROM_SIZE];
+        let mut out = String::new();
+        if let Some(label) = self.label_at(addr) {
+            let _ = writeln!(out, "({})", label);
+        }
+        let instruction = match disasm::decode(word) {
+            Ok(instruction) => instruction.to_string(),
+            Err(_) => format!("invalid {:016b}", word),
+        };
+        let marker = if addr == self.cpu.pc { "=>" } else { "  " };
+        let _ = write!(out, "{} {:5}  {:<16}", marker, addr, instruction);
+        if let Some(source) = self.source.get(addr as usize) {
+            let _ = write!(out, " // line {}", source.trim_start());
+        }
+        out
+    }
+
+    pub fn registers(&self) -> String {
+        let cpu = &self.cpu;
+        let mut out = format!(
+            "A={} D={} PC={} cycles={}\n",
+            cpu.a as i16, cpu.d as i16, cpu.pc, cpu.cycles
+        );
+        let pointers: Vec<String> = ["SP", "LCL", "ARG", "THIS", "THAT"]
+            .iter()
+            .enumerate()
+            .map(|(i, name)| format!("{}={}", name, cpu.memory.read(i as u16)))
+            .collect();
+        out.push_str(&pointers.join(" "));
+        out
+    }
+
+    fn print(&self, loc: &str) -> Result<String, String> {
+        let cpu = &self.cpu;
+        Ok(match loc {
+            "A" => format!("A = {}", cpu.a as i16),
+            "D" => format!("D = {}", cpu.d as i16),
+            "PC" => format!("PC = {}", cpu.pc),
+            _ if self.symbols.kind(loc) == Some(SymbolKind::Label) => {
+                format!("{} = ROM[{}]", loc, self.symbols.get(loc).unwrap())
+            }
+            _ => {
+                let addr = self.ram_address(loc)?;
+                let val = cpu.memory.read(addr);
+                format!("RAM[{}] = {} ({:#06x})", addr, val as i16, val)
+            }
+        })
+    }
+
+    fn stop_message(&self, stop: Stop) -> String {
+        let reason = match stop {
+            Stop::Breakpoint(addr) => format!("Breakpoint at {}", addr),
+            Stop::Watchpoint { addr, old, new } => {
+                format!(
+                    "RAM[{}] changed from {} to {}",
+                    addr, old as i16, new as i16
+                )
+            }
+            Stop::Halted => "Program halted".to_owned(),
+            Stop::CycleLimit => format!("Stopped after {} cycles", self.max_cycles),
+        };
+        format!("{}\n{}", reason, self.describe(self.cpu.pc))
+    }
+
+    // Runs a single debugger command, returning what to print.
+    pub fn execute(&mut self, line: &str) -> Result<String, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        if words.is_empty() {
+            return Ok(String::new());
+        }
+        let count = |arg: Option<&&str>, default| match arg {
+            Some(n) => n
+                .parse::<usize>()
+                .map_err(|_| format!("invalid count {}", n)),
+            None => Ok(default),
+        };
+        match (words[0], &words[1..]) {
+            ("s" | "step", args) if args.len() <= 1 => {
+                for _ in 0..count(args.first(), 1)? {
+                    self.step();
+                }
+                Ok(self.describe(self.cpu.pc))
+            }
+            ("rs" | "reverse", args) if args.len() <= 1 => {
+                let n = count(args.first(), 1)?;
+                let undone = (0..n).take_while(|_| self.reverse()).count();
+                let mut out = self.describe(self.cpu.pc);
+                if undone < n {
+                    out.insert_str(0, "Reached the start of the history\n");
+                }
+                Ok(out)
+            }
+            ("c" | "continue", []) => {
+                let stop = self.cont();
+                Ok(self.stop_message(stop))
+            }
+            ("b" | "break", [loc]) => {
+                let addr = self.rom_address(loc)?;
+                if !self.breakpoints.contains(&addr) {
+                    self.breakpoints.push(addr);
+                }
+                Ok(format!("Breakpoint set at {}", addr))
+            }
+            ("w" | "watch", [loc]) => {
+                let addr = self.ram_address(loc)?;
+                if !self.watchpoints.contains(&addr) {
+                    self.watchpoints.push(addr);
+                }
+                Ok(format!("Watching RAM[{}]", addr))
+            }
+            ("d" | "delete", [loc]) => {
+                let bp = self.rom_address(loc).ok();
+                let wp = self.ram_address(loc).ok();
+                let before = self.breakpoints.len() + self.watchpoints.len();
+                self.breakpoints.retain(|&b| Some(b) != bp);
+                self.watchpoints.retain(|&w| Some(w) != wp);
+                if before == self.breakpoints.len() + self.watchpoints.len() {
+                    return Err(format!("no breakpoint or watchpoint at {}", loc));
+                }
+                Ok(format!("Deleted {}", loc))
+            }
+            ("i" | "info", []) => {
+                let mut out = String::new();
+                for &addr in &self.breakpoints {
+                    let label = self.label_at(addr).unwrap_or("");
+                    let _ = writeln!(out, "breakpoint {:5} {}", addr, label);
+                }
+                for &addr in &self.watchpoints {
+                    let _ = writeln!(out, "watchpoint RAM[{}]", addr);
+                }
+                if out.is_empty() {
+                    out.push_str("No breakpoints or watchpoints");
+                }
+                Ok(out.trim_end().to_owned())
+            }
+            ("r" | "regs", []) => Ok(self.registers()),
+            ("p" | "print", [loc]) => self.print(loc),
+            ("x", [loc, args @ ..]) if args.len() <= 1 => {
+                let start = self.ram_address(loc)?;
+                let lines: Vec<String> = (0..count(args.first(), 8)?)
+                    .map(|i| start.wrapping_add(i as u16))
+                    .map(|addr| {
+                        let val = self.cpu.memory.read(addr);
+                        format!("RAM[{}] = {} ({:#06x})", addr, val as i16, val)
+                    })
+                    .collect();
+                Ok(lines.join("\n"))
+            }
+            ("l" | "list", args) if args.len() <= 1 => {
+                let n = count(args.first(), 5)?;
+                let pc = self.cpu.pc as usize;
+                let start = pc.saturating_sub(n / 2);
+                let lines: Vec<String> = (start..(start + n).min(ROM_SIZE))
+                    .map(|addr| self.describe(addr as u16))
+                    .collect();
+                Ok(lines.join("\n"))
+            }
+            ("set", [loc, val]) => {
+                let val = val
+                    .parse::<i32>()
+                    .ok()
+                    .filter(|v| (-32768..=65535).contains(v))
+                    .ok_or_else(|| format!("invalid value {}", val))?
+                    as u16;
+                match *loc {
+                    "A" => self.cpu.a = val,
+                    "D" => self.cpu.d = val,
+                    "PC" => self.cpu.pc = val,
+                    _ => {
+                        let addr = self.ram_address(loc)?;
+                        self.cpu.memory.write(addr, val);
+                    }
+                }
+                self.print(loc)
+            }
+            ("reset", []) => {
+                self.cpu.reset();
+                self.history.clear();
+                Ok(self.describe(self.cpu.pc))
+            }
+            ("h" | "help", []) => Ok(HELP.trim_end().to_owned()),
+            (command, _) => Err(format!(
+                "invalid command `{}`, type `help` for a list of commands",
+                command
+            )),
+        }
+    }
+}
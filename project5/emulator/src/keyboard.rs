@@ -0,0 +1,102 @@
+use crate::memory::Memory;
+
+// Key codes of the Hack keyboard which aren't plain characters.
+pub const KEYS: [(&str, u16); 14] = [
+    ("NEWLINE", 128),
+    ("BACKSPACE", 129),
+    ("LEFT", 130),
+    ("UP", 131),
+    ("RIGHT", 132),
+    ("DOWN", 133),
+    ("HOME", 134),
+    ("END", 135),
+    ("PAGEUP", 136),
+    ("PAGEDOWN", 137),
+    ("INSERT", 138),
+    ("DELETE", 139),
+    ("ESC", 140),
+    ("SPACE", 32),
+];
+
+// Parses a key name like `LEFT`, `F3`, a single character like `c` or a raw code like `#67`.
+pub fn key_code(name: &str) -> Option<u16> {
+    let upper = name.to_ascii_uppercase();
+    if let Some(&(_, code)) = KEYS.iter().find(|(n, _)| *n == upper) {
+        return Some(code);
+    }
+    if let Some(n) = upper.strip_prefix('F').and_then(|n| n.parse::<u16>().ok()) {
+        return (1..=12).contains(&n).then_some(140 + n);
+    }
+    if let Some(code) = name.strip_prefix('#') {
+        return code.parse().ok();
+    }
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_ascii_graphic() => Some(c as u16),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyEvent {
+    // Cycle count for the CPU emulator, or the number of VM commands for the VM emulator.
+    pub time: u64,
+    // 0 when the key is released.
+    pub key: u16,
+}
+
+// A list of key presses and releases, like
+//
+//     at 10000 press LEFT
+//     at 12000 release; at cycle 15000 press c
+//
+// Only one key can be held at a time, pressing a key releases the previous one.
+#[derive(Debug, Clone, Default)]
+pub struct KeyScript {
+    events: Vec<KeyEvent>,
+    next: usize,
+}
+
+impl KeyScript {
+    pub fn parse(src: &str) -> Result<KeyScript, String> {
+        let mut events = Vec::new();
+        for (line_no, line) in src.lines().enumerate() {
+            let line = &line[..line.find("//").unwrap_or(line.len())];
+            for event in line.split(';').map(str::trim).filter(|e| !e.is_empty()) {
+                let err = || format!("{}: invalid key event `{}`", line_no + 1, event);
+                let words: Vec<&str> = event.split_whitespace().collect();
+                let words = match words[..] {
+                    ["at", "cycle" | "step", ref rest @ ..] | ["at", ref rest @ ..] => rest,
+                    _ => return Err(err()),
+                };
+                let (time, key) = match words {
+                    [time, "press", key] => (time, key_code(key).ok_or_else(err)?),
+                    [time, "release"] => (time, 0),
+                    _ => return Err(err()),
+                };
+                let time = time.parse().map_err(|_| err())?;
+                events.push(KeyEvent { time, key });
+            }
+        }
+        // Stable, so events at the same time keep their order.
+        events.sort_by_key(|e| e.time);
+        Ok(KeyScript { events, next: 0 })
+    }
+
+    pub fn events(&self) -> &[KeyEvent] {
+        &self.events
+    }
+
+    // When the next event happens, if there's any left.
+    pub fn next_time(&self) -> Option<u64> {
+        self.events.get(self.next).map(|e| e.time)
+    }
+
+    // Applies every event due at `time` to the keyboard register.
+    pub fn apply(&mut self, time: u64, memory: &mut Memory) {
+        while let Some(event) = self.events.get(self.next).filter(|e| e.time <= time) {
+            memory.set_key(event.key);
+            self.next += 1;
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use std::path::Path;
+
+use assembler::instruction::Instruction;
+use assembler::SymbolTable;
+pub use cpu::{Cpu, StopReason, ROM_SIZE};
+pub use memory::{Memory, KBD, SCREEN};
+pub use vm::Vm;
+
+pub mod compare;
+pub mod cpu;
+pub mod debugger;
+pub mod keyboard;
+pub mod memory;
+pub mod os;
+pub mod profile;
+pub mod screen;
+pub mod tst;
+pub mod vm;
+
+// A program along with what's known about where it came from.
+pub struct Program {
+    pub words: Vec<u16>,
+    // Labels and variables for assembled programs, only the predefined symbols for `.hack` files.
+    pub symbols: SymbolTable,
+    // The source line of every instruction, empty for `.hack` files.
+    pub source: Vec<String>,
+}
+
+// Reads a program from either a `.hack` file or an `.asm` file, which gets assembled first.
+pub fn load_program(path: &Path) -> Result<Program, String> {
+    let src = std::fs::read_to_string(path)
+        .map_err(|_| format!("Couldn't open file: {}", path.display()))?;
+    let mut symbols = SymbolTable::new();
+    if path.extension().is_some_and(|e| e == "asm") {
+        let program = assembler::parse_source(&src, Some(path))
+            .map_err(|e| e.with_file(&path.display().to_string()).render())?;
+        let assembly = assembler::assemble_program(&program, &mut symbols, false)
+            .map_err(|e| e.with_file(&path.display().to_string()).render())?;
+        let source = program
+            .iter()
+            .filter(|s| !matches!(s.instruction, Instruction::Label(_)))
+            .map(|s| format!("{:>5}: {}", s.line, s.source.trim()))
+            .collect();
+        Ok(Program {
+            words: assembly.words,
+            symbols,
+            source,
+        })
+    } else {
+        let words = assembler::disasm::parse_hack(&src).map_err(|line| {
+            format!(
+                "{}:{}: expected a 16-digit binary word",
+                path.display(),
+                line
+            )
+        })?;
+        Ok(Program {
+            words,
+            symbols,
+            source: Vec::new(),
+        })
+    }
+}
+
+pub fn load_rom(path: &Path) -> Result<Vec<u16>, String> {
+    load_program(path).map(|p| p.words)
+}
@@ -0,0 +1,396 @@
+use std::path::Path;
+use std::process::ExitCode;
+
+use emulator::compare;
+use emulator::keyboard::KeyScript;
+use emulator::profile::Profiler;
+use emulator::screen::{self, Snapshots};
+use emulator::{Cpu, StopReason, Vm};
+
+const USAGE: &str = r#"Usage:
+    emulator <file.asm|file.hack> [options]
+    emulator <file.vm|dir>... [options]
+    emulator <script.tst>
+
+.vm files, and the .vm files in the given directories, are run as a single
+program by the VM emulator, starting at Sys.init with the stack at 256 like
+the translator's bootstrap code. Cycles are VM commands for those.
+
+Test scripts load .asm/.hack programs into the CPU emulator and .vm files
+into the VM emulator, and compare their output with the script's .cmp file.
+
+Options:
+  -c <n>,    --cycles <n>               Stops after <n> instructions (default 1000000)
+  -s <addr>=<val>, --set <addr>=<val>   Sets RAM[addr] before running, can be repeated
+  -p <from>..<to>, --print <from>..<to> Prints RAM[from..to] after running (default 0..16)
+  -k <file>, --keys <file>              Presses keys as given by a script of `at <cycle> press <key>`
+                                        and `at <cycle> release` lines
+  --screen <path>                       Saves the screen to <path> (.png or .pbm) when the program stops,
+                                        or at the times given below, with `{}` replaced by the cycle count
+  --screenshot <n>                      Saves the screen after <n> cycles, can be repeated
+  --every <n>                           Saves the screen every <n> frames
+  --frame <n>                           Cycles per frame (default 100000)
+  --native-os                           Runs the Jack OS functions of .vm programs in Rust rather than
+                                        as VM code, even if their .vm files are given. Without Sys.init,
+                                        Main.main is called instead. Native OS calls take a single cycle,
+                                        and Sys.wait 1000 per millisecond, so cycle counts, key scripts
+                                        and screenshots don't line up with the VM code of the OS
+  --compare-os                          Runs a .vm program with the OS given as VM code and with the
+                                        native OS side by side, in step by the program's own commands,
+                                        and reports the first OS call after which the screens, or the
+                                        returned values other than heap addresses, differ, or the VM
+                                        OS allocates memory still in use. Screen differences after
+                                        Output.moveCursor are skipped
+  --profile <file>                      Writes a flat and a call-tree profile of cycles per function
+  --coverage <file>                     Writes every instruction with the number of times it ran
+  -h,        --help                     Prints help message
+"#;
+
+fn parse_range(s: &str) -> Option<(u16, u16)> {
+    let (from, to) = s.split_once("..")?;
+    Some((from.parse().ok()?, to.parse().ok()?))
+}
+
+fn main() -> ExitCode {
+    let mut args = std::env::args();
+    args.next();
+    let mut inputs = Vec::new();
+    let mut max_cycles: u64 = 1_000_000;
+    let mut sets = Vec::new();
+    let mut print = (0, 16);
+    let mut snapshots = Snapshots {
+        frame_cycles: 100_000,
+        ..Default::default()
+    };
+    let mut screen_path: Option<String> = None;
+    let mut keys_path = None;
+    let mut profile_path = None;
+    let mut coverage_path = None;
+    let mut native_os = false;
+    let mut compare_os = false;
+    while let Some(arg) = args.next() {
+        let parsed = match arg.as_str() {
+            "-c" | "--cycles" => args
+                .next()
+                .and_then(|n| n.parse().ok())
+                .map(|n| max_cycles = n),
+            "-s" | "--set" => args
+                .next()
+                .and_then(|s| {
+                    let (addr, val) = s.split_once('=')?;
+                    Some((addr.parse::<u16>().ok()?, val.parse::<i16>().ok()? as u16))
+                })
+                .map(|set| sets.push(set)),
+            "-p" | "--print" => args.next().and_then(|r| parse_range(&r)).map(|r| print = r),
+            "-k" | "--keys" => args.next().map(|p| keys_path = Some(p)),
+            "--profile" => args.next().map(|p| profile_path = Some(p)),
+            "--coverage" => args.next().map(|p| coverage_path = Some(p)),
+            "--native-os" => {
+                native_os = true;
+                Some(())
+            }
+            "--compare-os" => {
+                compare_os = true;
+                Some(())
+            }
+            "--screen" => args.next().map(|p| screen_path = Some(p)),
+            "--screenshot" => args
+                .next()
+                .and_then(|n| n.parse().ok())
+                .map(|n| snapshots.cycles.push(n)),
+            "--every" => args
+                .next()
+                .and_then(|n| n.parse().ok())
+                .filter(|&n| n > 0)
+                .map(|n| snapshots.every = Some(n)),
+            "--frame" => args
+                .next()
+                .and_then(|n| n.parse().ok())
+                .filter(|&n| n > 0)
+                .map(|n| snapshots.frame_cycles = n),
+            "-h" | "--help" => {
+                print!("{}", USAGE);
+                return ExitCode::SUCCESS;
+            }
+            _ => {
+                inputs.push(arg.clone());
+                Some(())
+            }
+        };
+        if parsed.is_none() {
+            eprintln!("Invalid value for {}", arg);
+            return ExitCode::FAILURE;
+        }
+    }
+
+    let is_vm = |input: &String| input.ends_with(".vm") || Path::new(input).is_dir();
+    let vm = !inputs.is_empty() && inputs.iter().all(is_vm);
+    let input_file = match inputs.as_slice() {
+        [input] => input.clone(),
+        [_, _, ..] if vm => String::new(),
+        _ => {
+            print!("{}", USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+    if input_file.ends_with(".tst") {
+        return match emulator::tst::run_script(Path::new(&input_file)) {
+            Ok(None) => {
+                println!("End of script - Comparison ended successfully");
+                ExitCode::SUCCESS
+            }
+            Ok(Some(mismatch)) => {
+                eprintln!("Comparison failure at line {}", mismatch.line);
+                eprintln!("expected: {}", mismatch.expected);
+                eprintln!("  actual: {}", mismatch.actual);
+                ExitCode::FAILURE
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                ExitCode::FAILURE
+            }
+        };
+    }
+
+    let mut keys = KeyScript::default();
+    if let Some(path) = &keys_path {
+        let parsed = std::fs::read_to_string(path)
+            .map_err(|_| format!("Couldn't open file: {}", path))
+            .and_then(|src| KeyScript::parse(&src).map_err(|e| format!("{}:{}", path, e)));
+        match parsed {
+            Ok(script) => keys = script,
+            Err(e) => {
+                eprintln!("{}", e);
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+
+    if vm {
+        if profile_path.is_some() || coverage_path.is_some() {
+            eprintln!("--profile and --coverage only work for .asm and .hack programs");
+            return ExitCode::FAILURE;
+        }
+        let paths: Vec<&Path> = inputs.iter().map(Path::new).collect();
+        if compare_os {
+            if keys_path.is_some() {
+                eprintln!(
+                    "--keys doesn't work with --compare-os, as the cycles differ between the two"
+                );
+                return ExitCode::FAILURE;
+            }
+            return run_comparison(&paths, max_cycles, &sets);
+        }
+        let loaded = Vm::load_all(&paths).and_then(|mut vm| {
+            if native_os {
+                vm.use_native_os();
+            }
+            vm.bootstrap().map(|_| vm)
+        });
+        let mut vm = match loaded {
+            Ok(vm) => vm,
+            Err(e) => {
+                eprintln!("{}", e);
+                return ExitCode::FAILURE;
+            }
+        };
+        return run_vm(
+            &mut vm,
+            max_cycles,
+            &sets,
+            print,
+            keys,
+            snapshots,
+            screen_path,
+        );
+    }
+
+    let program = match emulator::load_program(Path::new(&input_file)) {
+        Ok(program) => program,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let save_at_end = screen_path.is_some();
+    let mut cpu = Cpu::new(&program.words);
+    let mut profiler =
+        (profile_path.is_some() || coverage_path.is_some()).then(|| Profiler::new(&program));
+    for (addr, val) in sets {
+        cpu.memory.write(addr, val);
+    }
+    // Without any screenshot times, the screen is saved once the program stops.
+    let timed = !snapshots.cycles.is_empty() || snapshots.every.is_some();
+    snapshots.pattern = screen_path.unwrap_or_else(|| "screen-{}.png".to_owned());
+    let mut from = 0;
+    let reason = loop {
+        keys.apply(cpu.cycles, &mut cpu.memory);
+        let shot = snapshots.next(from).filter(|_| timed);
+        let key = keys.next_time();
+        let reason = cpu.run_until(max_cycles.saturating_sub(cpu.cycles), |cpu| {
+            // `run_until` checks the condition before noticing the program has halted.
+            if let Some(profiler) = profiler.as_mut().filter(|_| !cpu.is_halted()) {
+                profiler.record(cpu);
+            }
+            Some(cpu.cycles) == shot || Some(cpu.cycles) == key
+        });
+        if reason != StopReason::Condition {
+            break reason;
+        }
+        if Some(cpu.cycles) == shot {
+            keys.apply(cpu.cycles, &mut cpu.memory);
+            if let Err(e) = screen::save_screen(&cpu.memory, Path::new(&snapshots.path(cpu.cycles)))
+            {
+                eprintln!("{}", e);
+                return ExitCode::FAILURE;
+            }
+            from = cpu.cycles + 1;
+        }
+    };
+    if !timed && save_at_end {
+        if let Err(e) = screen::save_screen(&cpu.memory, Path::new(&snapshots.path(cpu.cycles))) {
+            eprintln!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    }
+
+    if let Some(profiler) = profiler {
+        for (path, report) in [
+            (profile_path, Profiler::report as fn(&Profiler) -> String),
+            (coverage_path, Profiler::coverage),
+        ] {
+            let Some(path) = path else { continue };
+            if std::fs::write(&path, report(&profiler)).is_err() {
+                eprintln!("Couldn't write file: {}", path);
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+
+    println!(
+        "{} after {} cycles: A={} D={} PC={}",
+        match reason {
+            StopReason::Halted => "Halted",
+            _ => "Stopped",
+        },
+        cpu.cycles,
+        cpu.a as i16,
+        cpu.d as i16,
+        cpu.pc
+    );
+    for addr in print.0..print.1 {
+        println!("RAM[{}] = {}", addr, cpu.memory.read(addr) as i16);
+    }
+    ExitCode::SUCCESS
+}
+
+// Runs a program with both OSes, see `compare::compare_os`.
+fn run_comparison(paths: &[&Path], max_steps: u64, sets: &[(u16, u16)]) -> ExitCode {
+    let loaded = Vm::load_all(paths).and_then(|mut vm_os| {
+        let mut native_os = vm_os.clone();
+        native_os.use_native_os();
+        vm_os.bootstrap()?;
+        native_os.bootstrap()?;
+        for &(addr, val) in sets {
+            vm_os.memory.write(addr, val);
+            native_os.memory.write(addr, val);
+        }
+        compare::compare_os(&mut vm_os, &mut native_os, max_steps)
+    });
+    let skipped = |n: u64| match n {
+        0 => String::new(),
+        n => format!(" (skipping {} known screen differences)", n),
+    };
+    match loaded {
+        Ok(comparison) => match comparison.difference {
+            Some(difference) => {
+                eprintln!(
+                    "Differs after {} cycles of the program{}: {}",
+                    comparison.steps,
+                    skipped(comparison.skipped),
+                    difference
+                );
+                ExitCode::FAILURE
+            }
+            None => {
+                println!(
+                    "No difference in {} cycles of the program{}{}",
+                    comparison.steps,
+                    if comparison.halted {
+                        ", which halted"
+                    } else {
+                        ""
+                    },
+                    skipped(comparison.skipped)
+                );
+                ExitCode::SUCCESS
+            }
+        },
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+// Runs VM code like `main` runs the CPU, counting VM commands as cycles.
+fn run_vm(
+    vm: &mut Vm,
+    max_steps: u64,
+    sets: &[(u16, u16)],
+    print: (u16, u16),
+    mut keys: KeyScript,
+    mut snapshots: Snapshots,
+    screen_path: Option<String>,
+) -> ExitCode {
+    for &(addr, val) in sets {
+        vm.memory.write(addr, val);
+    }
+    let save_at_end = screen_path.is_some();
+    let timed = !snapshots.cycles.is_empty() || snapshots.every.is_some();
+    snapshots.pattern = screen_path.unwrap_or_else(|| "screen-{}.png".to_owned());
+    let mut shot = snapshots.next(0).filter(|_| timed);
+    let error = loop {
+        keys.apply(vm.steps, &mut vm.memory);
+        if Some(vm.steps) == shot {
+            if let Err(e) = screen::save_screen(&vm.memory, Path::new(&snapshots.path(vm.steps))) {
+                eprintln!("{}", e);
+                return ExitCode::FAILURE;
+            }
+            shot = snapshots.next(vm.steps + 1);
+        }
+        if vm.is_halted() || vm.steps >= max_steps {
+            break None;
+        }
+        if let Err(e) = vm.step() {
+            break Some(e);
+        }
+    };
+    if !timed && save_at_end {
+        if let Err(e) = screen::save_screen(&vm.memory, Path::new(&snapshots.path(vm.steps))) {
+            eprintln!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    }
+
+    match &error {
+        Some(e) => eprintln!("error: {} in {}", e, vm.current_function()),
+        None => println!(
+            "{} after {} cycles{}",
+            if vm.is_halted() { "Halted" } else { "Stopped" },
+            vm.steps,
+            match vm.current_function() {
+                "" => String::new(),
+                function => format!(" in {}", function),
+            }
+        ),
+    }
+    for addr in print.0..print.1 {
+        println!("RAM[{}] = {}", addr, vm.memory.read(addr) as i16);
+    }
+    match error {
+        Some(_) => ExitCode::FAILURE,
+        None => ExitCode::SUCCESS,
+    }
+}
@@ -0,0 +1,62 @@
+// The data memory of the Hack computer: 16K of RAM, followed by the 8K screen
+// memory map and the keyboard register.
+pub const RAM_SIZE: usize = 0x4000;
+pub const SCREEN: u16 = 0x4000;
+pub const SCREEN_SIZE: usize = 0x2000;
+pub const KBD: u16 = 0x6000;
+
+#[derive(Clone)]
+pub struct Memory {
+    data: Box<[u16]>,
+}
+
+impl Memory {
+    pub fn new() -> Memory {
+        Memory {
+            data: vec![0; KBD as usize + 1].into_boxed_slice(),
+        }
+    }
+
+    // Addresses past the keyboard aren't connected to anything and read as 0.
+    pub fn read(&self, addr: u16) -> u16 {
+        self.data.get(addr as usize).copied().unwrap_or(0)
+    }
+
+    // The keyboard register is read-only for programs, use `set_key` instead.
+    pub fn write(&mut self, addr: u16, val: u16) {
+        if addr < KBD {
+            self.data[addr as usize] = val;
+        }
+    }
+
+    pub fn set_key(&mut self, key: u16) {
+        self.data[KBD as usize] = key;
+    }
+
+    pub fn key(&self) -> u16 {
+        self.data[KBD as usize]
+    }
+
+    pub fn ram(&self) -> &[u16] {
+        &self.data[..RAM_SIZE]
+    }
+
+    pub fn ram_mut(&mut self) -> &mut [u16] {
+        &mut self.data[..RAM_SIZE]
+    }
+
+    // 256 rows of 32 words each, the least significant bit is the leftmost pixel.
+    pub fn screen(&self) -> &[u16] {
+        &self.data[SCREEN as usize..SCREEN as usize + SCREEN_SIZE]
+    }
+
+    pub fn screen_mut(&mut self) -> &mut [u16] {
+        &mut self.data[SCREEN as usize..SCREEN as usize + SCREEN_SIZE]
+    }
+}
+
+impl Default for Memory {
+    fn default() -> Self {
+        Memory::new()
+    }
+}
@@ -0,0 +1,773 @@
+// Rust implementations of the Jack OS classes, which the VM emulator can call instead of
+// running the OS's VM code. They follow the course's API, `Sys.error` codes included, and
+// use the standard memory layout: the heap at 2048-16383, and the screen and keyboard at
+// their usual addresses.
+
+use rustc_hash::FxHashMap;
+
+use crate::memory::{Memory, SCREEN, SCREEN_SIZE};
+
+const HEAP_BASE: u16 = 2048;
+const HEAP_END: u16 = SCREEN;
+
+const NEW_LINE: u16 = 128;
+const BACKSPACE: u16 = 129;
+const DOUBLE_QUOTE: u16 = 34;
+
+const ROWS: u16 = 23;
+const COLUMNS: u16 = 64;
+
+// How many VM steps `Sys.wait` takes per millisecond. The VM code of the OS takes far more
+// steps for everything else, so step-timed key scripts and screenshots still don't line up
+// with it; `--compare-os` lines the two up by the program's own commands instead.
+pub const WAIT_STEPS_PER_MS: u32 = 1000;
+
+// What a native function does to the VM once it's called.
+pub enum Native {
+    Return(u16),
+    // Called again on the next step, with the same arguments, like for `Keyboard.readChar`
+    // until a key is pressed and released.
+    Wait,
+    Halt,
+}
+
+pub type NativeFn = fn(&mut Os, &mut Memory, &[u16]) -> Result<Native, String>;
+
+// The state the OS classes keep in static variables.
+#[derive(Debug, Clone)]
+pub struct Os {
+    // Free blocks of the heap as (address, size), sorted by address.
+    free: Vec<(u16, u16)>,
+    // The size of every allocated block.
+    allocated: FxHashMap<u16, u16>,
+    color: bool,
+    row: u16,
+    column: u16,
+    // A key which `Keyboard.readChar` saw pressed, and waits to be released.
+    pressed: Option<u16>,
+    // The characters `Keyboard.readLine` has read so far, None when it isn't running.
+    line: Option<Vec<u16>>,
+    // The steps `Sys.wait` still has to take, None when it isn't running.
+    waiting: Option<u32>,
+}
+
+impl Os {
+    pub fn new() -> Os {
+        Os {
+            free: vec![(HEAP_BASE, HEAP_END - HEAP_BASE)],
+            allocated: FxHashMap::default(),
+            color: true,
+            row: 0,
+            column: 0,
+            pressed: None,
+            line: None,
+            waiting: None,
+        }
+    }
+}
+
+impl Default for Os {
+    fn default() -> Self {
+        Os::new()
+    }
+}
+
+// Every native function, with how many arguments it takes, methods counting `this`.
+// `Sys.init` isn't one of them since it calls `Main.main`, which runs as VM code.
+pub const NATIVES: &[(&str, u16, NativeFn)] = &[
+    ("Math.init", 0, nothing),
+    ("Math.abs", 1, math_abs),
+    ("Math.multiply", 2, math_multiply),
+    ("Math.divide", 2, math_divide),
+    ("Math.min", 2, math_min),
+    ("Math.max", 2, math_max),
+    ("Math.sqrt", 1, math_sqrt),
+    ("Memory.init", 0, memory_init),
+    ("Memory.peek", 1, memory_peek),
+    ("Memory.poke", 2, memory_poke),
+    ("Memory.alloc", 1, memory_alloc),
+    ("Memory.deAlloc", 1, memory_dealloc),
+    ("Array.new", 1, array_new),
+    ("Array.dispose", 1, memory_dealloc),
+    ("String.new", 1, string_new),
+    ("String.dispose", 1, memory_dealloc),
+    ("String.length", 1, string_length),
+    ("String.charAt", 2, string_char_at),
+    ("String.setCharAt", 3, string_set_char_at),
+    ("String.appendChar", 2, string_append_char),
+    ("String.eraseLastChar", 1, string_erase_last_char),
+    ("String.intValue", 1, string_int_value),
+    ("String.setInt", 2, string_set_int),
+    ("String.newLine", 0, |_, _, _| Ok(Native::Return(NEW_LINE))),
+    ("String.backSpace", 0, |_, _, _| {
+        Ok(Native::Return(BACKSPACE))
+    }),
+    ("String.doubleQuote", 0, |_, _, _| {
+        Ok(Native::Return(DOUBLE_QUOTE))
+    }),
+    ("Output.init", 0, output_init),
+    ("Output.moveCursor", 2, output_move_cursor),
+    ("Output.printChar", 1, output_print_char),
+    ("Output.printString", 1, output_print_string),
+    ("Output.printInt", 1, output_print_int),
+    ("Output.println", 0, output_println),
+    ("Output.backSpace", 0, output_backspace),
+    ("Screen.init", 0, screen_init),
+    ("Screen.clearScreen", 0, screen_clear),
+    ("Screen.setColor", 1, screen_set_color),
+    ("Screen.drawPixel", 2, screen_draw_pixel),
+    ("Screen.drawLine", 4, screen_draw_line),
+    ("Screen.drawRectangle", 4, screen_draw_rectangle),
+    ("Screen.drawCircle", 3, screen_draw_circle),
+    ("Keyboard.init", 0, nothing),
+    ("Keyboard.keyPressed", 0, |_, memory, _| {
+        Ok(Native::Return(memory.key()))
+    }),
+    ("Keyboard.readChar", 0, keyboard_read_char),
+    ("Keyboard.readLine", 1, keyboard_read_line),
+    ("Keyboard.readInt", 1, keyboard_read_int),
+    ("Sys.halt", 0, |_, _, _| Ok(Native::Halt)),
+    ("Sys.error", 1, |_, _, args| {
+        Err(format!("Sys.error({})", args[0] as i16))
+    }),
+    ("Sys.wait", 1, sys_wait),
+];
+
+// The native implementation of a function, and how many arguments it takes.
+pub fn native(name: &str) -> Option<(u16, NativeFn)> {
+    NATIVES
+        .iter()
+        .find(|(n, _, _)| *n == name)
+        .map(|&(_, args, f)| (args, f))
+}
+
+// True for every function of the OS classes, even ones without a native implementation like
+// `Output.initMap`, but not for `Sys.init`, which is what calls the program.
+pub fn is_os_function(name: &str) -> bool {
+    let class = name.split('.').next();
+    name != "Sys.init" && NATIVES.iter().any(|(n, _, _)| n.split('.').next() == class)
+}
+
+fn error(code: u16, what: &str) -> Result<Native, String> {
+    Err(format!("Sys.error({}): {}", code, what))
+}
+
+fn nothing(_: &mut Os, _: &mut Memory, _: &[u16]) -> Result<Native, String> {
+    Ok(Native::Return(0))
+}
+
+fn math_abs(_: &mut Os, _: &mut Memory, args: &[u16]) -> Result<Native, String> {
+    Ok(Native::Return((args[0] as i16).wrapping_abs() as u16))
+}
+
+fn math_multiply(_: &mut Os, _: &mut Memory, args: &[u16]) -> Result<Native, String> {
+    Ok(Native::Return(args[0].wrapping_mul(args[1])))
+}
+
+fn math_divide(_: &mut Os, _: &mut Memory, args: &[u16]) -> Result<Native, String> {
+    if args[1] == 0 {
+        return error(3, "division by zero");
+    }
+    let quotient = (args[0] as i16).wrapping_div(args[1] as i16);
+    Ok(Native::Return(quotient as u16))
+}
+
+fn math_min(_: &mut Os, _: &mut Memory, args: &[u16]) -> Result<Native, String> {
+    Ok(Native::Return((args[0] as i16).min(args[1] as i16) as u16))
+}
+
+fn math_max(_: &mut Os, _: &mut Memory, args: &[u16]) -> Result<Native, String> {
+    Ok(Native::Return((args[0] as i16).max(args[1] as i16) as u16))
+}
+
+fn math_sqrt(_: &mut Os, _: &mut Memory, args: &[u16]) -> Result<Native, String> {
+    let x = args[0] as i16;
+    if x < 0 {
+        return error(4, "square root of a negative number");
+    }
+    Ok(Native::Return((x as f64).sqrt() as u16))
+}
+
+fn memory_init(os: &mut Os, _: &mut Memory, _: &[u16]) -> Result<Native, String> {
+    os.free = vec![(HEAP_BASE, HEAP_END - HEAP_BASE)];
+    os.allocated.clear();
+    Ok(Native::Return(0))
+}
+
+fn memory_peek(_: &mut Os, memory: &mut Memory, args: &[u16]) -> Result<Native, String> {
+    Ok(Native::Return(memory.read(args[0])))
+}
+
+fn memory_poke(_: &mut Os, memory: &mut Memory, args: &[u16]) -> Result<Native, String> {
+    memory.write(args[0], args[1]);
+    Ok(Native::Return(0))
+}
+
+impl Os {
+    // First fit, taking the block from the start of the free one.
+    fn alloc(&mut self, size: u16) -> Result<u16, String> {
+        if size as i16 <= 0 {
+            return Err(format!(
+                "Sys.error(5): allocated memory size must be positive, got {}",
+                size as i16
+            ));
+        }
+        let Some(i) = self.free.iter().position(|&(_, free)| free >= size) else {
+            return Err(format!(
+                "Sys.error(6): heap overflow allocating {} words",
+                size
+            ));
+        };
+        let (addr, free) = self.free[i];
+        if free == size {
+            self.free.remove(i);
+        } else {
+            self.free[i] = (addr + size, free - size);
+        }
+        self.allocated.insert(addr, size);
+        Ok(addr)
+    }
+
+    // Freed blocks are merged with their free neighbours. Like the course's OS, anything
+    // which isn't an allocated block is ignored.
+    fn dealloc(&mut self, addr: u16) {
+        let Some(size) = self.allocated.remove(&addr) else {
+            return;
+        };
+        let i = self.free.partition_point(|&(a, _)| a < addr);
+        self.free.insert(i, (addr, size));
+        if i + 1 < self.free.len() && addr + size == self.free[i + 1].0 {
+            self.free[i].1 += self.free.remove(i + 1).1;
+        }
+        if i > 0 && self.free[i - 1].0 + self.free[i - 1].1 == addr {
+            self.free[i - 1].1 += self.free.remove(i).1;
+        }
+    }
+}
+
+fn memory_alloc(os: &mut Os, _: &mut Memory, args: &[u16]) -> Result<Native, String> {
+    os.alloc(args[0]).map(Native::Return)
+}
+
+fn memory_dealloc(os: &mut Os, _: &mut Memory, args: &[u16]) -> Result<Native, String> {
+    os.dealloc(args[0]);
+    Ok(Native::Return(0))
+}
+
+fn array_new(os: &mut Os, _: &mut Memory, args: &[u16]) -> Result<Native, String> {
+    if args[0] as i16 <= 0 {
+        return error(2, "array size must be positive");
+    }
+    os.alloc(args[0]).map(Native::Return)
+}
+
+// Strings are a block holding the maximum length, the length, and then the characters.
+fn new_string(
+    os: &mut Os,
+    memory: &mut Memory,
+    chars: &[u16],
+    max_len: u16,
+) -> Result<u16, String> {
+    let s = os.alloc(max_len + 2)?;
+    memory.write(s, max_len);
+    memory.write(s + 1, chars.len() as u16);
+    for (i, &c) in chars.iter().enumerate() {
+        memory.write(s + 2 + i as u16, c);
+    }
+    Ok(s)
+}
+
+fn string_chars(memory: &Memory, s: u16) -> Vec<u16> {
+    let len = memory.read(s.wrapping_add(1));
+    (0..len)
+        .map(|i| memory.read(s.wrapping_add(2 + i)))
+        .collect()
+}
+
+fn string_new(os: &mut Os, memory: &mut Memory, args: &[u16]) -> Result<Native, String> {
+    if (args[0] as i16) < 0 {
+        return error(14, "maximum length must be non-negative");
+    }
+    new_string(os, memory, &[], args[0]).map(Native::Return)
+}
+
+fn string_length(_: &mut Os, memory: &mut Memory, args: &[u16]) -> Result<Native, String> {
+    Ok(Native::Return(memory.read(args[0].wrapping_add(1))))
+}
+
+fn string_char_at(_: &mut Os, memory: &mut Memory, args: &[u16]) -> Result<Native, String> {
+    let (s, j) = (args[0], args[1]);
+    if j >= memory.read(s.wrapping_add(1)) {
+        return error(15, "string index out of bounds");
+    }
+    Ok(Native::Return(memory.read(s.wrapping_add(2 + j))))
+}
+
+fn string_set_char_at(_: &mut Os, memory: &mut Memory, args: &[u16]) -> Result<Native, String> {
+    let (s, j, c) = (args[0], args[1], args[2]);
+    if j >= memory.read(s.wrapping_add(1)) {
+        return error(16, "string index out of bounds");
+    }
+    memory.write(s.wrapping_add(2 + j), c);
+    Ok(Native::Return(0))
+}
+
+fn string_append_char(_: &mut Os, memory: &mut Memory, args: &[u16]) -> Result<Native, String> {
+    let (s, c) = (args[0], args[1]);
+    let len = memory.read(s.wrapping_add(1));
+    if len >= memory.read(s) {
+        return error(17, "string is full");
+    }
+    memory.write(s.wrapping_add(2 + len), c);
+    memory.write(s.wrapping_add(1), len + 1);
+    Ok(Native::Return(s))
+}
+
+fn string_erase_last_char(_: &mut Os, memory: &mut Memory, args: &[u16]) -> Result<Native, String> {
+    let len = memory.read(args[0].wrapping_add(1));
+    if len == 0 {
+        return error(18, "string is empty");
+    }
+    memory.write(args[0].wrapping_add(1), len - 1);
+    Ok(Native::Return(0))
+}
+
+// The value of the leading digits, after an optional minus sign.
+fn int_value(chars: &[u16]) -> u16 {
+    let (negative, digits) = match chars.first() {
+        Some(&c) if c == b'-' as u16 => (true, &chars[1..]),
+        _ => (false, chars),
+    };
+    let value = digits
+        .iter()
+        .take_while(|&&c| (b'0' as u16..=b'9' as u16).contains(&c))
+        .fold(0u16, |n, &c| {
+            n.wrapping_mul(10).wrapping_add(c - b'0' as u16)
+        });
+    if negative {
+        value.wrapping_neg()
+    } else {
+        value
+    }
+}
+
+fn string_int_value(_: &mut Os, memory: &mut Memory, args: &[u16]) -> Result<Native, String> {
+    Ok(Native::Return(int_value(&string_chars(memory, args[0]))))
+}
+
+fn string_set_int(_: &mut Os, memory: &mut Memory, args: &[u16]) -> Result<Native, String> {
+    let s = args[0];
+    let digits: Vec<u16> = (args[1] as i16)
+        .to_string()
+        .bytes()
+        .map(|b| b as u16)
+        .collect();
+    if digits.len() as u16 > memory.read(s) {
+        return error(19, "string is too short for the number");
+    }
+    memory.write(s.wrapping_add(1), digits.len() as u16);
+    for (i, &c) in digits.iter().enumerate() {
+        memory.write(s.wrapping_add(2 + i as u16), c);
+    }
+    Ok(Native::Return(0))
+}
+
+impl Os {
+    // Characters are 8 pixels wide and 11 high, so each one takes half of a word on 11 rows.
+    fn draw_char(&self, memory: &mut Memory, c: u16) {
+        let glyph = match c {
+            32..=126 => &FONT[c as usize - 32],
+            _ => &BOX,
+        };
+        let first = SCREEN + self.row * 11 * 32 + self.column / 2;
+        for (i, &bits) in glyph.iter().enumerate() {
+            let addr = first + i as u16 * 32;
+            let word = memory.read(addr);
+            let word = match self.column % 2 {
+                0 => (word & 0xff00) | bits as u16,
+                _ => (word & 0x00ff) | (bits as u16) << 8,
+            };
+            memory.write(addr, word);
+        }
+    }
+
+    fn print_char(&mut self, memory: &mut Memory, c: u16) {
+        match c {
+            NEW_LINE => self.println(),
+            BACKSPACE => self.backspace(memory),
+            _ => {
+                self.draw_char(memory, c);
+                self.column += 1;
+                if self.column == COLUMNS {
+                    self.println();
+                }
+            }
+        }
+    }
+
+    // Like the course's OS, the text starts again at the top instead of scrolling.
+    fn println(&mut self) {
+        self.column = 0;
+        self.row = (self.row + 1) % ROWS;
+    }
+
+    fn backspace(&mut self, memory: &mut Memory) {
+        if self.column > 0 {
+            self.column -= 1;
+        } else if self.row > 0 {
+            self.row -= 1;
+            self.column = COLUMNS - 1;
+        }
+        self.draw_char(memory, b' ' as u16);
+    }
+
+    fn print_chars(&mut self, memory: &mut Memory, chars: &[u16]) {
+        for &c in chars {
+            self.print_char(memory, c);
+        }
+    }
+}
+
+fn output_init(os: &mut Os, _: &mut Memory, _: &[u16]) -> Result<Native, String> {
+    (os.row, os.column) = (0, 0);
+    Ok(Native::Return(0))
+}
+
+fn output_move_cursor(os: &mut Os, memory: &mut Memory, args: &[u16]) -> Result<Native, String> {
+    let (row, column) = (args[0], args[1]);
+    if row >= ROWS || column >= COLUMNS {
+        return error(20, "cursor position out of the screen");
+    }
+    (os.row, os.column) = (row, column);
+    os.draw_char(memory, b' ' as u16);
+    Ok(Native::Return(0))
+}
+
+fn output_print_char(os: &mut Os, memory: &mut Memory, args: &[u16]) -> Result<Native, String> {
+    os.print_char(memory, args[0]);
+    Ok(Native::Return(0))
+}
+
+fn output_print_string(os: &mut Os, memory: &mut Memory, args: &[u16]) -> Result<Native, String> {
+    let chars = string_chars(memory, args[0]);
+    os.print_chars(memory, &chars);
+    Ok(Native::Return(0))
+}
+
+fn output_print_int(os: &mut Os, memory: &mut Memory, args: &[u16]) -> Result<Native, String> {
+    let digits: Vec<u16> = (args[0] as i16)
+        .to_string()
+        .bytes()
+        .map(|b| b as u16)
+        .collect();
+    os.print_chars(memory, &digits);
+    Ok(Native::Return(0))
+}
+
+fn output_println(os: &mut Os, _: &mut Memory, _: &[u16]) -> Result<Native, String> {
+    os.println();
+    Ok(Native::Return(0))
+}
+
+fn output_backspace(os: &mut Os, memory: &mut Memory, _: &[u16]) -> Result<Native, String> {
+    os.backspace(memory);
+    Ok(Native::Return(0))
+}
+
+impl Os {
+    // Coordinates are checked by the callers.
+    fn draw_pixel(&self, memory: &mut Memory, x: i16, y: i16) {
+        let addr = SCREEN + y as u16 * 32 + x as u16 / 16;
+        let mask = 1 << (x as u16 % 16);
+        let word = memory.read(addr);
+        memory.write(
+            addr,
+            if self.color {
+                word | mask
+            } else {
+                word & !mask
+            },
+        );
+    }
+
+    // The course's algorithm, which steps along x while `diff` is negative and along y
+    // otherwise, so lines come out the same as with its Screen.jack.
+    fn draw_line(&self, memory: &mut Memory, x1: i16, y1: i16, x2: i16, y2: i16) {
+        if x1 == x2 || y1 == y2 {
+            for y in y1.min(y2)..=y1.max(y2) {
+                for x in x1.min(x2)..=x1.max(x2) {
+                    self.draw_pixel(memory, x, y);
+                }
+            }
+            return;
+        }
+        let (dx, dy) = ((x2 - x1).abs(), (y2 - y1).abs());
+        let (step_x, step_y) = ((x2 - x1).signum(), (y2 - y1).signum());
+        let (mut a, mut b, mut diff) = (0i16, 0i16, 0i16);
+        while a.abs() <= dx && b.abs() <= dy {
+            self.draw_pixel(memory, x1 + a, y1 + b);
+            if diff < 0 {
+                a += step_x;
+                diff += dy;
+            } else {
+                b += step_y;
+                diff -= dx;
+            }
+        }
+    }
+}
+
+fn on_screen(x: u16, y: u16) -> bool {
+    (x as i16) >= 0 && x < 512 && (y as i16) >= 0 && y < 256
+}
+
+fn screen_init(os: &mut Os, _: &mut Memory, _: &[u16]) -> Result<Native, String> {
+    os.color = true;
+    Ok(Native::Return(0))
+}
+
+fn screen_clear(_: &mut Os, memory: &mut Memory, _: &[u16]) -> Result<Native, String> {
+    for addr in SCREEN..SCREEN + SCREEN_SIZE as u16 {
+        memory.write(addr, 0);
+    }
+    Ok(Native::Return(0))
+}
+
+fn screen_set_color(os: &mut Os, _: &mut Memory, args: &[u16]) -> Result<Native, String> {
+    os.color = args[0] != 0;
+    Ok(Native::Return(0))
+}
+
+fn screen_draw_pixel(os: &mut Os, memory: &mut Memory, args: &[u16]) -> Result<Native, String> {
+    if !on_screen(args[0], args[1]) {
+        return error(7, "pixel coordinates out of the screen");
+    }
+    os.draw_pixel(memory, args[0] as i16, args[1] as i16);
+    Ok(Native::Return(0))
+}
+
+fn screen_draw_line(os: &mut Os, memory: &mut Memory, args: &[u16]) -> Result<Native, String> {
+    if !on_screen(args[0], args[1]) || !on_screen(args[2], args[3]) {
+        return error(8, "line coordinates out of the screen");
+    }
+    let [x1, y1, x2, y2] = [args[0], args[1], args[2], args[3]].map(|a| a as i16);
+    os.draw_line(memory, x1, y1, x2, y2);
+    Ok(Native::Return(0))
+}
+
+fn screen_draw_rectangle(os: &mut Os, memory: &mut Memory, args: &[u16]) -> Result<Native, String> {
+    let [x1, y1, x2, y2] = [args[0], args[1], args[2], args[3]].map(|a| a as i16);
+    if !on_screen(args[0], args[1]) || !on_screen(args[2], args[3]) || x1 > x2 || y1 > y2 {
+        return error(
+            9,
+            "rectangle coordinates out of the screen or the wrong way around",
+        );
+    }
+    for y in y1..=y2 {
+        os.draw_line(memory, x1, y, x2, y);
+    }
+    Ok(Native::Return(0))
+}
+
+// Horizontal lines from the top of the circle to the bottom, like the course's OS draws it.
+fn screen_draw_circle(os: &mut Os, memory: &mut Memory, args: &[u16]) -> Result<Native, String> {
+    if !on_screen(args[0], args[1]) {
+        return error(12, "circle center out of the screen");
+    }
+    let [x, y, r] = [args[0], args[1], args[2]].map(|a| a as i16);
+    if !(0..=181).contains(&r) {
+        return error(13, "circle radius must be between 0 and 181");
+    }
+    for dy in -r..=r {
+        let dx = ((r as i32 * r as i32 - dy as i32 * dy as i32) as f64).sqrt() as i16;
+        let row = y + dy;
+        if (0..256).contains(&row) {
+            os.draw_line(memory, (x - dx).max(0), row, (x + dx).min(511), row);
+        }
+    }
+    Ok(Native::Return(0))
+}
+
+impl Os {
+    // A key once it's been pressed and released again, None until then.
+    fn typed_key(&mut self, memory: &Memory) -> Option<u16> {
+        let key = memory.key();
+        match self.pressed {
+            None => {
+                self.pressed = Some(key).filter(|&k| k != 0);
+                None
+            }
+            Some(c) if key == c => None,
+            Some(c) => {
+                self.pressed = None;
+                Some(c)
+            }
+        }
+    }
+
+    // Echoes the keys typed until Enter, and returns the line once it's pressed.
+    fn read_line(&mut self, memory: &mut Memory, message: u16) -> Option<Vec<u16>> {
+        if self.line.is_none() {
+            let chars = string_chars(memory, message);
+            self.print_chars(memory, &chars);
+            self.line = Some(Vec::new());
+        }
+        let c = self.typed_key(memory)?;
+        let line = self.line.as_mut().unwrap();
+        match c {
+            NEW_LINE => {
+                self.println();
+                return self.line.take();
+            }
+            BACKSPACE => {
+                if line.pop().is_some() {
+                    self.backspace(memory);
+                }
+            }
+            _ => {
+                line.push(c);
+                self.print_char(memory, c);
+            }
+        }
+        None
+    }
+}
+
+fn keyboard_read_char(os: &mut Os, memory: &mut Memory, _: &[u16]) -> Result<Native, String> {
+    Ok(match os.typed_key(memory) {
+        Some(c) => {
+            os.print_char(memory, c);
+            Native::Return(c)
+        }
+        None => Native::Wait,
+    })
+}
+
+fn keyboard_read_line(os: &mut Os, memory: &mut Memory, args: &[u16]) -> Result<Native, String> {
+    let Some(line) = os.read_line(memory, args[0]) else {
+        return Ok(Native::Wait);
+    };
+    new_string(os, memory, &line, line.len() as u16).map(Native::Return)
+}
+
+fn keyboard_read_int(os: &mut Os, memory: &mut Memory, args: &[u16]) -> Result<Native, String> {
+    Ok(match os.read_line(memory, args[0]) {
+        Some(line) => Native::Return(int_value(&line)),
+        None => Native::Wait,
+    })
+}
+
+fn sys_wait(os: &mut Os, _: &mut Memory, args: &[u16]) -> Result<Native, String> {
+    if (args[0] as i16) < 0 {
+        return error(1, "duration must be positive");
+    }
+    let remaining = os.waiting.get_or_insert(args[0] as u32 * WAIT_STEPS_PER_MS);
+    if *remaining == 0 {
+        os.waiting = None;
+        return Ok(Native::Return(0));
+    }
+    *remaining -= 1;
+    Ok(Native::Wait)
+}
+
+// The 11 rows of each character from ` ` to `~`, the same font as the course's Output.jack.
+// Bit 0 is the leftmost pixel.
+const FONT: [[u8; 11]; 95] = [
+    [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],           // ' '
+    [12, 30, 30, 30, 12, 12, 0, 12, 12, 0, 0],   // '!'
+    [54, 54, 20, 0, 0, 0, 0, 0, 0, 0, 0],        // '"'
+    [0, 18, 18, 63, 18, 18, 63, 18, 18, 0, 0],   // '#'
+    [12, 30, 51, 3, 30, 48, 51, 30, 12, 12, 0],  // '$'
+    [0, 0, 35, 51, 24, 12, 6, 51, 49, 0, 0],     // '%'
+    [12, 30, 30, 12, 54, 27, 27, 27, 54, 0, 0],  // '&'
+    [12, 12, 6, 0, 0, 0, 0, 0, 0, 0, 0],         // "'"
+    [24, 12, 6, 6, 6, 6, 6, 12, 24, 0, 0],       // '('
+    [6, 12, 24, 24, 24, 24, 24, 12, 6, 0, 0],    // ')'
+    [0, 0, 0, 51, 30, 63, 30, 51, 0, 0, 0],      // '*'
+    [0, 0, 0, 12, 12, 63, 12, 12, 0, 0, 0],      // '+'
+    [0, 0, 0, 0, 0, 0, 0, 12, 12, 6, 0],         // ','
+    [0, 0, 0, 0, 0, 63, 0, 0, 0, 0, 0],          // '-'
+    [0, 0, 0, 0, 0, 0, 0, 12, 12, 0, 0],         // '.'
+    [0, 0, 32, 48, 24, 12, 6, 3, 1, 0, 0],       // '/'
+    [12, 30, 51, 51, 51, 51, 51, 30, 12, 0, 0],  // '0'
+    [12, 14, 15, 12, 12, 12, 12, 12, 63, 0, 0],  // '1'
+    [30, 51, 48, 24, 12, 6, 3, 51, 63, 0, 0],    // '2'
+    [30, 51, 48, 48, 28, 48, 48, 51, 30, 0, 0],  // '3'
+    [16, 24, 28, 26, 25, 63, 24, 24, 60, 0, 0],  // '4'
+    [63, 3, 3, 31, 48, 48, 48, 51, 30, 0, 0],    // '5'
+    [28, 6, 3, 3, 31, 51, 51, 51, 30, 0, 0],     // '6'
+    [63, 49, 48, 48, 24, 12, 12, 12, 12, 0, 0],  // '7'
+    [30, 51, 51, 51, 30, 51, 51, 51, 30, 0, 0],  // '8'
+    [30, 51, 51, 51, 62, 48, 48, 24, 14, 0, 0],  // '9'
+    [0, 0, 12, 12, 0, 0, 12, 12, 0, 0, 0],       // ':'
+    [0, 0, 12, 12, 0, 0, 12, 12, 6, 0, 0],       // ';'
+    [0, 0, 24, 12, 6, 3, 6, 12, 24, 0, 0],       // '<'
+    [0, 0, 0, 63, 0, 0, 63, 0, 0, 0, 0],         // '='
+    [0, 0, 3, 6, 12, 24, 12, 6, 3, 0, 0],        // '>'
+    [30, 51, 51, 24, 12, 12, 0, 12, 12, 0, 0],   // '?'
+    [30, 51, 51, 59, 59, 59, 27, 3, 30, 0, 0],   // '@'
+    [12, 30, 51, 51, 63, 51, 51, 51, 51, 0, 0],  // 'A'
+    [31, 51, 51, 51, 31, 51, 51, 51, 31, 0, 0],  // 'B'
+    [28, 54, 35, 3, 3, 3, 35, 54, 28, 0, 0],     // 'C'
+    [15, 27, 51, 51, 51, 51, 51, 27, 15, 0, 0],  // 'D'
+    [63, 51, 35, 11, 15, 11, 35, 51, 63, 0, 0],  // 'E'
+    [63, 51, 35, 11, 15, 11, 3, 3, 3, 0, 0],     // 'F'
+    [28, 54, 35, 3, 59, 51, 51, 54, 44, 0, 0],   // 'G'
+    [51, 51, 51, 51, 63, 51, 51, 51, 51, 0, 0],  // 'H'
+    [30, 12, 12, 12, 12, 12, 12, 12, 30, 0, 0],  // 'I'
+    [60, 24, 24, 24, 24, 24, 27, 27, 14, 0, 0],  // 'J'
+    [51, 51, 51, 27, 15, 27, 51, 51, 51, 0, 0],  // 'K'
+    [3, 3, 3, 3, 3, 3, 35, 51, 63, 0, 0],        // 'L'
+    [33, 51, 63, 63, 51, 51, 51, 51, 51, 0, 0],  // 'M'
+    [51, 51, 55, 55, 63, 59, 59, 51, 51, 0, 0],  // 'N'
+    [30, 51, 51, 51, 51, 51, 51, 51, 30, 0, 0],  // 'O'
+    [31, 51, 51, 51, 31, 3, 3, 3, 3, 0, 0],      // 'P'
+    [30, 51, 51, 51, 51, 51, 63, 59, 30, 48, 0], // 'Q'
+    [31, 51, 51, 51, 31, 27, 51, 51, 51, 0, 0],  // 'R'
+    [30, 51, 51, 6, 28, 48, 51, 51, 30, 0, 0],   // 'S'
+    [63, 63, 45, 12, 12, 12, 12, 12, 30, 0, 0],  // 'T'
+    [51, 51, 51, 51, 51, 51, 51, 51, 30, 0, 0],  // 'U'
+    [51, 51, 51, 51, 51, 30, 30, 12, 12, 0, 0],  // 'V'
+    [51, 51, 51, 51, 51, 63, 63, 63, 18, 0, 0],  // 'W'
+    [51, 51, 30, 30, 12, 30, 30, 51, 51, 0, 0],  // 'X'
+    [51, 51, 51, 51, 30, 12, 12, 12, 30, 0, 0],  // 'Y'
+    [63, 51, 49, 24, 12, 6, 35, 51, 63, 0, 0],   // 'Z'
+    [30, 6, 6, 6, 6, 6, 6, 6, 30, 0, 0],         // '['
+    [0, 0, 1, 3, 6, 12, 24, 48, 32, 0, 0],       // '\\'
+    [30, 24, 24, 24, 24, 24, 24, 24, 30, 0, 0],  // ']'
+    [8, 28, 54, 0, 0, 0, 0, 0, 0, 0, 0],         // '^'
+    [0, 0, 0, 0, 0, 0, 0, 0, 0, 63, 0],          // '_'
+    [6, 12, 24, 0, 0, 0, 0, 0, 0, 0, 0],         // '`'
+    [0, 0, 0, 14, 24, 30, 27, 27, 54, 0, 0],     // 'a'
+    [3, 3, 3, 15, 27, 51, 51, 51, 30, 0, 0],     // 'b'
+    [0, 0, 0, 30, 51, 3, 3, 51, 30, 0, 0],       // 'c'
+    [48, 48, 48, 60, 54, 51, 51, 51, 30, 0, 0],  // 'd'
+    [0, 0, 0, 30, 51, 63, 3, 51, 30, 0, 0],      // 'e'
+    [28, 54, 38, 6, 15, 6, 6, 6, 15, 0, 0],      // 'f'
+    [0, 0, 30, 51, 51, 51, 62, 48, 51, 30, 0],   // 'g'
+    [3, 3, 3, 27, 55, 51, 51, 51, 51, 0, 0],     // 'h'
+    [12, 12, 0, 14, 12, 12, 12, 12, 30, 0, 0],   // 'i'
+    [48, 48, 0, 56, 48, 48, 48, 48, 51, 30, 0],  // 'j'
+    [3, 3, 3, 51, 27, 15, 15, 27, 51, 0, 0],     // 'k'
+    [14, 12, 12, 12, 12, 12, 12, 12, 30, 0, 0],  // 'l'
+    [0, 0, 0, 29, 63, 43, 43, 43, 43, 0, 0],     // 'm'
+    [0, 0, 0, 29, 51, 51, 51, 51, 51, 0, 0],     // 'n'
+    [0, 0, 0, 30, 51, 51, 51, 51, 30, 0, 0],     // 'o'
+    [0, 0, 0, 30, 51, 51, 51, 31, 3, 3, 0],      // 'p'
+    [0, 0, 0, 30, 51, 51, 51, 62, 48, 48, 0],    // 'q'
+    [0, 0, 0, 29, 55, 51, 3, 3, 7, 0, 0],        // 'r'
+    [0, 0, 0, 30, 51, 6, 24, 51, 30, 0, 0],      // 's'
+    [4, 6, 6, 15, 6, 6, 6, 54, 28, 0, 0],        // 't'
+    [0, 0, 0, 27, 27, 27, 27, 27, 54, 0, 0],     // 'u'
+    [0, 0, 0, 51, 51, 51, 51, 30, 12, 0, 0],     // 'v'
+    [0, 0, 0, 51, 51, 51, 63, 63, 18, 0, 0],     // 'w'
+    [0, 0, 0, 51, 30, 12, 12, 30, 51, 0, 0],     // 'x'
+    [0, 0, 0, 51, 51, 51, 62, 48, 24, 15, 0],    // 'y'
+    [0, 0, 0, 63, 27, 12, 6, 51, 63, 0, 0],      // 'z'
+    [56, 12, 12, 12, 7, 12, 12, 12, 56, 0, 0],   // '{'
+    [12, 12, 12, 12, 12, 12, 12, 12, 12, 0, 0],  // '|'
+    [7, 12, 12, 12, 56, 12, 12, 12, 7, 0, 0],    // '}'
+    [38, 45, 25, 0, 0, 0, 0, 0, 0, 0, 0],        // '~'
+];
+// Drawn for characters without a glyph.
+const BOX: [u8; 11] = [63, 63, 63, 63, 63, 63, 63, 63, 63, 0, 0];
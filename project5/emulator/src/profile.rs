@@ -0,0 +1,313 @@
+use std::fmt::Write;
+
+use assembler::{disasm, SymbolKind};
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use crate::cpu::{Cpu, ROM_SIZE};
+use crate::Program;
+
+const R13: u16 = 13;
+
+#[derive(Debug, Clone)]
+struct Node {
+    function: usize,
+    children: FxHashMap<usize, usize>,
+    calls: u64,
+    cycles: u64,
+}
+
+// Counts how many times every instruction runs, and which function calls which.
+//
+// Functions are found by their labels: `Foo.bar` starts a function, and everything up to
+// the next function, including `Foo.bar$LOOP` style labels, belongs to it. A call is a jump
+// from right before a `$ret.N` label, which is how the VM translator emits them, and it
+// returns once execution reaches that label again. With `-Os`, calls jump to `$$CALL`
+// instead, which jumps on to the function whose address is in R13, and the shared
+// `$$CALL`, `$$RETURN` and comparison routines count as functions of their own.
+pub struct Profiler {
+    counts: Vec<u64>,
+    words: Vec<u16>,
+    source: Vec<String>,
+    labels: FxHashMap<u16, String>,
+    // Addresses of `$ret.N` labels, which can share theirs with another label, like the
+    // bootstrap's with `$$CALL` right after it.
+    return_labels: FxHashSet<u16>,
+    function_names: Vec<String>,
+    // Index into `function_names` for every ROM address.
+    function_of: Vec<usize>,
+    // Entry address of every function.
+    entries: FxHashMap<u16, usize>,
+    // Address of the `-Os` call routine, if there's one.
+    call_routine: Option<u16>,
+    nodes: Vec<Node>,
+    // Call stack of (return address, node).
+    stack: Vec<(u16, usize)>,
+    last_pc: Option<u16>,
+    last_cycle: Option<u64>,
+}
+
+impl Profiler {
+    pub fn new(program: &Program) -> Profiler {
+        let mut labels: Vec<(u16, &str)> = program
+            .symbols
+            .entries()
+            .into_iter()
+            .filter(|&(_, _, kind)| kind == SymbolKind::Label)
+            .map(|(name, addr, _)| (addr, name))
+            .collect();
+        labels.sort();
+
+        // Translated code names functions `File.function`, while its comparison labels
+        // have no dot. Hand-written programs get a region for every label instead.
+        let translated = labels.iter().any(|(_, name)| name.contains('.'));
+        let starts_function = |name: &str| {
+            (name.starts_with("$$") && !name.contains('.'))
+                || (!name.contains('$') && (!translated || name.contains('.')))
+        };
+        let call_routine = labels
+            .iter()
+            .find(|&&(_, name)| name == "$$CALL")
+            .map(|&(addr, _)| addr);
+
+        let len = program.words.len();
+        let mut function_names = vec!["<start>".to_owned()];
+        let mut function_of = vec![0; len];
+        let mut entries = FxHashMap::default();
+        let mut next_label = labels.iter().peekable();
+        let mut current = 0;
+        for (addr, function) in function_of.iter_mut().enumerate() {
+            while let Some(&&(label_addr, name)) = next_label.peek() {
+                if label_addr as usize > addr {
+                    break;
+                }
+                if starts_function(name) && !entries.contains_key(&label_addr) {
+                    function_names.push(name.to_owned());
+                    current = function_names.len() - 1;
+                    entries.insert(label_addr, current);
+                }
+                next_label.next();
+            }
+            *function = current;
+        }
+
+        let mut label_at = FxHashMap::default();
+        for &(addr, name) in labels.iter().rev() {
+            label_at.insert(addr, name.to_owned());
+        }
+        Profiler {
+            counts: vec![0; len],
+            words: program.words.clone(),
+            source: program.source.clone(),
+            labels: label_at,
+            return_labels: labels
+                .iter()
+                .filter(|(_, name)| name.contains("$ret."))
+                .map(|&(addr, _)| addr)
+                .collect(),
+            function_names,
+            function_of,
+            entries,
+            call_routine,
+            nodes: vec![Node {
+                function: 0,
+                children: FxHashMap::default(),
+                calls: 1,
+                cycles: 0,
+            }],
+            stack: Vec::new(),
+            last_pc: None,
+            last_cycle: None,
+        }
+    }
+
+    fn is_return_label(&self, addr: u16) -> bool {
+        self.return_labels.contains(&addr)
+    }
+
+    // Records the instruction the CPU is about to execute. Calling it again for the
+    // same cycle, like a `run_until` condition might, doesn't count it twice.
+    pub fn record(&mut self, cpu: &Cpu) {
+        if self.last_cycle == Some(cpu.cycles) {
+            return;
+        }
+        self.last_cycle = Some(cpu.cycles);
+        let pc = cpu.pc;
+
+        // The bootstrap's jump to `$$CALL` is to the next address, as the routine comes
+        // right after it.
+        let jumped = |last: &u16| last.wrapping_add(1) != pc || self.call_routine == Some(pc);
+        if let Some(last) = self.last_pc.filter(jumped) {
+            let node = self.stack.last().map_or(0, |&(_, node)| node);
+            // Reaching `$$CALL` is another call, even though the bootstrap's call of
+            // `Sys.init`, which never returns, has its return address there.
+            if self.stack.last().is_some_and(|&(ret, _)| ret == pc) && self.call_routine != Some(pc)
+            {
+                self.stack.pop();
+            } else if self.is_return_label(last.wrapping_add(1)) {
+                let target = match self.call_routine {
+                    Some(call) if call == pc => cpu.memory.read(R13),
+                    _ => pc,
+                };
+                let function = self
+                    .entries
+                    .get(&target)
+                    .copied()
+                    .unwrap_or_else(|| self.function(target));
+                let child = match self.nodes[node].children.get(&function) {
+                    Some(&child) => child,
+                    None => {
+                        self.nodes.push(Node {
+                            function,
+                            children: FxHashMap::default(),
+                            calls: 0,
+                            cycles: 0,
+                        });
+                        let child = self.nodes.len() - 1;
+                        self.nodes[node].children.insert(function, child);
+                        child
+                    }
+                };
+                self.nodes[child].calls += 1;
+                self.stack.push((last.wrapping_add(1), child));
+            }
+        }
+        self.last_pc = Some(pc);
+
+        if let Some(count) = self.counts.get_mut(pc as usize) {
+            *count += 1;
+        }
+        let node = self.stack.last().map_or(0, |&(_, node)| node);
+        self.nodes[node].cycles += 1;
+    }
+
+    fn function(&self, addr: u16) -> usize {
+        self.function_of.get(addr as usize).copied().unwrap_or(0)
+    }
+
+    pub fn count(&self, addr: u16) -> u64 {
+        self.counts.get(addr as usize).copied().unwrap_or(0)
+    }
+
+    fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    // Cycles spent in each function, sorted with the most expensive first, along with
+    // how many times it was called.
+    pub fn flat(&self) -> Vec<(&str, u64, u64)> {
+        let mut cycles = vec![0; self.function_names.len()];
+        for (addr, &count) in self.counts.iter().enumerate() {
+            cycles[self.function_of[addr]] += count;
+        }
+        let mut calls = vec![0; self.function_names.len()];
+        for node in &self.nodes[1..] {
+            calls[node.function] += node.calls;
+        }
+        let mut flat: Vec<_> = self
+            .function_names
+            .iter()
+            .enumerate()
+            .filter(|&(i, _)| cycles[i] > 0)
+            .map(|(i, name)| (name.as_str(), cycles[i], calls[i]))
+            .collect();
+        flat.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        flat
+    }
+
+    fn inclusive(&self, node: usize) -> u64 {
+        let n = &self.nodes[node];
+        n.cycles
+            + n.children
+                .values()
+                .map(|&child| self.inclusive(child))
+                .sum::<u64>()
+    }
+
+    fn write_node(&self, out: &mut String, node: usize, depth: usize, total: u64) {
+        let n = &self.nodes[node];
+        let inclusive = self.inclusive(node);
+        let _ = writeln!(
+            out,
+            "{:>12} {:>6.2}% {:>10} {:>8}  {}{}",
+            inclusive,
+            percent(inclusive, total),
+            n.cycles,
+            n.calls,
+            "  ".repeat(depth),
+            self.function_names[n.function]
+        );
+        let mut children: Vec<usize> = n.children.values().copied().collect();
+        children.sort_by_key(|&child| std::cmp::Reverse(self.inclusive(child)));
+        for child in children {
+            self.write_node(out, child, depth + 1, total);
+        }
+    }
+
+    // The flat profile followed by the call tree.
+    pub fn report(&self) -> String {
+        let total = self.total();
+        let mut out = format!("Flat profile, {} cycles:\n", total);
+        let _ = writeln!(out, "{:>12} {:>7} {:>8}  FUNCTION", "CYCLES", "%", "CALLS");
+        for (name, cycles, calls) in self.flat() {
+            let _ = writeln!(
+                out,
+                "{:>12} {:>6.2}% {:>8}  {}",
+                cycles,
+                percent(cycles, total),
+                calls,
+                name
+            );
+        }
+        let _ = writeln!(out, "\nCall tree:");
+        let _ = writeln!(
+            out,
+            "{:>12} {:>7} {:>10} {:>8}  FUNCTION",
+            "TOTAL", "%", "SELF", "CALLS"
+        );
+        self.write_node(&mut out, 0, 0, total);
+        out
+    }
+
+    // Every instruction with how many times it ran, `#####` marking the ones that never did.
+    pub fn coverage(&self) -> String {
+        let executed = self.counts.iter().filter(|&&c| c > 0).count();
+        let mut out = format!(
+            "{} of {} instructions executed ({:.2}%)\n",
+            executed,
+            self.counts.len(),
+            percent(executed as u64, self.counts.len() as u64)
+        );
+        let mut function = usize::MAX;
+        for (addr, &word) in self.words.iter().enumerate().take(ROM_SIZE) {
+            if self.function_of[addr] != function {
+                function = self.function_of[addr];
+                let _ = writeln!(out, "\n{}:", self.function_names[function]);
+            }
+            if let Some(label) = self.labels.get(&(addr as u16)) {
+                let _ = writeln!(out, "{:>12}         ({})", "", label);
+            }
+            let count = match self.counts[addr] {
+                0 => "#####".to_owned(),
+                n => n.to_string(),
+            };
+            let instruction = match disasm::decode(word) {
+                Ok(instruction) => instruction.to_string(),
+                Err(_) => format!("invalid {:016b}", word),
+            };
+            let _ = write!(out, "{:>12} {:5}  {:<16}", count, addr, instruction);
+            if let Some(source) = self.source.get(addr) {
+                let _ = write!(out, " // line {}", source.trim_start());
+            }
+            out.push('\n');
+        }
+        out
+    }
+}
+
+fn percent(part: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        part as f64 * 100.0 / total as f64
+    }
+}
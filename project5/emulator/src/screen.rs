@@ -0,0 +1,150 @@
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::memory::{Memory, SCREEN_SIZE};
+
+pub const WIDTH: usize = 512;
+pub const HEIGHT: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    Pbm,
+    Png,
+}
+
+impl ImageFormat {
+    pub fn from_path(path: &Path) -> Option<ImageFormat> {
+        match path.extension()?.to_str()? {
+            "pbm" => Some(ImageFormat::Pbm),
+            "png" => Some(ImageFormat::Png),
+            _ => None,
+        }
+    }
+}
+
+// The screen as 256 rows of 64 bytes, the most significant bit being the leftmost pixel
+// and a set bit being black, which is how PBM stores it.
+fn packed_rows(memory: &Memory) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(SCREEN_SIZE * 2);
+    for &word in memory.screen() {
+        // On the Hack screen the least significant bit is the leftmost pixel.
+        bytes.push((word as u8).reverse_bits());
+        bytes.push(((word >> 8) as u8).reverse_bits());
+    }
+    bytes
+}
+
+pub fn write_pbm(memory: &Memory, mut writer: impl Write) -> io::Result<()> {
+    write!(writer, "P4\n{} {}\n", WIDTH, HEIGHT)?;
+    writer.write_all(&packed_rows(memory))
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &b in data {
+        crc ^= b as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb88320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+fn write_chunk(writer: &mut impl Write, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    writer.write_all(&(data.len() as u32).to_be_bytes())?;
+    let mut chunk = kind.to_vec();
+    chunk.extend_from_slice(data);
+    writer.write_all(&chunk)?;
+    writer.write_all(&crc32(&chunk).to_be_bytes())
+}
+
+// A 1-bit grayscale PNG. The image data is stored uncompressed, which for 16K
+// is small enough not to bother with a real deflate implementation.
+pub fn write_png(memory: &Memory, mut writer: impl Write) -> io::Result<()> {
+    // In grayscale 0 is black, so the bits are inverted, and every row starts with filter type 0.
+    let mut raw = Vec::with_capacity(HEIGHT * (WIDTH / 8 + 1));
+    for row in packed_rows(memory).chunks(WIDTH / 8) {
+        raw.push(0);
+        raw.extend(row.iter().map(|b| !b));
+    }
+
+    // A zlib stream made of stored deflate blocks.
+    let mut zlib = vec![0x78, 0x01];
+    let mut blocks = raw.chunks(0xffff).peekable();
+    while let Some(block) = blocks.next() {
+        zlib.push(blocks.peek().is_none() as u8);
+        let len = block.len() as u16;
+        zlib.extend_from_slice(&len.to_le_bytes());
+        zlib.extend_from_slice(&(!len).to_le_bytes());
+        zlib.extend_from_slice(block);
+    }
+    zlib.extend_from_slice(&adler32(&raw).to_be_bytes());
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&(WIDTH as u32).to_be_bytes());
+    header.extend_from_slice(&(HEIGHT as u32).to_be_bytes());
+    // Bit depth 1, grayscale, default compression, filtering and no interlacing.
+    header.extend_from_slice(&[1, 0, 0, 0, 0]);
+
+    writer.write_all(b"\x89PNG\r\n\x1a\n")?;
+    write_chunk(&mut writer, b"IHDR", &header)?;
+    write_chunk(&mut writer, b"IDAT", &zlib)?;
+    write_chunk(&mut writer, b"IEND", &[])
+}
+
+// Saves the screen to `path`, in the format given by its extension.
+pub fn save_screen(memory: &Memory, path: &Path) -> Result<(), String> {
+    let format = ImageFormat::from_path(path)
+        .ok_or_else(|| format!("Unknown image format: {}", path.display()))?;
+    let f = std::fs::File::create(path)
+        .map_err(|_| format!("Couldn't create file: {}", path.display()))?;
+    let writer = io::BufWriter::new(f);
+    match format {
+        ImageFormat::Pbm => write_pbm(memory, writer),
+        ImageFormat::Png => write_png(memory, writer),
+    }
+    .map_err(|e| format!("Couldn't write {}: {}", path.display(), e))
+}
+
+// When to take screenshots: at fixed cycle counts, and every `every` frames of `frame_cycles`.
+#[derive(Debug, Clone, Default)]
+pub struct Snapshots {
+    pub cycles: Vec<u64>,
+    pub every: Option<u64>,
+    pub frame_cycles: u64,
+    // Output path, where `{}` is replaced with the cycle count.
+    pub pattern: String,
+}
+
+impl Snapshots {
+    // The first cycle count at or after `from` at which a screenshot should be taken.
+    pub fn next(&self, from: u64) -> Option<u64> {
+        let fixed = self.cycles.iter().copied().filter(|&c| c >= from).min();
+        let periodic = self
+            .every
+            .map(|n| n * self.frame_cycles)
+            .filter(|&period| period > 0)
+            .map(|period| from.div_ceil(period).max(1) * period);
+        match (fixed, periodic) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+
+    pub fn path(&self, cycle: u64) -> String {
+        self.pattern.replace("{}", &cycle.to_string())
+    }
+}
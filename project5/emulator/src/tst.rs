@@ -0,0 +1,709 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::cpu::Cpu;
+use crate::vm::Vm;
+
+// Anything a test script can drive: the CPU emulator, the VM emulator and so on.
+pub trait Simulator {
+    // Reads a variable named in an output-list or a while condition, like `RAM[0]` or `PC`.
+    fn get(&self, var: &str) -> Result<i64, String>;
+    fn set(&mut self, var: &str, val: i64) -> Result<(), String>;
+    // Runs a simulation command such as `ticktock` or `vmstep`.
+    fn command(&mut self, name: &str) -> Result<(), String>;
+}
+
+// Splits `RAM[12]` into ("RAM", Some(12)).
+pub fn split_index(var: &str) -> Result<(&str, Option<u16>), String> {
+    match var.split_once('[') {
+        Some((name, idx)) => idx
+            .strip_suffix(']')
+            .and_then(|i| i.parse().ok())
+            .map(|i| (name, Some(i)))
+            .ok_or_else(|| format!("invalid variable {}", var)),
+        None => Ok((var, None)),
+    }
+}
+
+impl Simulator for Cpu {
+    fn get(&self, var: &str) -> Result<i64, String> {
+        match split_index(var)? {
+            ("A", None) => Ok(self.a as i16 as i64),
+            ("D", None) => Ok(self.d as i16 as i64),
+            ("PC", None) => Ok(self.pc as i64),
+            ("time", None) => Ok(self.cycles as i64),
+            ("RAM", Some(i)) => Ok(self.memory.read(i) as i16 as i64),
+            ("ROM", Some(i)) => Ok(self.rom[i as usize % self.rom.len()] as i16 as i64),
+            _ => Err(format!("unknown variable {}", var)),
+        }
+    }
+
+    fn set(&mut self, var: &str, val: i64) -> Result<(), String> {
+        let val = val as u16;
+        match split_index(var)? {
+            ("A", None) => self.a = val,
+            ("D", None) => self.d = val,
+            ("PC", None) => self.pc = val,
+            ("RAM", Some(i)) => self.memory.write(i, val),
+            ("ROM", Some(i)) => {
+                let len = self.rom.len();
+                self.rom[i as usize % len] = val
+            }
+            _ => return Err(format!("unknown variable {}", var)),
+        }
+        Ok(())
+    }
+
+    fn command(&mut self, name: &str) -> Result<(), String> {
+        match name {
+            // The CPU only changes state on tock, so a tick on its own does nothing.
+            "ticktock" | "ticknext" | "tock" => self.step(),
+            "tick" => {}
+            _ => return Err(format!("unknown command {}", name)),
+        }
+        Ok(())
+    }
+}
+
+impl Vm {
+    fn var_address(&self, var: &str) -> Result<u16, String> {
+        let pointer = |p: u16, i: u16| self.memory.read(p).wrapping_add(i);
+        Ok(match split_index(var)? {
+            ("sp", None) => 0,
+            ("local", None) => 1,
+            ("argument", None) => 2,
+            ("this", None) => 3,
+            ("that", None) => 4,
+            ("RAM", Some(i)) => i,
+            ("local", Some(i)) => pointer(1, i),
+            ("argument", Some(i)) => pointer(2, i),
+            ("this", Some(i)) => pointer(3, i),
+            ("that", Some(i)) => pointer(4, i),
+            ("pointer", Some(i)) => 3 + i,
+            ("temp", Some(i)) => 5 + i,
+            _ => return Err(format!("unknown variable {}", var)),
+        })
+    }
+}
+
+impl Simulator for Vm {
+    fn get(&self, var: &str) -> Result<i64, String> {
+        if var == "time" {
+            return Ok(self.steps as i64);
+        }
+        Ok(self.memory.read(self.var_address(var)?) as i16 as i64)
+    }
+
+    fn set(&mut self, var: &str, val: i64) -> Result<(), String> {
+        let addr = self.var_address(var)?;
+        self.memory.write(addr, val as u16);
+        Ok(())
+    }
+
+    fn command(&mut self, name: &str) -> Result<(), String> {
+        match name {
+            "vmstep" => self.step(),
+            _ => Err(format!("unknown command {}", name)),
+        }
+    }
+}
+
+// Creates the simulator for a `load` command. `file` is None for a bare `load`,
+// which loads every .vm file in the script's directory.
+pub fn load_simulator(dir: &Path, file: Option<&str>) -> Result<Box<dyn Simulator>, String> {
+    let Some(file) = file else {
+        return Ok(Box::new(Vm::load(dir)?));
+    };
+    let path = dir.join(file);
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("asm" | "hack") => Ok(Box::new(Cpu::new(&crate::load_rom(&path)?))),
+        _ => Ok(Box::new(Vm::load(&path)?)),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Column {
+    var: String,
+    format: char,
+    pad_left: usize,
+    len: usize,
+    pad_right: usize,
+}
+
+impl Column {
+    // Parses `RAM[0]%D2.6.2`: format, left padding, width, right padding.
+    fn parse(s: &str) -> Result<Column, String> {
+        let Some((var, format)) = s.split_once('%') else {
+            return Ok(Column {
+                var: s.to_owned(),
+                format: 'D',
+                pad_left: 1,
+                len: 6,
+                pad_right: 1,
+            });
+        };
+        let invalid = || format!("invalid output format {}", s);
+        let mut chars = format.chars();
+        let kind = chars
+            .next()
+            .filter(|c| "BDXS".contains(*c))
+            .ok_or_else(invalid)?;
+        let sizes: Vec<usize> = chars
+            .as_str()
+            .split('.')
+            .map(|n| n.parse().map_err(|_| invalid()))
+            .collect::<Result<_, _>>()?;
+        let [pad_left, len, pad_right] = sizes[..] else {
+            return Err(invalid());
+        };
+        Ok(Column {
+            var: var.to_owned(),
+            format: kind,
+            pad_left,
+            len,
+            pad_right,
+        })
+    }
+
+    fn width(&self) -> usize {
+        self.pad_left + self.len + self.pad_right
+    }
+
+    // The name centered in the column, cut if it doesn't fit.
+    fn header(&self) -> String {
+        let name: String = self.var.chars().take(self.width()).collect();
+        let left = (self.width() - name.len()) / 2;
+        let right = self.width() - left - name.len();
+        format!("{}{}{}", " ".repeat(left), name, " ".repeat(right))
+    }
+
+    fn value(&self, val: i64) -> String {
+        let val = match self.format {
+            'B' => {
+                let bits = format!("{:016b}", val as u16);
+                format!(
+                    "{:>1$}",
+                    &bits[16usize.saturating_sub(self.len)..],
+                    self.len
+                )
+            }
+            'X' => {
+                let hex = format!("{:04X}", val as u16);
+                format!("{:>1$}", &hex[4usize.saturating_sub(self.len)..], self.len)
+            }
+            'S' => format!("{:<1$}", val, self.len),
+            _ => format!("{:>1$}", val, self.len),
+        };
+        format!(
+            "{}{}{}",
+            " ".repeat(self.pad_left),
+            val,
+            " ".repeat(self.pad_right)
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Command {
+    Load(Option<String>),
+    OutputFile(String),
+    CompareTo(String),
+    OutputList(Vec<Column>),
+    Set(String, i64),
+    Output,
+    Echo(String),
+    Repeat(u64, Vec<Statement>),
+    While(Condition, Vec<Statement>),
+    Simulate(String),
+    // Things like breakpoints, which only matter for the GUI.
+    Ignored,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Condition {
+    var: String,
+    op: String,
+    val: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Statement {
+    command: Command,
+    line: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Token {
+    text: String,
+    line: usize,
+}
+
+fn tokenize(src: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut line = 1;
+    let mut rest = src;
+    while let Some(c) = rest.chars().next() {
+        let len = if c == '\n' {
+            line += 1;
+            1
+        } else if c.is_whitespace() {
+            c.len_utf8()
+        } else if rest.starts_with("//") {
+            rest.find('\n').unwrap_or(rest.len())
+        } else if rest.starts_with("/*") {
+            let len = rest.find("*/").map(|i| i + 2).unwrap_or(rest.len());
+            line += rest[..len].matches('\n').count();
+            len
+        } else if c == '"' {
+            let len = rest[1..].find('"').map(|i| i + 2).unwrap_or(rest.len());
+            tokens.push(Token {
+                text: rest[..len].to_owned(),
+                line,
+            });
+            len
+        } else if ",;{}".contains(c) {
+            tokens.push(Token {
+                text: c.to_string(),
+                line,
+            });
+            1
+        } else {
+            let len = rest
+                .find(|c: char| c.is_whitespace() || ",;{}".contains(c))
+                .unwrap_or(rest.len());
+            tokens.push(Token {
+                text: rest[..len].to_owned(),
+                line,
+            });
+            len
+        };
+        rest = &rest[len..];
+    }
+    tokens
+}
+
+// Values in scripts are decimal, or written as %B0101, %X1F or %D-3.
+fn parse_value(s: &str) -> Option<i64> {
+    if let Some(b) = s.strip_prefix("%B") {
+        i64::from_str_radix(b, 2)
+            .ok()
+            .map(|v| v as u16 as i16 as i64)
+    } else if let Some(x) = s.strip_prefix("%X") {
+        i64::from_str_radix(x, 16)
+            .ok()
+            .map(|v| v as u16 as i16 as i64)
+    } else {
+        s.strip_prefix("%D").unwrap_or(s).parse().ok()
+    }
+}
+
+struct ScriptParser {
+    tokens: Vec<Token>,
+    idx: usize,
+}
+
+impl ScriptParser {
+    fn next(&mut self) -> Option<&Token> {
+        let t = self.tokens.get(self.idx);
+        self.idx += 1;
+        t
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.idx).map(|t| t.text.as_str())
+    }
+
+    fn expect(&mut self, text: &str, line: usize) -> Result<(), String> {
+        match self.next() {
+            Some(t) if t.text == text => Ok(()),
+            Some(t) => Err(format!(
+                "line {}: expected `{}`, found `{}`",
+                t.line, text, t.text
+            )),
+            None => Err(format!("line {}: expected `{}`", line, text)),
+        }
+    }
+
+    // Statements up to the closing `}` of a block, or the end of the script.
+    fn block(&mut self, nested: bool) -> Result<Vec<Statement>, String> {
+        let mut statements = Vec::new();
+        loop {
+            match self.peek() {
+                None if nested => return Err("unexpected end of script, expected `}`".to_owned()),
+                None => return Ok(statements),
+                Some("}") if nested => {
+                    self.idx += 1;
+                    return Ok(statements);
+                }
+                Some("," | ";") => self.idx += 1,
+                Some(_) => statements.push(self.statement()?),
+            }
+        }
+    }
+
+    // Words until the `,` or `;` ending the command.
+    fn args(&mut self) -> Vec<String> {
+        let mut args = Vec::new();
+        while let Some(t) = self.peek() {
+            if matches!(t, "," | ";" | "{" | "}") {
+                break;
+            }
+            args.push(t.to_owned());
+            self.idx += 1;
+        }
+        args
+    }
+
+    fn statement(&mut self) -> Result<Statement, String> {
+        let token = self.next().unwrap().clone();
+        let line = token.line;
+        let err = |msg: &str| format!("line {}: {}", line, msg);
+        let command = match token.text.as_str() {
+            "repeat" => {
+                let count = match self.peek() {
+                    Some("{") => return Err(err("repeat needs a count")),
+                    _ => {
+                        let args = self.args();
+                        args.first()
+                            .and_then(|n| n.parse().ok())
+                            .ok_or_else(|| err("invalid repeat count"))?
+                    }
+                };
+                self.expect("{", line)?;
+                Command::Repeat(count, self.block(true)?)
+            }
+            "while" => {
+                let args = self.args();
+                let [var, op, val] = &args[..] else {
+                    return Err(err("expected `while <var> <op> <value>`"));
+                };
+                if !["=", "<>", "<", ">", "<=", ">="].contains(&op.as_str()) {
+                    return Err(err("invalid comparison"));
+                }
+                let val = parse_value(val).ok_or_else(|| err("invalid value"))?;
+                self.expect("{", line)?;
+                Command::While(
+                    Condition {
+                        var: var.clone(),
+                        op: op.clone(),
+                        val,
+                    },
+                    self.block(true)?,
+                )
+            }
+            word => {
+                let args = self.args();
+                match (word, &args[..]) {
+                    ("load", []) => Command::Load(None),
+                    ("load", [file]) => Command::Load(Some(file.clone())),
+                    ("output-file", [file]) => Command::OutputFile(file.clone()),
+                    ("compare-to", [file]) => Command::CompareTo(file.clone()),
+                    ("output-list", columns) => Command::OutputList(
+                        columns
+                            .iter()
+                            .map(|c| Column::parse(c).map_err(|e| err(&e)))
+                            .collect::<Result<_, _>>()?,
+                    ),
+                    ("set", [var, val]) => Command::Set(
+                        var.clone(),
+                        parse_value(val).ok_or_else(|| err("invalid value"))?,
+                    ),
+                    ("output", []) => Command::Output,
+                    ("echo", text) => Command::Echo(text.join(" ").trim_matches('"').to_owned()),
+                    ("clear-echo" | "breakpoint" | "clear-breakpoints", _) => Command::Ignored,
+                    (_, []) => Command::Simulate(word.to_owned()),
+                    _ => return Err(err(&format!("unexpected arguments to `{}`", word))),
+                }
+            }
+        };
+        Ok(Statement { command, line })
+    }
+}
+
+// The first line of output which differs from the compare file. Line numbers are 1-based.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    pub line: usize,
+    pub expected: String,
+    pub actual: String,
+}
+
+// `*` in the compare file matches any character.
+fn lines_match(expected: &str, actual: &str) -> bool {
+    expected.len() == actual.len()
+        && expected
+            .chars()
+            .zip(actual.chars())
+            .all(|(e, a)| e == '*' || e == a)
+}
+
+type Loader<'a> = dyn FnMut(&Path, Option<&str>) -> Result<Box<dyn Simulator>, String> + 'a;
+
+pub struct Runner<'a> {
+    dir: PathBuf,
+    loader: Box<Loader<'a>>,
+    simulator: Option<Box<dyn Simulator>>,
+    columns: Vec<Column>,
+    output: Option<io::BufWriter<fs::File>>,
+    compare: Option<Vec<String>>,
+    lines_written: usize,
+    pub mismatch: Option<Mismatch>,
+}
+
+impl<'a> Runner<'a> {
+    // `loader` turns the argument of a `load` command into a simulator.
+    pub fn new(
+        dir: &Path,
+        loader: impl FnMut(&Path, Option<&str>) -> Result<Box<dyn Simulator>, String> + 'a,
+    ) -> Runner<'a> {
+        Runner {
+            dir: dir.to_path_buf(),
+            loader: Box::new(loader),
+            simulator: None,
+            columns: Vec::new(),
+            output: None,
+            compare: None,
+            lines_written: 0,
+            mismatch: None,
+        }
+    }
+
+    // Runs the script, stopping at the first line of output which doesn't match the compare file.
+    pub fn run(&mut self, src: &str) -> Result<(), String> {
+        let mut parser = ScriptParser {
+            tokens: tokenize(src),
+            idx: 0,
+        };
+        let statements = parser.block(false)?;
+        self.run_block(&statements)?;
+        if let Some(output) = self.output.as_mut() {
+            output.flush().map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+
+    fn simulator(&mut self) -> Result<&mut Box<dyn Simulator>, String> {
+        self.simulator
+            .as_mut()
+            .ok_or_else(|| "nothing was loaded".to_owned())
+    }
+
+    fn run_block(&mut self, statements: &[Statement]) -> Result<(), String> {
+        for s in statements {
+            if self.mismatch.is_some() {
+                return Ok(());
+            }
+            self.execute(&s.command)
+                .map_err(|e| format!("line {}: {}", s.line, e))?;
+        }
+        Ok(())
+    }
+
+    fn execute(&mut self, command: &Command) -> Result<(), String> {
+        match command {
+            Command::Load(file) => {
+                self.simulator = Some((self.loader)(&self.dir, file.as_deref())?);
+            }
+            Command::OutputFile(file) => {
+                let path = self.dir.join(file);
+                let f = fs::File::create(&path)
+                    .map_err(|_| format!("Couldn't create file: {}", path.display()))?;
+                self.output = Some(io::BufWriter::new(f));
+            }
+            Command::CompareTo(file) => {
+                let path = self.dir.join(file);
+                let src = fs::read_to_string(&path)
+                    .map_err(|_| format!("Couldn't open file: {}", path.display()))?;
+                self.compare = Some(src.lines().map(|l| l.trim_end().to_owned()).collect());
+            }
+            Command::OutputList(columns) => {
+                self.columns = columns.clone();
+                let header = format!(
+                    "|{}|",
+                    columns
+                        .iter()
+                        .map(Column::header)
+                        .collect::<Vec<_>>()
+                        .join("|")
+                );
+                self.write_line(header)?;
+            }
+            Command::Set(var, val) => self.simulator()?.set(var, *val)?,
+            Command::Output => {
+                let simulator = self
+                    .simulator
+                    .as_ref()
+                    .ok_or_else(|| "nothing was loaded".to_owned())?;
+                let values = self
+                    .columns
+                    .iter()
+                    .map(|c| simulator.get(&c.var).map(|v| c.value(v)))
+                    .collect::<Result<Vec<_>, _>>()?;
+                self.write_line(format!("|{}|", values.join("|")))?;
+            }
+            Command::Echo(text) => println!("{}", text),
+            Command::Repeat(count, body) => {
+                for _ in 0..*count {
+                    if self.mismatch.is_some() {
+                        break;
+                    }
+                    self.run_block(body)?;
+                }
+            }
+            Command::While(cond, body) => {
+                while self.mismatch.is_none() && self.holds(cond)? {
+                    self.run_block(body)?;
+                }
+            }
+            Command::Simulate(name) => self.simulator()?.command(name)?,
+            Command::Ignored => {}
+        }
+        Ok(())
+    }
+
+    fn holds(&mut self, cond: &Condition) -> Result<bool, String> {
+        let val = self.simulator()?.get(&cond.var)?;
+        Ok(match cond.op.as_str() {
+            "=" => val == cond.val,
+            "<>" => val != cond.val,
+            "<" => val < cond.val,
+            ">" => val > cond.val,
+            "<=" => val <= cond.val,
+            _ => val >= cond.val,
+        })
+    }
+
+    fn write_line(&mut self, line: String) -> Result<(), String> {
+        if let Some(output) = self.output.as_mut() {
+            writeln!(output, "{}", line).map_err(|e| e.to_string())?;
+        }
+        self.lines_written += 1;
+        let Some(compare) = self.compare.as_ref() else {
+            return Ok(());
+        };
+        // Output past the end of the compare file is a mismatch too.
+        let expected = compare.get(self.lines_written - 1);
+        if !expected.is_some_and(|expected| lines_match(expected, &line)) {
+            self.mismatch = Some(Mismatch {
+                line: self.lines_written,
+                expected: expected.map_or("<end of compare file>".to_owned(), String::clone),
+                actual: line,
+            });
+        }
+        Ok(())
+    }
+
+    // The first line of the compare file the script never output, if any. Blank lines at
+    // the end don't count.
+    fn missing_line(&self) -> Option<Mismatch> {
+        let compare = self.compare.as_ref()?;
+        let (i, expected) = compare
+            .iter()
+            .enumerate()
+            .skip(self.lines_written)
+            .find(|(_, line)| !line.is_empty())?;
+        Some(Mismatch {
+            line: i + 1,
+            expected: expected.clone(),
+            actual: "<end of output>".to_owned(),
+        })
+    }
+}
+
+// Runs a .tst script with the CPU and VM emulators, returning the first mismatch, if any.
+pub fn run_script(path: &Path) -> Result<Option<Mismatch>, String> {
+    let src =
+        fs::read_to_string(path).map_err(|_| format!("Couldn't open file: {}", path.display()))?;
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let mut runner = Runner::new(dir, load_simulator);
+    runner.run(&src)?;
+    Ok(runner.mismatch.take().or_else(|| runner.missing_line()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MULT_TST: &str = "\
+load Mult.asm,
+output-file Mult.out,
+compare-to Mult.cmp,
+output-list RAM[0]%D2.6.2 RAM[1]%D2.6.2 RAM[2]%D2.6.2;
+
+set RAM[0] 0, set RAM[1] 0, set RAM[2] -1;
+repeat 20 { ticktock; }
+output;
+
+set PC 0, set RAM[0] 6, set RAM[1] 7, set RAM[2] -1;
+repeat 300 { ticktock; }
+output;
+";
+
+    const MULT_CMP: &str = "\
+|  RAM[0]  |  RAM[1]  |  RAM[2]  |
+|       0  |       0  |       0  |
+|       6  |       7  |      42  |
+";
+
+    // Runs MULT_TST on project 4's Mult.asm against `cmp`, in a directory of its own.
+    fn run_mult(name: &str, cmp: &str) -> Option<Mismatch> {
+        let dir = std::env::temp_dir().join(format!("tst-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let asm = concat!(env!("CARGO_MANIFEST_DIR"), "/../../project4/Mult.asm");
+        fs::copy(asm, dir.join("Mult.asm")).unwrap();
+        fs::write(dir.join("Mult.tst"), MULT_TST).unwrap();
+        fs::write(dir.join("Mult.cmp"), cmp).unwrap();
+        let mismatch = run_script(&dir.join("Mult.tst")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        mismatch
+    }
+
+    #[test]
+    fn mult_matches_its_compare_file() {
+        assert_eq!(run_mult("pass", MULT_CMP), None);
+    }
+
+    #[test]
+    fn reports_the_first_mismatch() {
+        let cmp = MULT_CMP.replace("42", "43");
+        assert_eq!(
+            run_mult("mismatch", &cmp),
+            Some(Mismatch {
+                line: 3,
+                expected: "|       6  |       7  |      43  |".to_owned(),
+                actual: "|       6  |       7  |      42  |".to_owned(),
+            })
+        );
+    }
+
+    #[test]
+    fn reports_compare_lines_never_output() {
+        let cmp = format!("{}|       1  |       1  |       1  |\n", MULT_CMP);
+        assert_eq!(
+            run_mult("missing", &cmp),
+            Some(Mismatch {
+                line: 4,
+                expected: "|       1  |       1  |       1  |".to_owned(),
+                actual: "<end of output>".to_owned(),
+            })
+        );
+    }
+
+    #[test]
+    fn reports_output_past_the_compare_file() {
+        let cmp: String = MULT_CMP
+            .lines()
+            .take(2)
+            .map(|l| format!("{}\n", l))
+            .collect();
+        assert_eq!(
+            run_mult("extra", &cmp),
+            Some(Mismatch {
+                line: 3,
+                expected: "<end of compare file>".to_owned(),
+                actual: "|       6  |       7  |      42  |".to_owned(),
+            })
+        );
+    }
+}
@@ -0,0 +1,396 @@
+use std::path::Path;
+
+use rustc_hash::FxHashMap;
+use vm_translator::{parser, Segment, VMInstruction};
+
+use crate::memory::{Memory, SCREEN};
+use crate::os::{self, Native, NativeFn, Os};
+
+const SP: u16 = 0;
+const LCL: u16 = 1;
+const ARG: u16 = 2;
+const THIS: u16 = 3;
+const THAT: u16 = 4;
+const TEMP: u16 = 5;
+const FIRST_STATIC: u16 = 16;
+
+// Marks a call whose function doesn't exist, reported only once it's executed. Missing
+// `goto`/`if-goto` labels are rejected when loading.
+const UNRESOLVED: usize = usize::MAX;
+
+// Executes VM code directly, using the same RAM layout as the translated program would,
+// so the stack, segments, screen and keyboard live at the usual addresses.
+#[derive(Clone)]
+pub struct Vm {
+    pub memory: Memory,
+    pub pc: usize,
+    pub steps: u64,
+    program: Vec<VMInstruction>,
+    // Jump target of every goto, if-goto and call, resolved when loading.
+    targets: Vec<usize>,
+    // RAM address of every static segment access.
+    statics: Vec<u16>,
+    // Name of the function every instruction belongs to.
+    function_names: Vec<usize>,
+    names: Vec<String>,
+    functions: FxHashMap<String, usize>,
+    // The native implementation every call runs instead of VM code, see `use_native_os`.
+    natives: Vec<Option<(u16, NativeFn)>>,
+    pub os: Os,
+    halted: bool,
+}
+
+impl Vm {
+    // Loads a list of (file name, source) pairs, where the file name is without the extension.
+    // Execution starts at Sys.init if there's one, or at the first instruction otherwise.
+    pub fn new(files: &[(String, String)]) -> Result<Vm, String> {
+        let mut program = Vec::new();
+        let mut files_of = Vec::new();
+        for (i, (name, src)) in files.iter().enumerate() {
+            let instructions = parser::parse_source(src).map_err(|errors| {
+                let file = format!("{}.vm", name);
+                errors
+                    .into_iter()
+                    .map(|e| e.with_file(&file).render())
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            })?;
+            for (_, instruction) in instructions {
+                program.push(instruction);
+                files_of.push(i);
+            }
+        }
+
+        // Return addresses are pushed as command indices, one past the last for the bootstrap's.
+        if program.len() > u16::MAX as usize {
+            return Err(format!(
+                "the program has {} commands, but return addresses only fit {}",
+                program.len(),
+                u16::MAX
+            ));
+        }
+
+        // Functions and labels first, so jumps forward can be resolved.
+        let mut functions = FxHashMap::default();
+        let mut labels = FxHashMap::default();
+        let mut names = Vec::new();
+        let mut function_names = Vec::with_capacity(program.len());
+        let mut current = String::new();
+        for (idx, instruction) in program.iter().enumerate() {
+            if idx == 0 || files_of[idx] != files_of[idx - 1] {
+                // Code outside of any function is scoped to its file, like the translator does it.
+                current = format!("{}.", files[files_of[idx]].0);
+                names.push(current.clone());
+            }
+            match instruction {
+                VMInstruction::Function(name, _) => {
+                    if functions.insert(name.clone(), idx).is_some() {
+                        return Err(format!("function {} is defined twice", name));
+                    }
+                    current = name.clone();
+                    names.push(current.clone());
+                }
+                VMInstruction::Label(label) => {
+                    labels.insert(format!("{}${}", current, label), idx);
+                }
+                _ => {}
+            }
+            function_names.push(names.len() - 1);
+        }
+
+        let mut targets = vec![UNRESOLVED; program.len()];
+        let mut statics = vec![0; program.len()];
+        let mut static_addrs = FxHashMap::default();
+        for (idx, instruction) in program.iter().enumerate() {
+            let function = &names[function_names[idx]];
+            match instruction {
+                VMInstruction::Goto(label) | VMInstruction::IfGoto(label) => {
+                    targets[idx] = *labels
+                        .get(&format!("{}${}", function, label))
+                        .ok_or_else(|| format!("label {} not found in {}", label, function))?;
+                }
+                VMInstruction::Call(name, _) => {
+                    targets[idx] = functions.get(name).copied().unwrap_or(UNRESOLVED);
+                }
+                VMInstruction::Push(Segment::Static, i)
+                | VMInstruction::Pop(Segment::Static, i) => {
+                    let next = FIRST_STATIC + static_addrs.len() as u16;
+                    statics[idx] = *static_addrs.entry((files_of[idx], *i)).or_insert(next);
+                }
+                _ => {}
+            }
+        }
+
+        let pc = functions.get("Sys.init").copied().unwrap_or(0);
+        Ok(Vm {
+            memory: Memory::new(),
+            pc,
+            steps: 0,
+            natives: vec![None; program.len()],
+            program,
+            targets,
+            statics,
+            function_names,
+            names,
+            functions,
+            os: Os::new(),
+            halted: false,
+        })
+    }
+
+    // Loads a single .vm file, or every .vm file in a directory in alphabetical order.
+    pub fn load(path: &Path) -> Result<Vm, String> {
+        Vm::load_all(&[path])
+    }
+
+    // Loads the .vm files of several paths as one program, like a game's directory along
+    // with the directory of the OS it uses.
+    pub fn load_all(paths: &[&Path]) -> Result<Vm, String> {
+        let mut files = Vec::new();
+        for path in paths {
+            let mut vm_files = Vec::new();
+            if path.is_dir() {
+                for e in path
+                    .read_dir()
+                    .map_err(|_| format!("Couldn't read directory: {}", path.display()))?
+                {
+                    let e = e.map_err(|e| e.to_string())?.path();
+                    if e.is_file() && e.extension().is_some_and(|ext| ext == "vm") {
+                        vm_files.push(e);
+                    }
+                }
+                vm_files.sort();
+            } else {
+                vm_files.push(path.to_path_buf());
+            }
+            for p in vm_files {
+                let src = std::fs::read_to_string(&p)
+                    .map_err(|_| format!("Couldn't open file: {}", p.display()))?;
+                let name = p.file_stem().unwrap().to_string_lossy().into_owned();
+                files.push((name, src));
+            }
+        }
+        Vm::new(&files)
+    }
+
+    // Makes calls to the Jack OS run its Rust implementation, even when the program has
+    // VM code for the function, so the two can be compared.
+    pub fn use_native_os(&mut self) {
+        for (idx, instruction) in self.program.iter().enumerate() {
+            if let VMInstruction::Call(name, _) = instruction {
+                self.natives[idx] = os::native(name);
+            }
+        }
+    }
+
+    // Sets the stack up and calls Sys.init like the translator's bootstrap code, for running
+    // whole programs. Returning from Sys.init ends the program. With the native OS and
+    // without VM code for Sys.init, it calls Main.main instead, as Sys.init would.
+    pub fn bootstrap(&mut self) -> Result<(), String> {
+        let native_os = self.natives.iter().any(Option::is_some);
+        let target = match self.function_address("Sys.init") {
+            Some(target) => target,
+            None if native_os => self
+                .function_address("Main.main")
+                .ok_or("there's no Sys.init or Main.main to call")?,
+            None => return Err("there's no Sys.init to call".to_owned()),
+        };
+        self.memory.write(SP, 256);
+        self.call(self.program.len(), 0);
+        self.pc = target;
+        Ok(())
+    }
+
+    pub fn instruction(&self) -> Option<&VMInstruction> {
+        self.program.get(self.pc)
+    }
+
+    // Name of the function the next instruction belongs to.
+    pub fn current_function(&self) -> &str {
+        match self.function_names.get(self.pc) {
+            Some(&i) => &self.names[i],
+            None => "",
+        }
+    }
+
+    pub fn function_address(&self, name: &str) -> Option<usize> {
+        self.functions.get(name).copied()
+    }
+
+    // True when there's nothing left to run, or the program is stuck in a `label X, goto X` loop.
+    pub fn is_halted(&self) -> bool {
+        if self.halted {
+            return true;
+        }
+        match self.program.get(self.pc) {
+            None => true,
+            Some(VMInstruction::Goto(_)) => {
+                let target = self.targets[self.pc];
+                target == self.pc || (target + 1 == self.pc)
+            }
+            _ => false,
+        }
+    }
+
+    fn push(&mut self, val: u16) {
+        let sp = self.memory.read(SP);
+        self.memory.write(sp, val);
+        self.memory.write(SP, sp.wrapping_add(1));
+    }
+
+    fn pop(&mut self) -> u16 {
+        let sp = self.memory.read(SP).wrapping_sub(1);
+        self.memory.write(SP, sp);
+        self.memory.read(sp)
+    }
+
+    // Pushes the frame of a call returning to `ret`, and points ARG and LCL at the callee's
+    // arguments and locals.
+    fn call(&mut self, ret: usize, args: u16) {
+        self.push(ret as u16);
+        for pointer in [LCL, ARG, THIS, THAT] {
+            let val = self.memory.read(pointer);
+            self.push(val);
+        }
+        let sp = self.memory.read(SP);
+        self.memory.write(ARG, sp.wrapping_sub(args + 5));
+        self.memory.write(LCL, sp);
+    }
+
+    // RAM address of the given segment entry, None for `constant`.
+    fn address(&self, segment: &Segment, idx: u16) -> Option<u16> {
+        let base = |pointer| self.memory.read(pointer).wrapping_add(idx);
+        match segment {
+            Segment::Constant => None,
+            Segment::Local => Some(base(LCL)),
+            Segment::Argument => Some(base(ARG)),
+            Segment::This => Some(base(THIS)),
+            Segment::That => Some(base(THAT)),
+            Segment::Pointer => Some(THIS + idx),
+            Segment::Temp => Some(TEMP + idx),
+            Segment::Static => Some(self.statics[self.pc]),
+        }
+    }
+
+    // Executes a single VM command.
+    pub fn step(&mut self) -> Result<(), String> {
+        let Some(instruction) = self.program.get(self.pc) else {
+            return Err("reached the end of the program".to_owned());
+        };
+        self.steps += 1;
+        let mut next = self.pc + 1;
+        match instruction {
+            VMInstruction::Push(segment, idx) => {
+                let val = match self.address(segment, *idx) {
+                    Some(addr) => self.memory.read(addr),
+                    None => *idx,
+                };
+                self.push(val);
+            }
+            VMInstruction::Pop(segment, idx) => {
+                let addr = self
+                    .address(segment, *idx)
+                    .ok_or("can't pop to the constant segment")?;
+                let val = self.pop();
+                self.memory.write(addr, val);
+            }
+            VMInstruction::Add
+            | VMInstruction::Sub
+            | VMInstruction::And
+            | VMInstruction::Or
+            | VMInstruction::Eq
+            | VMInstruction::Gt
+            | VMInstruction::Lt => {
+                let op: fn(u16, u16) -> u16 = match instruction {
+                    VMInstruction::Add => u16::wrapping_add,
+                    VMInstruction::Sub => u16::wrapping_sub,
+                    VMInstruction::And => |x, y| x & y,
+                    VMInstruction::Or => |x, y| x | y,
+                    VMInstruction::Eq => |x, y| bool_value(x == y),
+                    VMInstruction::Gt => |x, y| bool_value((x as i16) > (y as i16)),
+                    _ => |x, y| bool_value((x as i16) < (y as i16)),
+                };
+                let y = self.pop();
+                let x = self.pop();
+                self.push(op(x, y));
+            }
+            VMInstruction::Neg | VMInstruction::Not => {
+                let neg = matches!(instruction, VMInstruction::Neg);
+                let x = self.pop();
+                self.push(if neg { x.wrapping_neg() } else { !x });
+            }
+            VMInstruction::Label(_) => {}
+            VMInstruction::Goto(_) => next = self.targets[self.pc],
+            VMInstruction::IfGoto(_) => {
+                if self.pop() != 0 {
+                    next = self.targets[self.pc];
+                }
+            }
+            VMInstruction::Function(_, locals) => {
+                for _ in 0..*locals {
+                    self.push(0);
+                }
+            }
+            VMInstruction::Call(name, args) if self.natives[self.pc].is_some() => {
+                let (expected, native) = self.natives[self.pc].unwrap();
+                if *args != expected {
+                    return Err(format!(
+                        "{} takes {} arguments, called with {}",
+                        name, expected, args
+                    ));
+                }
+                let sp = self.memory.read(SP);
+                let first = sp.wrapping_sub(*args);
+                let values: Vec<u16> = (0..*args)
+                    .map(|i| self.memory.read(first.wrapping_add(i)))
+                    .collect();
+                match native(&mut self.os, &mut self.memory, &values)? {
+                    Native::Return(val) => {
+                        self.memory.write(SP, first);
+                        self.push(val);
+                    }
+                    Native::Wait => next = self.pc,
+                    Native::Halt => {
+                        self.halted = true;
+                        next = self.pc;
+                    }
+                }
+            }
+            VMInstruction::Call(name, args) => {
+                let target = self.targets[self.pc];
+                if target == UNRESOLVED {
+                    return Err(format!("call to undefined function {}", name));
+                }
+                // Past the heap the stack would run over the screen, and then wrap around to SP.
+                if self.memory.read(SP) >= SCREEN - 5 {
+                    return Err(format!("stack overflow calling {}", name));
+                }
+                self.call(next, *args);
+                next = target;
+            }
+            VMInstruction::Return => {
+                let frame = self.memory.read(LCL);
+                let ret = self.memory.read(frame.wrapping_sub(5));
+                let val = self.pop();
+                let arg = self.memory.read(ARG);
+                self.memory.write(arg, val);
+                self.memory.write(SP, arg.wrapping_add(1));
+                for (i, pointer) in [THAT, THIS, ARG, LCL].into_iter().enumerate() {
+                    let val = self.memory.read(frame.wrapping_sub(i as u16 + 1));
+                    self.memory.write(pointer, val);
+                }
+                next = ret as usize;
+            }
+        }
+        self.pc = next;
+        Ok(())
+    }
+}
+
+fn bool_value(b: bool) -> u16 {
+    if b {
+        0xffff
+    } else {
+        0
+    }
+}
@@ -0,0 +1,83 @@
+// The chip definition exactly as written, before any of the names are checked.
+
+// Byte offsets into the source, `end` being exclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            start: self.start,
+            end: other.end,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ident {
+    pub name: String,
+    pub span: Span,
+}
+
+// `a` or `a[16]` in the IN and OUT lists.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PinDecl {
+    pub name: Ident,
+    pub width: u16,
+}
+
+// `a`, `a[3]` or `a[0..7]` on either side of a connection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PinRef {
+    pub name: Ident,
+    // Inclusive bit range.
+    pub range: Option<(u16, u16)>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Wire {
+    Pin(PinRef),
+    Const(bool, Span),
+}
+
+impl Wire {
+    pub fn span(&self) -> Span {
+        match self {
+            Wire::Pin(pin) => pin.span,
+            Wire::Const(_, span) => *span,
+        }
+    }
+}
+
+// `pin=wire` inside a part: the pin belongs to the part, the wire to the chip being defined.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Connection {
+    pub pin: PinRef,
+    pub wire: Wire,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Part {
+    pub chip: Ident,
+    pub connections: Vec<Connection>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Body {
+    Parts(Vec<Part>),
+    // `BUILTIN Name;` with the pins listed after `CLOCKED`.
+    Builtin { name: Ident, clocked: Vec<Ident> },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chip {
+    pub name: Ident,
+    pub inputs: Vec<PinDecl>,
+    pub outputs: Vec<PinDecl>,
+    pub body: Body,
+}
@@ -0,0 +1,182 @@
+// The chips which the course's hardware simulator implements natively, and their pins.
+
+pub type Pins = &'static [(&'static str, u16)];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Builtin {
+    pub name: &'static str,
+    pub inputs: Pins,
+    pub outputs: Pins,
+    // Inputs which only affect the outputs after the next clock cycle.
+    pub clocked: &'static [&'static str],
+}
+
+const fn chip(name: &'static str, inputs: Pins, outputs: Pins) -> Builtin {
+    Builtin {
+        name,
+        inputs,
+        outputs,
+        clocked: &[],
+    }
+}
+
+const fn clocked(
+    name: &'static str,
+    inputs: Pins,
+    outputs: Pins,
+    clocked: &'static [&'static str],
+) -> Builtin {
+    Builtin {
+        name,
+        inputs,
+        outputs,
+        clocked,
+    }
+}
+
+const OUT: Pins = &[("out", 1)];
+const OUT16: Pins = &[("out", 16)];
+const AB: Pins = &[("a", 1), ("b", 1)];
+const AB16: Pins = &[("a", 16), ("b", 16)];
+const REGISTER: Pins = &[("in", 16), ("load", 1)];
+
+pub const BUILTINS: &[Builtin] = &[
+    chip("Nand", AB, OUT),
+    chip("Not", &[("in", 1)], OUT),
+    chip("And", AB, OUT),
+    chip("Or", AB, OUT),
+    chip("Xor", AB, OUT),
+    chip("Mux", &[("a", 1), ("b", 1), ("sel", 1)], OUT),
+    chip("DMux", &[("in", 1), ("sel", 1)], AB),
+    chip("Not16", &[("in", 16)], OUT16),
+    chip("And16", AB16, OUT16),
+    chip("Or16", AB16, OUT16),
+    chip("Mux16", &[("a", 16), ("b", 16), ("sel", 1)], OUT16),
+    chip("Or8Way", &[("in", 8)], OUT),
+    chip(
+        "Mux4Way16",
+        &[("a", 16), ("b", 16), ("c", 16), ("d", 16), ("sel", 2)],
+        OUT16,
+    ),
+    chip(
+        "Mux8Way16",
+        &[
+            ("a", 16),
+            ("b", 16),
+            ("c", 16),
+            ("d", 16),
+            ("e", 16),
+            ("f", 16),
+            ("g", 16),
+            ("h", 16),
+            ("sel", 3),
+        ],
+        OUT16,
+    ),
+    chip(
+        "DMux4Way",
+        &[("in", 1), ("sel", 2)],
+        &[("a", 1), ("b", 1), ("c", 1), ("d", 1)],
+    ),
+    chip(
+        "DMux8Way",
+        &[("in", 1), ("sel", 3)],
+        &[
+            ("a", 1),
+            ("b", 1),
+            ("c", 1),
+            ("d", 1),
+            ("e", 1),
+            ("f", 1),
+            ("g", 1),
+            ("h", 1),
+        ],
+    ),
+    chip("HalfAdder", AB, &[("sum", 1), ("carry", 1)]),
+    chip(
+        "FullAdder",
+        &[("a", 1), ("b", 1), ("c", 1)],
+        &[("sum", 1), ("carry", 1)],
+    ),
+    chip("Add16", AB16, OUT16),
+    chip("Inc16", &[("in", 16)], OUT16),
+    chip(
+        "ALU",
+        &[
+            ("x", 16),
+            ("y", 16),
+            ("zx", 1),
+            ("nx", 1),
+            ("zy", 1),
+            ("ny", 1),
+            ("f", 1),
+            ("no", 1),
+        ],
+        &[("out", 16), ("zr", 1), ("ng", 1)],
+    ),
+    clocked("DFF", &[("in", 1)], OUT, &["in"]),
+    clocked("Bit", &[("in", 1), ("load", 1)], OUT, &["in", "load"]),
+    clocked("Register", REGISTER, OUT16, &["in", "load"]),
+    clocked("ARegister", REGISTER, OUT16, &["in", "load"]),
+    clocked("DRegister", REGISTER, OUT16, &["in", "load"]),
+    clocked(
+        "PC",
+        &[("in", 16), ("load", 1), ("inc", 1), ("reset", 1)],
+        OUT16,
+        &["in", "load", "inc", "reset"],
+    ),
+    clocked(
+        "RAM8",
+        &[("in", 16), ("load", 1), ("address", 3)],
+        OUT16,
+        &["in", "load"],
+    ),
+    clocked(
+        "RAM64",
+        &[("in", 16), ("load", 1), ("address", 6)],
+        OUT16,
+        &["in", "load"],
+    ),
+    clocked(
+        "RAM512",
+        &[("in", 16), ("load", 1), ("address", 9)],
+        OUT16,
+        &["in", "load"],
+    ),
+    clocked(
+        "RAM4K",
+        &[("in", 16), ("load", 1), ("address", 12)],
+        OUT16,
+        &["in", "load"],
+    ),
+    clocked(
+        "RAM16K",
+        &[("in", 16), ("load", 1), ("address", 14)],
+        OUT16,
+        &["in", "load"],
+    ),
+    chip("ROM32K", &[("address", 15)], OUT16),
+    clocked(
+        "Screen",
+        &[("in", 16), ("load", 1), ("address", 13)],
+        OUT16,
+        &["in", "load"],
+    ),
+    chip("Keyboard", &[], OUT16),
+    clocked(
+        "Memory",
+        &[("in", 16), ("load", 1), ("address", 15)],
+        OUT16,
+        &["in", "load"],
+    ),
+    clocked(
+        "CPU",
+        &[("inM", 16), ("instruction", 16), ("reset", 1)],
+        &[("outM", 16), ("writeM", 1), ("addressM", 15), ("pc", 15)],
+        &["reset"],
+    ),
+];
+
+pub fn builtin(name: &str) -> Option<&'static Builtin> {
+    BUILTINS.iter().find(|b| b.name == name)
+}
@@ -0,0 +1,304 @@
+// Checks a chip against the built-in chip of the same name, trying every input when there
+// are few enough of them and random ones otherwise, and shrinks any counterexample found.
+
+use std::rc::Rc;
+
+use crate::builtin::{self, Builtin};
+use crate::netlist::{Bus, ChipDef};
+use crate::sim::{self, Simulator};
+
+// Chips with at most this many input bits are checked exhaustively.
+pub const EXHAUSTIVE_BITS: u32 = 20;
+
+// Values which tend to find bugs in adders and the ALU, given to random inputs now and then.
+const SPECIAL_VALUES: &[u16] = &[0, 1, 0xffff, 0x8000, 0x7fff, 0x5555, 0xaaaa, 0x00ff, 0xff00];
+
+// xorshift64*, good enough for test vectors and reproducible from the seed.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Rng {
+        Rng(seed.max(1))
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Counterexample {
+    // Pin names with their width and value, in the order the chip declares them.
+    pub inputs: Vec<(String, u16, u16)>,
+    pub expected: Vec<(String, u16, u16)>,
+    pub actual: Vec<(String, u16, u16)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    // How many inputs were tried, up to the first failing one.
+    pub vectors: u64,
+    // How many would have been tried without a failure.
+    pub total: u64,
+    pub exhaustive: bool,
+    pub counterexample: Option<Counterexample>,
+}
+
+struct Checker {
+    sim: Simulator,
+    reference: &'static Builtin,
+    // For each of the reference's inputs and outputs, the index of the chip's pin.
+    inputs: Vec<usize>,
+    outputs: Vec<usize>,
+    widths: Vec<u16>,
+}
+
+impl Checker {
+    fn new(chip: Rc<ChipDef>) -> Result<Checker, String> {
+        let reference = builtin::builtin(&chip.name)
+            .ok_or_else(|| format!("there's no reference model for chip {}", chip.name))?;
+        if sim::eval_builtin(reference, &vec![0; reference.inputs.len()]).is_none() {
+            return Err(format!(
+                "chip {} keeps state, only combinational chips can be checked",
+                chip.name
+            ));
+        }
+        let pins = |declared: &[Bus], expected: builtin::Pins, what: &str| {
+            if declared.len() != expected.len() {
+                return Err(format!(
+                    "chip {} should have {} {} pins",
+                    chip.name,
+                    expected.len(),
+                    what
+                ));
+            }
+            expected
+                .iter()
+                .map(
+                    |&(name, width)| match declared.iter().position(|b| b.name == name) {
+                        Some(i) if declared[i].width == width => Ok(i),
+                        _ => Err(format!(
+                            "chip {} should have an {} pin {}[{}]",
+                            chip.name, what, name, width
+                        )),
+                    },
+                )
+                .collect::<Result<Vec<_>, _>>()
+        };
+        let inputs = pins(chip.inputs(), reference.inputs, "input")?;
+        let outputs = pins(chip.outputs(), reference.outputs, "output")?;
+        Ok(Checker {
+            widths: reference.inputs.iter().map(|&(_, w)| w).collect(),
+            sim: Simulator::new(chip),
+            reference,
+            inputs,
+            outputs,
+        })
+    }
+
+    // The expected and the actual outputs, in the reference's pin order.
+    fn run(&mut self, values: &[u16]) -> Result<(Vec<u16>, Vec<u16>), String> {
+        for (&pin, &val) in self.inputs.iter().zip(values) {
+            let name = self.sim.chip().inputs()[pin].name.clone();
+            self.sim.set(&name, val)?;
+        }
+        self.sim.eval()?;
+        let expected = sim::eval_builtin(self.reference, values).unwrap_or_default();
+        let chip = self.sim.chip();
+        let actual = self
+            .outputs
+            .iter()
+            .map(|&pin| self.sim.get(&chip.outputs()[pin].name).unwrap_or(0))
+            .collect();
+        Ok((expected, actual))
+    }
+
+    fn fails(&mut self, values: &[u16]) -> Result<bool, String> {
+        let (expected, actual) = self.run(values)?;
+        Ok(expected != actual)
+    }
+
+    // Clears bits of the failing inputs for as long as they keep failing, so what's left
+    // is a counterexample in which clearing any single bit makes the chip agree.
+    fn shrink(&mut self, mut values: Vec<u16>) -> Result<Vec<u16>, String> {
+        loop {
+            let mut shrunk = false;
+            for i in 0..values.len() {
+                let mut candidates = vec![0];
+                candidates.extend((0..16).rev().map(|bit| values[i] & !(1 << bit)));
+                for candidate in candidates {
+                    if candidate == values[i] {
+                        continue;
+                    }
+                    let mut tried = values.clone();
+                    tried[i] = candidate;
+                    if self.fails(&tried)? {
+                        values = tried;
+                        shrunk = true;
+                        break;
+                    }
+                }
+            }
+            if !shrunk {
+                return Ok(values);
+            }
+        }
+    }
+
+    fn counterexample(&mut self, values: Vec<u16>) -> Result<Counterexample, String> {
+        let values = self.shrink(values)?;
+        let (expected, actual) = self.run(&values)?;
+        let named = |pins: builtin::Pins, values: &[u16]| {
+            pins.iter()
+                .zip(values)
+                .map(|(&(name, width), &val)| (name.to_owned(), width, val))
+                .collect()
+        };
+        Ok(Counterexample {
+            inputs: named(self.reference.inputs, &values),
+            expected: named(self.reference.outputs, &expected),
+            actual: named(self.reference.outputs, &actual),
+        })
+    }
+}
+
+fn random_value(rng: &mut Rng, width: u16) -> u16 {
+    let mask = if width >= 16 {
+        0xffff
+    } else {
+        (1 << width) - 1
+    };
+    let n = rng.next_u64();
+    let val = if n.is_multiple_of(4) {
+        SPECIAL_VALUES[(n >> 8) as usize % SPECIAL_VALUES.len()]
+    } else {
+        (n >> 16) as u16
+    };
+    val & mask
+}
+
+// Compares `chip` with the built-in chip of the same name. Chips with few input bits get
+// every input tried, the rest `vectors` random ones.
+pub fn check(chip: Rc<ChipDef>, vectors: u64, seed: u64) -> Result<Report, String> {
+    let mut checker = Checker::new(chip)?;
+    let bits: u32 = checker.widths.iter().map(|&w| w as u32).sum();
+
+    let exhaustive = bits <= EXHAUSTIVE_BITS;
+    let total = if exhaustive { 1 << bits } else { vectors };
+    let mut rng = Rng::new(seed);
+    for n in 0..total {
+        let values: Vec<u16> = if exhaustive {
+            // Splits the vector number into the inputs, the first input in the lowest bits.
+            let mut rest = n;
+            checker
+                .widths
+                .iter()
+                .map(|&w| {
+                    let val = (rest & ((1 << w) - 1)) as u16;
+                    rest >>= w;
+                    val
+                })
+                .collect()
+        } else {
+            checker
+                .widths
+                .iter()
+                .map(|&w| random_value(&mut rng, w))
+                .collect()
+        };
+        if checker.fails(&values)? {
+            return Ok(Report {
+                vectors: n + 1,
+                total,
+                exhaustive,
+                counterexample: Some(checker.counterexample(values)?),
+            });
+        }
+    }
+    Ok(Report {
+        vectors: total,
+        total,
+        exhaustive,
+        counterexample: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    // Checks the chip `name` with the given parts, on its own.
+    fn check_chip(name: &str, parts: &str) -> Report {
+        let dir = std::env::temp_dir().join(format!("check-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(format!("{}.hdl", name));
+        let builtin = builtin::builtin(name).unwrap();
+        let pins = |pins: builtin::Pins| {
+            let pins: Vec<String> = pins
+                .iter()
+                .map(|&(pin, width)| match width {
+                    1 => pin.to_owned(),
+                    _ => format!("{}[{}]", pin, width),
+                })
+                .collect();
+            pins.join(", ")
+        };
+        let src = format!(
+            "CHIP {} {{ IN {}; OUT {}; PARTS: {} }}",
+            name,
+            pins(builtin.inputs),
+            pins(builtin.outputs),
+            parts
+        );
+        std::fs::write(&path, src).unwrap();
+        let (chip, _) = crate::load(&path, &[]).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        check(chip, 1000, 1).unwrap()
+    }
+
+    #[test]
+    fn passes_a_correct_chip() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../project1/Xor.hdl");
+        let (chip, _) = crate::load(&path, &[]).unwrap();
+        let report = check(chip, 1000, 1).unwrap();
+        assert!(report.exhaustive);
+        assert_eq!((report.vectors, report.total), (4, 4));
+        assert_eq!(report.counterexample, None);
+    }
+
+    #[test]
+    fn finds_a_counterexample_exhaustively() {
+        let report = check_chip("Xor", "Or(a=a, b=b, out=out);");
+        assert!(report.exhaustive);
+        assert_eq!(report.vectors, 4);
+        let pin = |name: &str, value| (name.to_owned(), 1, value);
+        assert_eq!(
+            report.counterexample,
+            Some(Counterexample {
+                inputs: vec![pin("a", 1), pin("b", 1)],
+                expected: vec![pin("out", 0)],
+                actual: vec![pin("out", 1)],
+            })
+        );
+    }
+
+    #[test]
+    fn finds_a_counterexample_at_random() {
+        let report = check_chip("Add16", "Or16(a=a, b=b, out=out);");
+        assert!(!report.exhaustive);
+        assert_eq!(report.total, 1000);
+        // Shrunk down to a single carry.
+        let counterexample = report.counterexample.unwrap();
+        let [(_, _, a), (_, _, b)] = counterexample.inputs[..] else {
+            panic!("{:?}", counterexample.inputs);
+        };
+        assert_eq!((a, a.count_ones()), (b, 1));
+        assert_eq!(counterexample.expected, [("out".to_owned(), 16, a * 2)]);
+        assert_eq!(counterexample.actual, [("out".to_owned(), 16, a)]);
+    }
+}
@@ -0,0 +1,214 @@
+use std::fmt;
+
+use assembler::snippet::Snippet;
+
+use crate::ast::Span;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    // What the parser was looking for, like "a pin name".
+    Expected(&'static str),
+    // A keyword or a symbol the parser was looking for.
+    ExpectedToken(&'static str),
+    InvalidCharacter,
+    UnterminatedComment,
+    InvalidWidth,
+    DuplicatePin,
+    ChipNotFound,
+    NameMismatch,
+    RecursiveChip,
+    UnknownBuiltin,
+    UnknownPin,
+    UndefinedPin,
+    IndexOutOfRange,
+    ReversedRange,
+    WidthMismatch,
+    SubBusOfInternalPin,
+    DuplicateConnection,
+    MultipleDrivers,
+    DrivenInput,
+    OutputUsedAsInput,
+    ConstantOutput,
+    UnconnectedOutput,
+    UnusedPin,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+// A single problem in a chip definition, pointing at the offending text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub kind: ErrorKind,
+    pub severity: Severity,
+    pub file: Option<String>,
+    // See `Snippet`.
+    pub line: usize,
+    pub column: usize,
+    pub text: String,
+    pub source_line: String,
+    pub note: Option<String>,
+}
+
+// Every diagnostic found while loading a chip, possibly coming from several files.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HdlError {
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Diagnostic {
+    // `span` is a range of byte offsets into `src`.
+    pub fn new(kind: ErrorKind, src: &str, span: Span) -> Diagnostic {
+        let start = span.start.min(src.len());
+        let end = span.end.clamp(start, src.len());
+        let line_start = src[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = src[start..].find('\n').map_or(src.len(), |i| start + i);
+        Diagnostic {
+            kind,
+            severity: Severity::Error,
+            file: None,
+            line: src[..start].matches('\n').count() + 1,
+            column: src[line_start..start].chars().count() + 1,
+            // Only the part on the first line is underlined.
+            text: src[start..end.min(line_end)].to_owned(),
+            source_line: src[line_start..line_end].to_owned(),
+            note: None,
+        }
+    }
+
+    pub fn with_note(mut self, note: String) -> Diagnostic {
+        self.note = Some(note);
+        self
+    }
+
+    pub fn warning(mut self) -> Diagnostic {
+        self.severity = Severity::Warning;
+        self
+    }
+
+    pub fn message(&self) -> String {
+        match self.kind {
+            ErrorKind::Expected(what) if self.text.is_empty() => {
+                format!("expected {}, found the end of the file", what)
+            }
+            ErrorKind::Expected(what) => format!("expected {}, found `{}`", what, self.text),
+            ErrorKind::ExpectedToken(token) if self.text.is_empty() => {
+                format!("expected `{}`, found the end of the file", token)
+            }
+            ErrorKind::ExpectedToken(token) => {
+                format!("expected `{}`, found `{}`", token, self.text)
+            }
+            ErrorKind::InvalidCharacter => format!("unexpected character `{}`", self.text),
+            ErrorKind::UnterminatedComment => "comment is missing the closing `*/`".to_owned(),
+            ErrorKind::InvalidWidth => format!("invalid bus width `{}`", self.text),
+            ErrorKind::DuplicatePin => format!("pin `{}` is declared twice", self.text),
+            ErrorKind::ChipNotFound => format!("chip `{}` not found", self.text),
+            ErrorKind::NameMismatch => {
+                format!("chip `{}` doesn't match the name of its file", self.text)
+            }
+            ErrorKind::RecursiveChip => format!("chip `{}` contains itself", self.text),
+            ErrorKind::UnknownBuiltin => format!("there's no built-in chip `{}`", self.text),
+            ErrorKind::UnknownPin => format!("the part has no pin `{}`", self.text),
+            ErrorKind::UndefinedPin => format!("pin `{}` is never given a value", self.text),
+            ErrorKind::IndexOutOfRange => format!("`{}` is out of the bus's range", self.text),
+            ErrorKind::ReversedRange => {
+                format!("the range's start is after its end in `{}`", self.text)
+            }
+            ErrorKind::WidthMismatch => format!("width mismatch in `{}`", self.text),
+            ErrorKind::SubBusOfInternalPin => {
+                format!("internal pin `{}` can't be indexed", self.text)
+            }
+            ErrorKind::DuplicateConnection => {
+                format!("pin `{}` of the part is connected twice", self.text)
+            }
+            ErrorKind::MultipleDrivers => format!("pin `{}` has more than one source", self.text),
+            ErrorKind::DrivenInput => format!("input pin `{}` can't be a part's output", self.text),
+            ErrorKind::OutputUsedAsInput => {
+                format!("output pin `{}` can't be fed into a part", self.text)
+            }
+            ErrorKind::ConstantOutput => format!("a part's output can't go to `{}`", self.text),
+            ErrorKind::UnconnectedOutput => {
+                format!("output pin `{}` is never connected", self.text)
+            }
+            ErrorKind::UnusedPin => format!("pin `{}` is never used", self.text),
+        }
+    }
+
+    pub fn snippet(&self) -> Snippet<'_> {
+        Snippet {
+            file: self.file.as_deref(),
+            line: self.line,
+            column: self.column,
+            text: &self.text,
+            source_line: &self.source_line,
+        }
+    }
+
+    pub fn render(&self) -> String {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        self.snippet()
+            .render(severity, &self.message(), self.note.as_deref())
+    }
+}
+
+impl HdlError {
+    // Attributes every diagnostic which doesn't know its file yet to `file`.
+    pub fn with_file(mut self, file: &str) -> HdlError {
+        for d in self.diagnostics.iter_mut().filter(|d| d.file.is_none()) {
+            d.file = Some(file.to_owned());
+        }
+        self
+    }
+
+    pub fn render(&self) -> String {
+        self.diagnostics
+            .iter()
+            .map(Diagnostic::render)
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+impl From<Diagnostic> for HdlError {
+    fn from(d: Diagnostic) -> Self {
+        HdlError {
+            diagnostics: vec![d],
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {}{}",
+            self.snippet(),
+            if self.severity == Severity::Warning {
+                "warning: "
+            } else {
+                ""
+            },
+            self.message()
+        )
+    }
+}
+
+impl fmt::Display for HdlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, d) in self.diagnostics.iter().enumerate() {
+            if i != 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", d)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for HdlError {}
@@ -0,0 +1,173 @@
+// Writes flattened chips out for other tools: Graphviz DOT and structural Verilog.
+
+use std::fmt::Write;
+
+use crate::flatten::{FlatChip, Signal};
+
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+pub fn to_dot(chip: &FlatChip) -> String {
+    let drivers = chip.drivers();
+    // Where the edge carrying a signal starts.
+    let source = |signal: Signal, net_bit: &dyn Fn(usize) -> String| match signal {
+        Signal::Const(val) => format!("const_{}", val as u8),
+        Signal::Net(net) => match drivers[net] {
+            Some(gate) => format!("g{}", gate),
+            None => net_bit(net),
+        },
+    };
+    let input_node = |net: usize| format!("in{}", net);
+
+    let mut out = String::new();
+    writeln!(out, "digraph {} {{", quote(&chip.name)).unwrap();
+    writeln!(out, "  rankdir=LR;").unwrap();
+    writeln!(out, "  node [shape=box];").unwrap();
+    writeln!(out, "  const_0 [label=\"false\", shape=plaintext];").unwrap();
+    writeln!(out, "  const_1 [label=\"true\", shape=plaintext];").unwrap();
+    for port in &chip.inputs {
+        for signal in &port.bits {
+            if let Signal::Net(net) = signal {
+                let label = quote(&chip.nets[*net]);
+                writeln!(out, "  in{} [label={}, shape=circle];", net, label).unwrap();
+            }
+        }
+    }
+    for (i, gate) in chip.gates.iter().enumerate() {
+        let shape = if gate.is_nand() { "invhouse" } else { "box3d" };
+        let label = match gate.is_nand() {
+            true => "Nand".to_owned(),
+            false => format!("{}\\n{}", gate.name(), gate.path),
+        };
+        writeln!(out, "  g{} [label={}, shape={}];", i, quote(&label), shape).unwrap();
+    }
+    for (i, gate) in chip.gates.iter().enumerate() {
+        for &signal in gate.inputs.iter().flatten() {
+            writeln!(out, "  {} -> g{};", source(signal, &input_node), i).unwrap();
+        }
+    }
+    for port in &chip.outputs {
+        for (bit, &signal) in port.bits.iter().enumerate() {
+            let name = match port.bits.len() {
+                1 => port.name.clone(),
+                _ => format!("{}[{}]", port.name, bit),
+            };
+            let node = quote(&format!("out {}", name));
+            writeln!(
+                out,
+                "  {} [label={}, shape=doublecircle];",
+                node,
+                quote(&name)
+            )
+            .unwrap();
+            writeln!(out, "  {} -> {};", source(signal, &input_node), node).unwrap();
+        }
+    }
+    out.push_str("}\n");
+    out
+}
+
+// Verilog identifiers can't contain the `.` and `[]` of hierarchical names.
+fn identifier(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+// A structural Verilog module using `nand` primitives, and a `DFF` module of its own for
+// the clocked parts, which get a `clk` input. Any other built-in chip has no Verilog
+// equivalent, so those have to be made of `Nand` gates by their `.hdl` files.
+pub fn to_verilog(chip: &FlatChip) -> Result<String, String> {
+    if let Some(gate) = chip
+        .gates
+        .iter()
+        .find(|g| !g.is_nand() && g.name() != "DFF")
+    {
+        return Err(format!(
+            "{} is a built-in chip without a Verilog equivalent, look for its .hdl file with -L",
+            gate.path
+        ));
+    }
+    let clocked = chip.gates.iter().any(|g| !g.is_nand());
+
+    // Inputs are referred to by their port bits, every other net by its own wire.
+    let mut input_bits = vec![None; chip.nets.len()];
+    for port in &chip.inputs {
+        for (bit, signal) in port.bits.iter().enumerate() {
+            if let Signal::Net(net) = signal {
+                input_bits[*net] = Some(match port.bits.len() {
+                    1 => identifier(&port.name),
+                    _ => format!("{}[{}]", identifier(&port.name), bit),
+                });
+            }
+        }
+    }
+    let expr = |signal: Signal| match signal {
+        Signal::Const(val) => format!("1'b{}", val as u8),
+        Signal::Net(net) => input_bits[net]
+            .clone()
+            .unwrap_or_else(|| format!("n{}", net)),
+    };
+    let port_decl = |dir: &str, name: &str, width: usize| match width {
+        1 => format!("{} {}", dir, identifier(name)),
+        w => format!("{} [{}:0] {}", dir, w - 1, identifier(name)),
+    };
+
+    let mut out = String::new();
+    let mut ports: Vec<String> = Vec::new();
+    if clocked {
+        ports.push("input clk".to_owned());
+    }
+    ports.extend(
+        chip.inputs
+            .iter()
+            .map(|p| port_decl("input", &p.name, p.bits.len())),
+    );
+    ports.extend(
+        chip.outputs
+            .iter()
+            .map(|p| port_decl("output", &p.name, p.bits.len())),
+    );
+    writeln!(out, "// Generated from {}.hdl", chip.name).unwrap();
+    writeln!(out, "module {}(", identifier(&chip.name)).unwrap();
+    writeln!(out, "  {}", ports.join(",\n  ")).unwrap();
+    writeln!(out, ");").unwrap();
+    for net in (0..chip.nets.len()).filter(|&n| input_bits[n].is_none()) {
+        writeln!(out, "  wire n{}; // {}", net, chip.nets[net]).unwrap();
+    }
+    for (i, gate) in chip.gates.iter().enumerate() {
+        let inputs: Vec<String> = gate.inputs.iter().flatten().map(|&s| expr(s)).collect();
+        let output = gate.outputs[0][0];
+        if gate.is_nand() {
+            writeln!(out, "  nand g{}(n{}, {});", i, output, inputs.join(", ")).unwrap();
+        } else {
+            writeln!(
+                out,
+                "  DFF g{}(.clk(clk), .in({}), .out(n{})); // {}",
+                i, inputs[0], output, gate.path
+            )
+            .unwrap();
+        }
+    }
+    for port in &chip.outputs {
+        for (bit, &signal) in port.bits.iter().enumerate() {
+            let name = match port.bits.len() {
+                1 => identifier(&port.name),
+                _ => format!("{}[{}]", identifier(&port.name), bit),
+            };
+            writeln!(out, "  assign {} = {};", name, expr(signal)).unwrap();
+        }
+    }
+    writeln!(out, "endmodule").unwrap();
+    if clocked {
+        out.push_str(concat!(
+            "\n",
+            "module DFF(input clk, input in, output reg out);\n",
+            "  initial out = 1'b0;\n",
+            "  always @(posedge clk) out <= in;\n",
+            "endmodule\n"
+        ));
+    }
+    Ok(out)
+}
@@ -0,0 +1,354 @@
+// A chip flattened down to single-bit signals between `Nand` gates and whatever built-in
+// chips are left, for counting gates and exporting the netlist to other tools.
+
+use rustc_hash::FxHashMap;
+
+use crate::builtin::Builtin;
+use crate::netlist::{ChipDef, Implementation, Source};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Signal {
+    Const(bool),
+    Net(usize),
+}
+
+#[derive(Debug, Clone)]
+pub struct Gate {
+    // None for `Nand`, whose pins are `a`, `b` and `out` like the built-in one.
+    pub builtin: Option<&'static Builtin>,
+    // Where the part sits in the chip, like `ALU.Add16_7.FullAdder_0`.
+    pub path: String,
+    // One signal per bit of each input pin, least significant first.
+    pub inputs: Vec<Vec<Signal>>,
+    // The nets driven by each output pin; bits which aren't connected get their own net.
+    pub outputs: Vec<Vec<usize>>,
+}
+
+impl Gate {
+    pub fn is_nand(&self) -> bool {
+        self.builtin.is_none()
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.builtin.map_or("Nand", |b| b.name)
+    }
+}
+
+// A top-level pin, with a signal for each of its bits.
+#[derive(Debug, Clone)]
+pub struct Port {
+    pub name: String,
+    pub bits: Vec<Signal>,
+}
+
+#[derive(Debug, Clone)]
+pub struct FlatChip {
+    pub name: String,
+    pub inputs: Vec<Port>,
+    pub outputs: Vec<Port>,
+    pub gates: Vec<Gate>,
+    // A name for each net, the shallowest one connected to it.
+    pub nets: Vec<String>,
+}
+
+struct Flattener {
+    // Union-find over every bit of every bus of every part.
+    parent: Vec<usize>,
+    names: Vec<String>,
+    consts: Vec<(usize, bool)>,
+    gates: Vec<UnresolvedGate>,
+}
+
+// A gate's built-in chip, path, and the bits of its inputs and outputs.
+type UnresolvedGate = (
+    Option<&'static Builtin>,
+    String,
+    Vec<Vec<usize>>,
+    Vec<Vec<usize>>,
+);
+
+impl Flattener {
+    fn bits(&mut self, prefix: &str, name: &str, width: u16) -> Vec<usize> {
+        (0..width)
+            .map(|bit| {
+                self.parent.push(self.parent.len());
+                self.names.push(match width {
+                    1 => format!("{}{}", prefix, name),
+                    _ => format!("{}{}[{}]", prefix, name, bit),
+                });
+                self.parent.len() - 1
+            })
+            .collect()
+    }
+
+    fn find(&mut self, mut bit: usize) -> usize {
+        while self.parent[bit] != bit {
+            self.parent[bit] = self.parent[self.parent[bit]];
+            bit = self.parent[bit];
+        }
+        bit
+    }
+
+    // The root is always the older bit, which keeps the shallowest name.
+    fn union(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        self.parent[a.max(b)] = a.min(b);
+    }
+
+    fn instantiate(&mut self, chip: &ChipDef, path: &str, buses: &[Vec<usize>]) {
+        if let Implementation::Builtin(builtin) = &chip.implementation {
+            let inputs = chip.inputs().len();
+            self.gates.push((
+                (builtin.name != "Nand").then_some(*builtin),
+                path.to_owned(),
+                buses[..inputs].to_vec(),
+                buses[inputs..].to_vec(),
+            ));
+            return;
+        }
+        for (i, part) in chip.parts().iter().enumerate() {
+            let sub_path = format!("{}.{}_{}", path, part.chip.name, i);
+            let prefix = format!("{}.", sub_path);
+            let sub_buses: Vec<Vec<usize>> = part
+                .chip
+                .buses
+                .iter()
+                .map(|b| self.bits(&prefix, &b.name, b.width))
+                .collect();
+            for input in &part.inputs {
+                let pin = &sub_buses[input.pin];
+                for k in 0..=(input.hi - input.lo) {
+                    let bit = pin[(input.lo + k) as usize];
+                    match input.source {
+                        Source::Bus { bus, lo, .. } => {
+                            self.union(bit, buses[bus][(lo + k) as usize])
+                        }
+                        Source::Const(val) => self.consts.push((bit, val)),
+                    }
+                }
+            }
+            let first_output = part.chip.inputs().len();
+            for output in &part.outputs {
+                let pin = &sub_buses[first_output + output.pin];
+                for k in 0..=(output.hi - output.lo) {
+                    let bus_bit = buses[output.bus][(output.bus_lo + k) as usize];
+                    self.union(pin[(output.lo + k) as usize], bus_bit);
+                }
+            }
+            self.instantiate(&part.chip, &sub_path, &sub_buses);
+        }
+    }
+}
+
+impl FlatChip {
+    pub fn new(chip: &ChipDef) -> FlatChip {
+        let mut f = Flattener {
+            parent: Vec::new(),
+            names: Vec::new(),
+            consts: Vec::new(),
+            gates: Vec::new(),
+        };
+        let buses: Vec<Vec<usize>> = chip
+            .buses
+            .iter()
+            .map(|b| f.bits("", &b.name, b.width))
+            .collect();
+        f.instantiate(chip, &chip.name, &buses);
+
+        // Bits are either constant, driven by a gate or one of the inputs, or left floating,
+        // which the course's simulator treats as false.
+        let mut value: FxHashMap<usize, Signal> = FxHashMap::default();
+        for (bit, val) in f.consts.clone() {
+            let root = f.find(bit);
+            value.insert(root, Signal::Const(val));
+        }
+        let mut nets = Vec::new();
+        let mut net = |f: &mut Flattener, bit: usize, value: &mut FxHashMap<usize, Signal>| {
+            let root = f.find(bit);
+            match value.get(&root) {
+                Some(&Signal::Net(n)) => n,
+                _ => {
+                    nets.push(f.names[root].clone());
+                    value.insert(root, Signal::Net(nets.len() - 1));
+                    nets.len() - 1
+                }
+            }
+        };
+        let inputs = chip.inputs().len();
+        for bits in &buses[..inputs] {
+            for &bit in bits {
+                net(&mut f, bit, &mut value);
+            }
+        }
+        let mut gates = Vec::new();
+        for (builtin, path, gate_inputs, gate_outputs) in std::mem::take(&mut f.gates) {
+            let outputs = gate_outputs
+                .iter()
+                .map(|pin| pin.iter().map(|&b| net(&mut f, b, &mut value)).collect())
+                .collect();
+            gates.push((builtin, path, gate_inputs, outputs));
+        }
+        let signal = |f: &mut Flattener, bit: usize| {
+            let root = f.find(bit);
+            value.get(&root).copied().unwrap_or(Signal::Const(false))
+        };
+        let gates = gates
+            .into_iter()
+            .map(|(builtin, path, gate_inputs, outputs)| Gate {
+                builtin,
+                path,
+                inputs: gate_inputs
+                    .iter()
+                    .map(|pin| pin.iter().map(|&b| signal(&mut f, b)).collect())
+                    .collect(),
+                outputs,
+            })
+            .collect();
+        let mut ports = |range: std::ops::Range<usize>| -> Vec<Port> {
+            range
+                .map(|bus| Port {
+                    name: chip.buses[bus].name.clone(),
+                    bits: buses[bus].iter().map(|&b| signal(&mut f, b)).collect(),
+                })
+                .collect()
+        };
+        let inputs = ports(0..inputs);
+        let outputs = ports(inputs.len()..inputs.len() + chip.outputs().len());
+        FlatChip {
+            name: chip.name.clone(),
+            inputs,
+            outputs,
+            gates,
+            nets,
+        }
+    }
+
+    pub fn nand_count(&self) -> usize {
+        self.gates.iter().filter(|g| g.is_nand()).count()
+    }
+
+    // How many of each built-in chip other than `Nand` are left, by name.
+    pub fn builtin_counts(&self) -> Vec<(&'static str, usize)> {
+        let mut counts: Vec<(&'static str, usize)> = Vec::new();
+        for gate in self.gates.iter().filter(|g| !g.is_nand()) {
+            match counts.iter_mut().find(|(name, _)| *name == gate.name()) {
+                Some((_, n)) => *n += 1,
+                None => counts.push((gate.name(), 1)),
+            }
+        }
+        counts
+    }
+
+    // The gate driving each net, None for the chip's inputs.
+    pub fn drivers(&self) -> Vec<Option<usize>> {
+        let mut drivers = vec![None; self.nets.len()];
+        for (i, gate) in self.gates.iter().enumerate() {
+            for &net in gate.outputs.iter().flatten() {
+                drivers[net] = Some(i);
+            }
+        }
+        drivers
+    }
+
+    // How many gate inputs and chip outputs read each net.
+    pub fn fan_out(&self) -> Vec<usize> {
+        let mut fan_out = vec![0; self.nets.len()];
+        let signals = self
+            .gates
+            .iter()
+            .flat_map(|g| g.inputs.iter().flatten())
+            .chain(self.outputs.iter().flat_map(|p| &p.bits));
+        for signal in signals {
+            if let Signal::Net(net) = signal {
+                fan_out[*net] += 1;
+            }
+        }
+        fan_out
+    }
+
+    // The longest chain of `Nand` gates between the chip's inputs, the other built-in chips
+    // and the chip's outputs, None if the gates form a loop.
+    pub fn depth(&self) -> Option<usize> {
+        let drivers = self.drivers();
+        // The depth at each gate's output, computed depth-first.
+        let mut depth: Vec<Option<usize>> = vec![None; self.gates.len()];
+        let mut visiting = vec![false; self.gates.len()];
+        let mut max = 0;
+        for root in 0..self.gates.len() {
+            if depth[root].is_some() {
+                continue;
+            }
+            let mut stack = vec![(root, 0)];
+            while let Some(&(gate, input)) = stack.last() {
+                let g = &self.gates[gate];
+                if !g.is_nand() {
+                    depth[gate] = Some(0);
+                    stack.pop();
+                    continue;
+                }
+                visiting[gate] = true;
+                let inputs: Vec<Signal> = g.inputs.iter().flatten().copied().collect();
+                if let Some(&signal) = inputs.get(input) {
+                    stack.last_mut().unwrap().1 += 1;
+                    if let Signal::Net(net) = signal {
+                        if let Some(d) = drivers[net].filter(|&d| depth[d].is_none()) {
+                            if visiting[d] {
+                                return None;
+                            }
+                            stack.push((d, 0));
+                        }
+                    }
+                    continue;
+                }
+                let deepest = inputs
+                    .iter()
+                    .filter_map(|s| match s {
+                        Signal::Net(net) => drivers[*net].and_then(|d| depth[d]),
+                        Signal::Const(_) => None,
+                    })
+                    .max()
+                    .unwrap_or(0);
+                depth[gate] = Some(deepest + 1);
+                max = max.max(deepest + 1);
+                visiting[gate] = false;
+                stack.pop();
+            }
+        }
+        Some(max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    // A chip of the repo, flattened without any library directory.
+    fn flatten(path: &str) -> FlatChip {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../..")
+            .join(path);
+        let (chip, _) = crate::load(&path, &[]).unwrap();
+        FlatChip::new(&chip)
+    }
+
+    #[test]
+    fn xor_stats() {
+        let xor = flatten("project1/Xor.hdl");
+        assert_eq!(xor.nand_count(), 4);
+        assert_eq!(xor.depth(), Some(3));
+        assert_eq!(xor.fan_out().iter().max(), Some(&2));
+        assert!(xor.builtin_counts().is_empty());
+    }
+
+    #[test]
+    fn chips_without_hdl_files_stay_built_in() {
+        let add16 = flatten("project2/Add16.hdl");
+        assert_eq!(add16.nand_count(), 0);
+        assert_eq!(
+            add16.builtin_counts(),
+            [("Xor", 31), ("And", 31), ("Or", 15)]
+        );
+    }
+}
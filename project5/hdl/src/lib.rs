@@ -0,0 +1,25 @@
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+pub use error::{Diagnostic, ErrorKind, HdlError, Severity};
+pub use flatten::FlatChip;
+pub use netlist::{ChipDef, Library};
+pub use sim::Simulator;
+
+pub mod ast;
+pub mod builtin;
+pub mod check;
+pub mod error;
+pub mod export;
+pub mod flatten;
+pub mod netlist;
+pub mod parser;
+pub mod sim;
+
+// Loads the chip in `path` along with every chip it's made of, looking for those next to it
+// first and then in `dirs`.
+pub fn load(path: &Path, dirs: &[PathBuf]) -> Result<(Rc<ChipDef>, Library), HdlError> {
+    let mut library = Library::new(dirs.to_vec());
+    let chip = library.load_file(path)?;
+    Ok((chip, library))
+}
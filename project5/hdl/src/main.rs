@@ -0,0 +1,411 @@
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::rc::Rc;
+
+use hdl::netlist::{BusKind, Implementation, Source};
+use hdl::{ChipDef, FlatChip, Simulator};
+
+const USAGE: &str = r#"Usage:
+    hdl <file.hdl> [options]
+    hdl --stats <file.hdl>... [options]
+    hdl --check <file.hdl>... [options]
+
+Checks a chip and every chip it's made of, then prints its pins and parts.
+Given any of the simulation options, it simulates the chip instead and prints its
+outputs. Chips without an .hdl file next to the chip or in a library directory are
+simulated by built-in ones, so leaving out `-L` keeps e.g. RAM16K fast.
+
+Options:
+  -L <dir>,  --library <dir>            Also looks for chips in <dir>, can be repeated
+  --stats                               Prints the number of Nand gates, the longest path through them
+                                        and the fan-out of each chip, flattened
+  --check                               Compares each chip with the built-in chip of the same name,
+                                        trying every input or random ones for wide inputs
+  --vectors <n>                         Random inputs tried by --check (default 10000)
+  --seed <n>                            Seed for the random inputs (default 1)
+  --dot <file>                          Writes the flattened chip as a Graphviz graph
+  --verilog <file>                      Writes the flattened chip as a structural Verilog module
+  --pin <name>=<val>                    Sets an input pin, can be repeated
+  --rom <file.asm|file.hack>            Loads a program into the chip's ROM32K
+  -c <n>,    --cycles <n>               Runs the chip for <n> clock cycles (default 0)
+  -s <addr>=<val>, --set <addr>=<val>   Sets RAM[addr] before running, can be repeated. In a RAM16K
+                                        of your own, words are found through the Mux chips picking
+                                        its output by address bits
+  -p <from>..<to>, --print <from>..<to> Prints RAM[from..to] after running (default 0..16
+                                        when there's a ROM)
+  -h,        --help                     Prints help message
+"#;
+
+fn describe(chip: &ChipDef) -> String {
+    let pins = |kind| {
+        chip.buses
+            .iter()
+            .filter(|b| b.kind == kind)
+            .map(|b| match b.width {
+                1 => b.name.clone(),
+                w => format!("{}[{}]", b.name, w),
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let mut out = format!("CHIP {}\n", chip.name);
+    out.push_str(&format!("  IN {}\n", pins(BusKind::Input)));
+    out.push_str(&format!("  OUT {}\n", pins(BusKind::Output)));
+    match &chip.implementation {
+        Implementation::Builtin(builtin) => out.push_str(&format!("  BUILTIN {}\n", builtin.name)),
+        Implementation::Parts(parts) => {
+            out.push_str(&format!("  internal {}\n", pins(BusKind::Internal)));
+            let range = |name: &str, width: u16, lo: u16, hi: u16| match (lo, hi) {
+                (0, hi) if hi + 1 == width => name.to_owned(),
+                (lo, hi) if lo == hi => format!("{}[{}]", name, lo),
+                (lo, hi) => format!("{}[{}..{}]", name, lo, hi),
+            };
+            for part in parts {
+                let mut connections = Vec::new();
+                for input in &part.inputs {
+                    let pin = &part.chip.inputs()[input.pin];
+                    let source = match input.source {
+                        Source::Const(val) => val.to_string(),
+                        Source::Bus { bus, lo, hi } => {
+                            let bus = &chip.buses[bus];
+                            range(&bus.name, bus.width, lo, hi)
+                        }
+                    };
+                    connections.push(format!(
+                        "{}={}",
+                        range(&pin.name, pin.width, input.lo, input.hi),
+                        source
+                    ));
+                }
+                for output in &part.outputs {
+                    let pin = &part.chip.outputs()[output.pin];
+                    let bus = &chip.buses[output.bus];
+                    connections.push(format!(
+                        "{}={}",
+                        range(&pin.name, pin.width, output.lo, output.hi),
+                        range(&bus.name, bus.width, output.bus_lo, output.bus_hi)
+                    ));
+                }
+                out.push_str(&format!(
+                    "  {}({})\n",
+                    part.chip.name,
+                    connections.join(", ")
+                ));
+            }
+        }
+    }
+    out
+}
+
+fn parse_range(s: &str) -> Option<(u16, u16)> {
+    let (from, to) = s.split_once("..")?;
+    Some((from.parse().ok()?, to.parse().ok()?))
+}
+
+// Values set from the command line may be negative, like the emulator's.
+fn parse_assignment<T: std::str::FromStr>(s: &str) -> Option<(T, u16)> {
+    let (target, val) = s.split_once('=')?;
+    let val = val
+        .parse::<u16>()
+        .or_else(|_| val.parse::<i16>().map(|v| v as u16))
+        .ok()?;
+    Some((target.parse().ok()?, val))
+}
+
+struct Simulation {
+    pins: Vec<(String, u16)>,
+    rom: Option<String>,
+    cycles: u64,
+    sets: Vec<(u16, u16)>,
+    print: Option<(u16, u16)>,
+}
+
+fn simulate(chip: Rc<ChipDef>, options: &Simulation) -> Result<(), String> {
+    let mut sim = Simulator::new(chip);
+    let mut print = options.print;
+    if let Some(path) = &options.rom {
+        let program = emulator::load_rom(Path::new(path))?;
+        if !sim.load_rom(&program) {
+            return Err(format!("chip {} has no ROM32K", sim.chip().name));
+        }
+        print = print.or(Some((0, 16)));
+    }
+    for (pin, val) in &options.pins {
+        sim.set(pin, *val)?;
+    }
+    for &(addr, val) in &options.sets {
+        if !sim.set_memory(addr, val) {
+            return Err(format!(
+                "RAM[{}] isn't part of chip {}",
+                addr,
+                sim.chip().name
+            ));
+        }
+    }
+    sim.eval()?;
+    for _ in 0..options.cycles {
+        sim.step()?;
+    }
+
+    for bus in sim.chip().outputs() {
+        let val = sim.get(&bus.name).unwrap_or(0);
+        match bus.width {
+            16 => println!("{} = {}", bus.name, val as i16),
+            _ => println!("{} = {}", bus.name, val),
+        }
+    }
+    if let Some((from, to)) = print {
+        for addr in from..to {
+            match sim.memory(addr) {
+                Some(val) => println!("RAM[{}] = {}", addr, val as i16),
+                None => {
+                    return Err(format!(
+                        "RAM[{}] isn't part of chip {}",
+                        addr,
+                        sim.chip().name
+                    ))
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn stats_header() -> String {
+    format!(
+        "{:<16} {:>7} {:>6} {:>12} {:>12}  {}",
+        "Chip", "NANDs", "Depth", "Max fan-out", "Avg fan-out", "Built-in parts"
+    )
+}
+
+fn stats(chip: &ChipDef) -> String {
+    let flat = FlatChip::new(chip);
+    let fan_out = flat.fan_out();
+    let max = fan_out.iter().max().copied().unwrap_or(0);
+    let avg = match fan_out.len() {
+        0 => 0.0,
+        n => fan_out.iter().sum::<usize>() as f64 / n as f64,
+    };
+    let depth = match flat.depth() {
+        Some(depth) => depth.to_string(),
+        None => "loop".to_owned(),
+    };
+    let builtins: Vec<String> = flat
+        .builtin_counts()
+        .iter()
+        .map(|(name, n)| format!("{} x{}", name, n))
+        .collect();
+    format!(
+        "{:<16} {:>7} {:>6} {:>12} {:>12.2}  {}",
+        chip.name,
+        flat.nand_count(),
+        depth,
+        max,
+        avg,
+        builtins.join(", ")
+    )
+}
+
+fn check_chip(chip: Rc<ChipDef>, vectors: u64, seed: u64) -> Result<(), String> {
+    let name = chip.name.clone();
+    let report = hdl::check::check(chip, vectors, seed)?;
+    let Some(counterexample) = report.counterexample else {
+        if report.exhaustive {
+            println!("{}: ok, all {} inputs agree", name, report.vectors);
+        } else {
+            println!("{}: ok, {} random inputs agree", name, report.vectors);
+        }
+        return Ok(());
+    };
+    let pin = |(name, width, val): &(String, u16, u16)| {
+        format!("{} = {:0w$b}", name, val, w = *width as usize)
+    };
+    let mut message = format!(
+        "{}: differs from the built-in chip after {} of {} inputs ({})\n  inputs:",
+        name,
+        report.vectors,
+        report.total,
+        if report.exhaustive {
+            "exhaustive"
+        } else {
+            "random"
+        }
+    );
+    for input in &counterexample.inputs {
+        message.push_str(&format!("\n    {}", pin(input)));
+    }
+    message.push_str("\n  outputs:");
+    for (expected, actual) in counterexample.expected.iter().zip(&counterexample.actual) {
+        if expected == actual {
+            message.push_str(&format!("\n    {}", pin(actual)));
+        } else {
+            message.push_str(&format!(
+                "\n    {}, expected {:0w$b}",
+                pin(actual),
+                expected.2,
+                w = expected.1 as usize
+            ));
+        }
+    }
+    Err(message)
+}
+
+fn load(input_file: &str, dirs: &[PathBuf]) -> Option<Rc<ChipDef>> {
+    let path = Path::new(input_file);
+    if !path.is_file() {
+        eprintln!("Couldn't open file: {}", input_file);
+        return None;
+    }
+    match hdl::load(path, dirs) {
+        Ok((chip, library)) => {
+            for warning in &library.warnings {
+                eprintln!("{}", warning.render());
+            }
+            Some(chip)
+        }
+        Err(e) => {
+            eprintln!("{}", e.render());
+            let errors = e.diagnostics.len();
+            eprintln!(
+                "error: could not load chip due to {} previous error{}",
+                errors,
+                if errors == 1 { "" } else { "s" }
+            );
+            None
+        }
+    }
+}
+
+fn export(
+    chip: &ChipDef,
+    dot_path: Option<&str>,
+    verilog_path: Option<&str>,
+) -> Result<(), String> {
+    let flat = FlatChip::new(chip);
+    if let Some(path) = dot_path {
+        std::fs::write(path, hdl::export::to_dot(&flat))
+            .map_err(|e| format!("Couldn't write {}: {}", path, e))?;
+    }
+    if let Some(path) = verilog_path {
+        let verilog = hdl::export::to_verilog(&flat)?;
+        std::fs::write(path, verilog).map_err(|e| format!("Couldn't write {}: {}", path, e))?;
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let mut args = std::env::args();
+    args.next();
+    let mut input_files = Vec::new();
+    let mut show_stats = false;
+    let mut check = false;
+    let mut vectors = 10_000;
+    let mut seed = 1;
+    let mut dot_path = None;
+    let mut verilog_path = None;
+    let mut dirs = Vec::new();
+    let mut simulation = Simulation {
+        pins: Vec::new(),
+        rom: None,
+        cycles: 0,
+        sets: Vec::new(),
+        print: None,
+    };
+    while let Some(arg) = args.next() {
+        let parsed = match arg.as_str() {
+            "-L" | "--library" => args.next().map(|dir| dirs.push(PathBuf::from(dir))),
+            "--pin" => args
+                .next()
+                .and_then(|s| parse_assignment(&s))
+                .map(|pin| simulation.pins.push(pin)),
+            "--stats" => {
+                show_stats = true;
+                Some(())
+            }
+            "--check" => {
+                check = true;
+                Some(())
+            }
+            "--vectors" => args
+                .next()
+                .and_then(|n| n.parse().ok())
+                .map(|n| vectors = n),
+            "--seed" => args.next().and_then(|n| n.parse().ok()).map(|n| seed = n),
+            "--dot" => args.next().map(|p| dot_path = Some(p)),
+            "--verilog" => args.next().map(|p| verilog_path = Some(p)),
+            "--rom" => args.next().map(|p| simulation.rom = Some(p)),
+            "-c" | "--cycles" => args
+                .next()
+                .and_then(|n| n.parse().ok())
+                .map(|n| simulation.cycles = n),
+            "-s" | "--set" => args
+                .next()
+                .and_then(|s| parse_assignment(&s))
+                .map(|set| simulation.sets.push(set)),
+            "-p" | "--print" => args
+                .next()
+                .and_then(|r| parse_range(&r))
+                .map(|r| simulation.print = Some(r)),
+            "-h" | "--help" => {
+                print!("{}", USAGE);
+                return ExitCode::SUCCESS;
+            }
+            _ => {
+                input_files.push(arg.clone());
+                Some(())
+            }
+        };
+        if parsed.is_none() {
+            eprintln!("Invalid value for {}", arg);
+            return ExitCode::FAILURE;
+        }
+    }
+
+    if input_files.is_empty() {
+        print!("{}", USAGE);
+        return ExitCode::FAILURE;
+    }
+    let exporting = dot_path.is_some() || verilog_path.is_some();
+    let simulating = !simulation.pins.is_empty()
+        || simulation.rom.is_some()
+        || simulation.cycles > 0
+        || !simulation.sets.is_empty()
+        || simulation.print.is_some();
+    if input_files.len() > 1 && (exporting || simulating) {
+        eprintln!("Only --stats and --check work on several chips at once");
+        return ExitCode::FAILURE;
+    }
+
+    if show_stats {
+        println!("{}", stats_header());
+    }
+    let mut failed = false;
+    for input_file in &input_files {
+        let Some(chip) = load(input_file, &dirs) else {
+            failed = true;
+            continue;
+        };
+        let result = if show_stats {
+            println!("{}", stats(&chip));
+            Ok(())
+        } else if check {
+            check_chip(chip, vectors, seed)
+        } else if exporting {
+            export(&chip, dot_path.as_deref(), verilog_path.as_deref())
+        } else if simulating {
+            simulate(chip, &simulation)
+        } else {
+            print!("{}", describe(&chip));
+            Ok(())
+        };
+        if let Err(e) = result {
+            eprintln!("{}", e);
+            failed = true;
+        }
+    }
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
@@ -0,0 +1,601 @@
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use rustc_hash::FxHashMap;
+
+use crate::ast::{self, Body, PinRef, Span, Wire};
+use crate::builtin::{self, Builtin};
+use crate::error::{Diagnostic, ErrorKind, HdlError};
+use crate::parser;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BusKind {
+    Input,
+    Output,
+    Internal,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bus {
+    pub name: String,
+    pub width: u16,
+    pub kind: BusKind,
+}
+
+// Where the bits fed into a part's input come from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    // Bits `lo..=hi` of one of the chip's buses.
+    Bus { bus: usize, lo: u16, hi: u16 },
+    // `true` or `false`, repeated over the whole range.
+    Const(bool),
+}
+
+// Bits `lo..=hi` of the part's input `pin`, which is an index into its inputs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PartInput {
+    pub pin: usize,
+    pub lo: u16,
+    pub hi: u16,
+    pub source: Source,
+}
+
+// Bits `lo..=hi` of the part's output `pin` go to bits `bus_lo..=bus_hi` of `bus`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PartOutput {
+    pub pin: usize,
+    pub lo: u16,
+    pub hi: u16,
+    pub bus: usize,
+    pub bus_lo: u16,
+    pub bus_hi: u16,
+}
+
+#[derive(Debug, Clone)]
+pub struct Part {
+    pub chip: Rc<ChipDef>,
+    pub inputs: Vec<PartInput>,
+    pub outputs: Vec<PartOutput>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub enum Implementation {
+    Builtin(&'static Builtin),
+    Parts(Vec<Part>),
+}
+
+// A chip with every name resolved: its pins, and the parts wired between them.
+#[derive(Debug, Clone)]
+pub struct ChipDef {
+    pub name: String,
+    pub file: Option<PathBuf>,
+    // The inputs come first, then the outputs, then the internal pins.
+    pub buses: Vec<Bus>,
+    pub implementation: Implementation,
+}
+
+impl ChipDef {
+    fn from_builtin(builtin: &'static Builtin) -> ChipDef {
+        let pins = |pins: builtin::Pins, kind| {
+            pins.iter().map(move |&(name, width)| Bus {
+                name: name.to_owned(),
+                width,
+                kind,
+            })
+        };
+        ChipDef {
+            name: builtin.name.to_owned(),
+            file: None,
+            buses: pins(builtin.inputs, BusKind::Input)
+                .chain(pins(builtin.outputs, BusKind::Output))
+                .collect(),
+            implementation: Implementation::Builtin(builtin),
+        }
+    }
+
+    pub fn inputs(&self) -> &[Bus] {
+        let n = self.count(BusKind::Input);
+        &self.buses[..n]
+    }
+
+    pub fn outputs(&self) -> &[Bus] {
+        let n = self.count(BusKind::Input);
+        &self.buses[n..n + self.count(BusKind::Output)]
+    }
+
+    fn count(&self, kind: BusKind) -> usize {
+        self.buses.iter().filter(|b| b.kind == kind).count()
+    }
+
+    pub fn input(&self, name: &str) -> Option<usize> {
+        self.inputs().iter().position(|b| b.name == name)
+    }
+
+    pub fn output(&self, name: &str) -> Option<usize> {
+        self.outputs().iter().position(|b| b.name == name)
+    }
+
+    pub fn bus(&self, name: &str) -> Option<usize> {
+        self.buses.iter().position(|b| b.name == name)
+    }
+
+    pub fn parts(&self) -> &[Part] {
+        match &self.implementation {
+            Implementation::Parts(parts) => parts,
+            Implementation::Builtin(_) => &[],
+        }
+    }
+}
+
+// Bits `lo..=hi` as a mask.
+fn mask(lo: u16, hi: u16) -> u32 {
+    ((1u32 << (hi + 1)) - 1) & !((1u32 << lo) - 1)
+}
+
+// Loads chips by name, looking for `Name.hdl` in each directory in turn and falling back
+// to the built-in chips, like the course's hardware simulator does.
+pub struct Library {
+    dirs: Vec<PathBuf>,
+    chips: FxHashMap<String, Rc<ChipDef>>,
+    // Chips being loaded right now, to catch chips which contain themselves.
+    loading: Vec<String>,
+    // Warnings from every chip loaded so far.
+    pub warnings: Vec<Diagnostic>,
+}
+
+impl Library {
+    pub fn new(dirs: Vec<PathBuf>) -> Library {
+        Library {
+            dirs,
+            chips: FxHashMap::default(),
+            loading: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    // Loads the chip in `path`, whose directory is searched before any other for its parts.
+    pub fn load_file(&mut self, path: &Path) -> Result<Rc<ChipDef>, HdlError> {
+        let dir = path.parent().unwrap_or(Path::new(".")).to_path_buf();
+        if !self.dirs.contains(&dir) {
+            self.dirs.insert(0, dir);
+        }
+        let name = path.file_stem().unwrap_or_default().to_string_lossy();
+        if let Some(chip) = self.chips.get(name.as_ref()) {
+            return Ok(chip.clone());
+        }
+        self.load_hdl(path, &name)
+    }
+
+    // Finds a chip by name, None if there's neither an `.hdl` file nor a built-in chip for it.
+    pub fn chip(&mut self, name: &str) -> Result<Option<Rc<ChipDef>>, HdlError> {
+        if let Some(chip) = self.chips.get(name) {
+            return Ok(Some(chip.clone()));
+        }
+        let file = format!("{}.hdl", name);
+        if let Some(path) = self
+            .dirs
+            .iter()
+            .map(|d| d.join(&file))
+            .find(|p| p.is_file())
+        {
+            return self.load_hdl(&path, name).map(Some);
+        }
+        let Some(builtin) = builtin::builtin(name) else {
+            return Ok(None);
+        };
+        let chip = Rc::new(ChipDef::from_builtin(builtin));
+        self.chips.insert(name.to_owned(), chip.clone());
+        Ok(Some(chip))
+    }
+
+    fn load_hdl(&mut self, path: &Path, name: &str) -> Result<Rc<ChipDef>, HdlError> {
+        let file = path.display().to_string();
+        let src = std::fs::read_to_string(path).map_err(|_| {
+            HdlError::from(
+                Diagnostic::new(
+                    ErrorKind::ChipNotFound,
+                    name,
+                    Span {
+                        start: 0,
+                        end: name.len(),
+                    },
+                )
+                .with_note(format!("couldn't read {}", file)),
+            )
+        })?;
+        let chip = parser::parse(&src).map_err(|e| e.with_file(&file))?;
+        if chip.name.name != name {
+            let d = Diagnostic::new(ErrorKind::NameMismatch, &src, chip.name.span);
+            return Err(HdlError::from(d).with_file(&file));
+        }
+
+        self.loading.push(name.to_owned());
+        let elaborated = self.elaborate(&chip, &src, path);
+        self.loading.pop();
+        let (chip, warnings) = elaborated.map_err(|e| e.with_file(&file))?;
+        self.warnings.extend(
+            HdlError {
+                diagnostics: warnings,
+            }
+            .with_file(&file)
+            .diagnostics,
+        );
+        let chip = Rc::new(chip);
+        self.chips.insert(name.to_owned(), chip.clone());
+        Ok(chip)
+    }
+
+    // Resolves every name in the chip and checks the widths of all connections.
+    fn elaborate(
+        &mut self,
+        chip: &ast::Chip,
+        src: &str,
+        path: &Path,
+    ) -> Result<(ChipDef, Vec<Diagnostic>), HdlError> {
+        let mut errors = Vec::new();
+        let mut warnings = Vec::new();
+        let error = |kind, span| Diagnostic::new(kind, src, span);
+
+        let mut buses: Vec<Bus> = Vec::new();
+        let mut decl_spans = Vec::new();
+        let declared = chip
+            .inputs
+            .iter()
+            .map(|p| (p, BusKind::Input))
+            .chain(chip.outputs.iter().map(|p| (p, BusKind::Output)));
+        for (pin, kind) in declared {
+            if buses.iter().any(|b| b.name == pin.name.name) {
+                errors.push(error(ErrorKind::DuplicatePin, pin.name.span));
+                continue;
+            }
+            buses.push(Bus {
+                name: pin.name.name.clone(),
+                width: pin.width,
+                kind,
+            });
+            decl_spans.push(pin.name.span);
+        }
+
+        let parts = match &chip.body {
+            Body::Builtin { name, .. } => {
+                let Some(builtin) = builtin::builtin(&name.name) else {
+                    errors.push(error(ErrorKind::UnknownBuiltin, name.span));
+                    return Err(HdlError {
+                        diagnostics: errors,
+                    });
+                };
+                let mut def = ChipDef::from_builtin(builtin);
+                def.name = chip.name.name.clone();
+                def.file = Some(path.to_path_buf());
+                return if errors.is_empty() {
+                    Ok((def, warnings))
+                } else {
+                    Err(HdlError {
+                        diagnostics: errors,
+                    })
+                };
+            }
+            Body::Parts(parts) => parts,
+        };
+
+        // The parts' chips, None for the ones which couldn't be loaded.
+        let mut chips = Vec::new();
+        for part in parts {
+            let name = &part.chip.name;
+            if self.loading.contains(name) {
+                let note = format!("{} -> {}", self.loading.join(" -> "), name);
+                errors.push(error(ErrorKind::RecursiveChip, part.chip.span).with_note(note));
+                chips.push(None);
+                continue;
+            }
+            match self.chip(name) {
+                Ok(Some(c)) => chips.push(Some(c)),
+                Ok(None) => {
+                    errors.push(error(ErrorKind::ChipNotFound, part.chip.span));
+                    chips.push(None);
+                }
+                Err(e) => {
+                    errors.extend(e.diagnostics);
+                    chips.push(None);
+                }
+            }
+        }
+
+        // The bits of every bus some part drives, and which of them are read.
+        let mut driven: Vec<u32> = vec![0; buses.len()];
+        let mut read: Vec<bool> = vec![false; buses.len()];
+        let mut net_parts: Vec<Part> = Vec::new();
+
+        // The bits of a pin a connection refers to, given the width of the whole pin.
+        let pin_range = |pin: &PinRef, width: u16| -> Result<(u16, u16), Box<Diagnostic>> {
+            match pin.range {
+                Some((lo, hi)) if lo > hi => {
+                    let note = format!("write it as `{}[{}..{}]`", pin.name.name, hi, lo);
+                    Err(Box::new(
+                        error(ErrorKind::ReversedRange, pin.span).with_note(note),
+                    ))
+                }
+                Some((_, hi)) if hi >= width => {
+                    let note = format!(
+                        "`{}` is {} bit{} wide",
+                        pin.name.name,
+                        width,
+                        if width == 1 { "" } else { "s" }
+                    );
+                    Err(Box::new(
+                        error(ErrorKind::IndexOutOfRange, pin.span).with_note(note),
+                    ))
+                }
+                Some(range) => Ok(range),
+                None => Ok((0, width - 1)),
+            }
+        };
+        let mismatch = |pin: &PinRef, wire: &PinRef, pin_width: u16, wire_width: u16| {
+            error(ErrorKind::WidthMismatch, pin.span.to(wire.span)).with_note(format!(
+                "`{}` is {} bit{} wide, `{}` is {}",
+                &src[pin.span.start..pin.span.end],
+                pin_width,
+                if pin_width == 1 { "" } else { "s" },
+                &src[wire.span.start..wire.span.end],
+                wire_width
+            ))
+        };
+
+        // Outputs first, since they're what defines the internal pins, which may be used
+        // by parts listed before the one driving them.
+        for (part, chip_def) in parts.iter().zip(&chips) {
+            let mut net_part = Part {
+                chip: match chip_def {
+                    Some(c) => c.clone(),
+                    None => Rc::new(ChipDef::from_builtin(builtin::builtin("Nand").unwrap())),
+                },
+                inputs: Vec::new(),
+                outputs: Vec::new(),
+                span: part.span,
+            };
+            let Some(chip_def) = chip_def else {
+                net_parts.push(net_part);
+                continue;
+            };
+            for connection in &part.connections {
+                let pin = &connection.pin;
+                let Some(out) = chip_def.output(&pin.name.name) else {
+                    if chip_def.input(&pin.name.name).is_none() {
+                        errors.push(
+                            error(ErrorKind::UnknownPin, pin.name.span)
+                                .with_note(format!("see the definition of `{}`", chip_def.name)),
+                        );
+                    }
+                    continue;
+                };
+                let (lo, hi) = match pin_range(pin, chip_def.outputs()[out].width) {
+                    Ok(range) => range,
+                    Err(d) => {
+                        errors.push(*d);
+                        continue;
+                    }
+                };
+                let width = hi - lo + 1;
+                let wire = match &connection.wire {
+                    Wire::Const(_, span) => {
+                        errors.push(error(ErrorKind::ConstantOutput, *span));
+                        continue;
+                    }
+                    Wire::Pin(wire) => wire,
+                };
+
+                let bus = match buses.iter().position(|b| b.name == wire.name.name) {
+                    Some(bus) => bus,
+                    None => {
+                        if wire.range.is_some() {
+                            errors.push(error(ErrorKind::SubBusOfInternalPin, wire.span));
+                            continue;
+                        }
+                        buses.push(Bus {
+                            name: wire.name.name.clone(),
+                            width,
+                            kind: BusKind::Internal,
+                        });
+                        decl_spans.push(wire.name.span);
+                        driven.push(0);
+                        read.push(false);
+                        buses.len() - 1
+                    }
+                };
+                let (bus_lo, bus_hi) = match buses[bus].kind {
+                    BusKind::Input => {
+                        errors.push(error(ErrorKind::DrivenInput, wire.span));
+                        continue;
+                    }
+                    BusKind::Internal if wire.range.is_some() => {
+                        errors.push(error(ErrorKind::SubBusOfInternalPin, wire.span));
+                        continue;
+                    }
+                    _ => match pin_range(wire, buses[bus].width) {
+                        Ok(range) => range,
+                        Err(d) => {
+                            errors.push(*d);
+                            continue;
+                        }
+                    },
+                };
+                if bus_hi - bus_lo + 1 != width {
+                    errors.push(mismatch(pin, wire, width, bus_hi - bus_lo + 1));
+                    continue;
+                }
+                let bits = mask(bus_lo, bus_hi);
+                if driven[bus] & bits != 0 {
+                    errors.push(error(ErrorKind::MultipleDrivers, wire.span));
+                    continue;
+                }
+                driven[bus] |= bits;
+                net_part.outputs.push(PartOutput {
+                    pin: out,
+                    lo,
+                    hi,
+                    bus,
+                    bus_lo,
+                    bus_hi,
+                });
+            }
+            net_parts.push(net_part);
+        }
+
+        for ((part, chip_def), net_part) in parts.iter().zip(&chips).zip(&mut net_parts) {
+            let Some(chip_def) = chip_def else {
+                continue;
+            };
+            let mut connected = vec![0u32; chip_def.inputs().len()];
+            for connection in &part.connections {
+                let pin = &connection.pin;
+                let Some(input) = chip_def.input(&pin.name.name) else {
+                    continue;
+                };
+                let (lo, hi) = match pin_range(pin, chip_def.inputs()[input].width) {
+                    Ok(range) => range,
+                    Err(d) => {
+                        errors.push(*d);
+                        continue;
+                    }
+                };
+                if connected[input] & mask(lo, hi) != 0 {
+                    errors.push(error(ErrorKind::DuplicateConnection, pin.span));
+                    continue;
+                }
+                connected[input] |= mask(lo, hi);
+
+                let source = match &connection.wire {
+                    Wire::Const(val, _) => Source::Const(*val),
+                    Wire::Pin(wire) => {
+                        let Some(bus) = buses.iter().position(|b| b.name == wire.name.name) else {
+                            errors.push(error(ErrorKind::UndefinedPin, wire.name.span));
+                            continue;
+                        };
+                        let (bus_lo, bus_hi) = match buses[bus].kind {
+                            BusKind::Output => {
+                                errors.push(error(ErrorKind::OutputUsedAsInput, wire.span));
+                                continue;
+                            }
+                            BusKind::Internal if wire.range.is_some() => {
+                                errors.push(error(ErrorKind::SubBusOfInternalPin, wire.span));
+                                continue;
+                            }
+                            _ => match pin_range(wire, buses[bus].width) {
+                                Ok(range) => range,
+                                Err(d) => {
+                                    errors.push(*d);
+                                    continue;
+                                }
+                            },
+                        };
+                        if bus_hi - bus_lo + 1 != hi - lo + 1 {
+                            errors.push(mismatch(pin, wire, hi - lo + 1, bus_hi - bus_lo + 1));
+                            continue;
+                        }
+                        read[bus] = true;
+                        Source::Bus {
+                            bus,
+                            lo: bus_lo,
+                            hi: bus_hi,
+                        }
+                    }
+                };
+                net_part.inputs.push(PartInput {
+                    pin: input,
+                    lo,
+                    hi,
+                    source,
+                });
+            }
+        }
+
+        for (bus, b) in buses.iter().enumerate() {
+            match b.kind {
+                // Parts which couldn't be loaded would only make for more errors here.
+                BusKind::Output if driven[bus] == 0 && chips.iter().all(Option::is_some) => {
+                    errors.push(error(ErrorKind::UnconnectedOutput, decl_spans[bus]));
+                }
+                BusKind::Input | BusKind::Internal if !read[bus] => {
+                    warnings.push(error(ErrorKind::UnusedPin, decl_spans[bus]).warning());
+                }
+                _ => {}
+            }
+        }
+
+        if !errors.is_empty() {
+            // This chip's own errors in source order, followed by the ones from its parts.
+            errors.sort_by_key(|d| match d.file {
+                None => (0, d.line, d.column),
+                Some(_) => (1, 0, 0),
+            });
+            return Err(HdlError {
+                diagnostics: errors,
+            });
+        }
+        Ok((
+            ChipDef {
+                name: chip.name.name.clone(),
+                file: Some(path.to_path_buf()),
+                buses,
+                implementation: Implementation::Parts(net_parts),
+            },
+            warnings,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The errors loading a chip called `T` with the given parts, as (kind, offending text).
+    fn errors(name: &str, body: &str) -> Vec<(ErrorKind, String)> {
+        let dir = std::env::temp_dir().join(format!("hdl-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("T.hdl");
+        std::fs::write(&path, format!("CHIP T {{\n{}\n}}\n", body)).unwrap();
+        let loaded = Library::new(Vec::new()).load_file(&path);
+        std::fs::remove_dir_all(&dir).unwrap();
+        match loaded {
+            Ok(_) => Vec::new(),
+            Err(e) => e
+                .diagnostics
+                .into_iter()
+                .map(|d| (d.kind, d.text))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn width_mismatch() {
+        let body = "IN a[16]; OUT out; PARTS: Not(in=a, out=out);";
+        assert_eq!(
+            errors("width", body),
+            [(ErrorKind::WidthMismatch, "in=a".to_owned())]
+        );
+    }
+
+    #[test]
+    fn undriven_pin() {
+        let body = "IN a; OUT out; PARTS: And(a=a, b=x, out=out);";
+        assert_eq!(
+            errors("undriven", body),
+            [(ErrorKind::UndefinedPin, "x".to_owned())]
+        );
+    }
+
+    #[test]
+    fn bad_slices() {
+        let body = "IN a[4]; OUT out; PARTS: Not(in=a[4], out=out);";
+        assert_eq!(
+            errors("slice", body),
+            [(ErrorKind::IndexOutOfRange, "a[4]".to_owned())]
+        );
+        let body = "IN a[4]; OUT out[2]; PARTS: Not16(in[0..1]=a[3..2], out[0..1]=out);";
+        assert_eq!(
+            errors("reversed", body),
+            [(ErrorKind::ReversedRange, "a[3..2]".to_owned())]
+        );
+    }
+}
@@ -0,0 +1,283 @@
+use crate::ast::{Body, Chip, Connection, Ident, Part, PinDecl, PinRef, Span, Wire};
+use crate::error::{Diagnostic, ErrorKind, HdlError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TokenKind {
+    Ident,
+    Number,
+    // Any of `{ } ( ) [ ] , ; : =` and `..`.
+    Symbol,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Token {
+    kind: TokenKind,
+    span: Span,
+}
+
+// Errors point at a span of the source, turned into a `Diagnostic` once parsing stops.
+type ParseResult<T> = Result<T, (ErrorKind, Span)>;
+
+fn tokenize(src: &str) -> ParseResult<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+    while let Some(c) = src[pos..].chars().next() {
+        let rest = &src[pos..];
+        let (kind, len) = if c.is_whitespace() {
+            (None, c.len_utf8())
+        } else if rest.starts_with("//") {
+            (None, rest.find('\n').unwrap_or(rest.len()))
+        } else if rest.starts_with("/*") {
+            let Some(end) = rest.find("*/") else {
+                let span = Span {
+                    start: pos,
+                    end: pos + 2,
+                };
+                return Err((ErrorKind::UnterminatedComment, span));
+            };
+            (None, end + 2)
+        } else if rest.starts_with("..") {
+            (Some(TokenKind::Symbol), 2)
+        } else if "{}()[],;:=".contains(c) {
+            (Some(TokenKind::Symbol), 1)
+        } else if c.is_ascii_digit() {
+            let len = rest
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len());
+            (Some(TokenKind::Number), len)
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let len = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '.'))
+                .unwrap_or(rest.len());
+            (Some(TokenKind::Ident), len)
+        } else {
+            let span = Span {
+                start: pos,
+                end: pos + c.len_utf8(),
+            };
+            return Err((ErrorKind::InvalidCharacter, span));
+        };
+        if let Some(kind) = kind {
+            tokens.push(Token {
+                kind,
+                span: Span {
+                    start: pos,
+                    end: pos + len,
+                },
+            });
+        }
+        pos += len;
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    src: &'a str,
+    tokens: Vec<Token>,
+    idx: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn text(&self, token: Token) -> &'a str {
+        &self.src[token.span.start..token.span.end]
+    }
+
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.get(self.idx).map(|&t| self.text(t))
+    }
+
+    // The span of the next token, or an empty one at the end of the file.
+    fn next_span(&self) -> Span {
+        match self.tokens.get(self.idx) {
+            Some(t) => t.span,
+            None => Span {
+                start: self.src.len(),
+                end: self.src.len(),
+            },
+        }
+    }
+
+    fn error(&self, what: &'static str) -> (ErrorKind, Span) {
+        (ErrorKind::Expected(what), self.next_span())
+    }
+
+    fn eat(&mut self, symbol: &str) -> bool {
+        let matches = self.peek() == Some(symbol);
+        if matches {
+            self.idx += 1;
+        }
+        matches
+    }
+
+    fn expect(&mut self, symbol: &'static str) -> ParseResult<Span> {
+        let span = self.next_span();
+        if self.eat(symbol) {
+            Ok(span)
+        } else {
+            Err((ErrorKind::ExpectedToken(symbol), span))
+        }
+    }
+
+    fn ident(&mut self, what: &'static str) -> ParseResult<Ident> {
+        match self.tokens.get(self.idx) {
+            Some(&t) if t.kind == TokenKind::Ident => {
+                self.idx += 1;
+                Ok(Ident {
+                    name: self.text(t).to_owned(),
+                    span: t.span,
+                })
+            }
+            _ => Err(self.error(what)),
+        }
+    }
+
+    fn number(&mut self) -> ParseResult<(u16, Span)> {
+        match self.tokens.get(self.idx) {
+            Some(&t) if t.kind == TokenKind::Number => {
+                self.idx += 1;
+                let n = self
+                    .text(t)
+                    .parse()
+                    .map_err(|_| (ErrorKind::InvalidWidth, t.span))?;
+                Ok((n, t.span))
+            }
+            _ => Err(self.error("a number")),
+        }
+    }
+
+    // `a, b[16], c;`
+    fn pin_decls(&mut self) -> ParseResult<Vec<PinDecl>> {
+        let mut pins = Vec::new();
+        if self.eat(";") {
+            return Ok(pins);
+        }
+        loop {
+            let name = self.ident("a pin name")?;
+            let mut width = 1;
+            if self.eat("[") {
+                let (n, span) = self.number()?;
+                if n == 0 || n > 16 {
+                    return Err((ErrorKind::InvalidWidth, span));
+                }
+                width = n;
+                self.expect("]")?;
+            }
+            pins.push(PinDecl { name, width });
+            if self.eat(";") {
+                return Ok(pins);
+            }
+            self.expect(",")?;
+        }
+    }
+
+    fn pin_ref(&mut self) -> ParseResult<PinRef> {
+        let name = self.ident("a pin name")?;
+        let mut span = name.span;
+        let mut range = None;
+        if self.eat("[") {
+            let (lo, _) = self.number()?;
+            let hi = if self.eat("..") { self.number()?.0 } else { lo };
+            span = span.to(self.expect("]")?);
+            range = Some((lo, hi));
+        }
+        Ok(PinRef { name, range, span })
+    }
+
+    fn part(&mut self) -> ParseResult<Part> {
+        let chip = self.ident("a chip name")?;
+        self.expect("(")?;
+        let mut connections = Vec::new();
+        loop {
+            let pin = self.pin_ref()?;
+            self.expect("=")?;
+            let wire = match self.peek() {
+                Some(c @ ("true" | "false")) => {
+                    let span = self.next_span();
+                    self.idx += 1;
+                    Wire::Const(c == "true", span)
+                }
+                _ => Wire::Pin(self.pin_ref()?),
+            };
+            connections.push(Connection { pin, wire });
+            if !self.eat(",") {
+                break;
+            }
+        }
+        self.expect(")")?;
+        let end = self.expect(";")?;
+        Ok(Part {
+            span: chip.span.to(end),
+            chip,
+            connections,
+        })
+    }
+
+    fn chip(&mut self) -> ParseResult<Chip> {
+        self.expect("CHIP")?;
+        let name = self.ident("the chip's name")?;
+        self.expect("{")?;
+        let inputs = if self.eat("IN") {
+            self.pin_decls()?
+        } else {
+            Vec::new()
+        };
+        let outputs = if self.eat("OUT") {
+            self.pin_decls()?
+        } else {
+            Vec::new()
+        };
+
+        let body = if self.eat("BUILTIN") {
+            let name = self.ident("the name of a built-in chip")?;
+            self.expect(";")?;
+            let mut clocked = Vec::new();
+            if self.eat("CLOCKED") {
+                loop {
+                    clocked.push(self.ident("a pin name")?);
+                    if self.eat(";") {
+                        break;
+                    }
+                    self.expect(",")?;
+                }
+            }
+            Body::Builtin { name, clocked }
+        } else {
+            if !self.eat("PARTS") {
+                return Err(self.error("`PARTS:` or `BUILTIN`"));
+            }
+            self.expect(":")?;
+            let mut parts = Vec::new();
+            while self.peek().is_some_and(|t| t != "}") {
+                parts.push(self.part()?);
+            }
+            Body::Parts(parts)
+        };
+        self.expect("}")?;
+        if self.peek().is_some() {
+            return Err(self.error("the end of the file"));
+        }
+        Ok(Chip {
+            name,
+            inputs,
+            outputs,
+            body,
+        })
+    }
+}
+
+// Parses a single `CHIP Name { ... }` definition.
+pub fn parse(src: &str) -> Result<Chip, HdlError> {
+    let to_diagnostic = |(kind, span)| {
+        let d = Diagnostic::new(kind, src, span);
+        HdlError::from(match kind {
+            ErrorKind::InvalidWidth => d.with_note("buses are 1 to 16 bits wide".to_owned()),
+            _ => d,
+        })
+    };
+    let mut parser = Parser {
+        src,
+        tokens: tokenize(src).map_err(to_diagnostic)?,
+        idx: 0,
+    };
+    parser.chip().map_err(to_diagnostic)
+}
@@ -0,0 +1,833 @@
+// Simulates a chip by flattening it down to `Nand` gates and the built-in chips used
+// where no `.hdl` file was found, then propagating changes between them until every
+// pin settles.
+
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+use emulator::cpu::alu;
+use emulator::{KBD, ROM_SIZE, SCREEN};
+
+use crate::builtin::Builtin;
+use crate::netlist::{ChipDef, Implementation, Source};
+
+// Bits `0..width` as a mask.
+fn mask(width: u16) -> u16 {
+    if width >= 16 {
+        0xffff
+    } else {
+        (1 << width) - 1
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Prim {
+    Not,
+    And,
+    Or,
+    Xor,
+    Mux,
+    DMux,
+    Not16,
+    And16,
+    Or16,
+    Mux16,
+    Or8Way,
+    Mux4Way16,
+    Mux8Way16,
+    DMux4Way,
+    DMux8Way,
+    HalfAdder,
+    FullAdder,
+    Add16,
+    Inc16,
+    Alu,
+    Dff,
+    // Bit, Register, ARegister and DRegister.
+    Register,
+    Pc,
+    Ram,
+    Rom,
+    Screen,
+    Keyboard,
+    Memory,
+    Cpu,
+}
+
+impl Prim {
+    fn from_name(name: &str) -> Prim {
+        match name {
+            "Not" => Prim::Not,
+            "And" => Prim::And,
+            "Or" => Prim::Or,
+            "Xor" => Prim::Xor,
+            "Mux" => Prim::Mux,
+            "DMux" => Prim::DMux,
+            "Not16" => Prim::Not16,
+            "And16" => Prim::And16,
+            "Or16" => Prim::Or16,
+            "Mux16" => Prim::Mux16,
+            "Or8Way" => Prim::Or8Way,
+            "Mux4Way16" => Prim::Mux4Way16,
+            "Mux8Way16" => Prim::Mux8Way16,
+            "DMux4Way" => Prim::DMux4Way,
+            "DMux8Way" => Prim::DMux8Way,
+            "HalfAdder" => Prim::HalfAdder,
+            "FullAdder" => Prim::FullAdder,
+            "Add16" => Prim::Add16,
+            "Inc16" => Prim::Inc16,
+            "ALU" => Prim::Alu,
+            "DFF" => Prim::Dff,
+            "Bit" | "Register" | "ARegister" | "DRegister" => Prim::Register,
+            "PC" => Prim::Pc,
+            "RAM8" | "RAM64" | "RAM512" | "RAM4K" | "RAM16K" => Prim::Ram,
+            "ROM32K" => Prim::Rom,
+            "Screen" => Prim::Screen,
+            "Keyboard" => Prim::Keyboard,
+            "Memory" => Prim::Memory,
+            "CPU" => Prim::Cpu,
+            _ => unreachable!("no simulation for built-in chip {}", name),
+        }
+    }
+
+    // The number of words of state the chip keeps between clock cycles.
+    fn state_size(self, inputs: &[u16]) -> usize {
+        match self {
+            Prim::Dff | Prim::Register | Prim::Pc | Prim::Keyboard => 1,
+            // The width of the address pin, which is the last input.
+            Prim::Ram => 1 << inputs[inputs.len() - 1],
+            Prim::Rom => ROM_SIZE,
+            Prim::Screen => KBD as usize - SCREEN as usize,
+            Prim::Memory => KBD as usize + 1,
+            // A, D and PC.
+            Prim::Cpu => 3,
+            _ => 0,
+        }
+    }
+}
+
+// A built-in chip other than `Nand` somewhere inside the simulated chip.
+#[derive(Debug, Clone)]
+struct BuiltinPart {
+    builtin: &'static Builtin,
+    prim: Prim,
+    inputs: Vec<usize>,
+    outputs: Vec<usize>,
+    // Registers and memory contents.
+    state: Vec<u16>,
+    // Writes to `state` decided on the last tick, made on the following tock.
+    pending: Vec<(usize, u16)>,
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    // Copies `width` bits of one net, starting at `from_lo`, into another starting at `to_lo`.
+    Copy {
+        from: usize,
+        from_lo: u16,
+        to: usize,
+        to_lo: u16,
+        width: u16,
+    },
+    Nand {
+        a: usize,
+        b: usize,
+        out: usize,
+    },
+    Builtin(Box<BuiltinPart>),
+}
+
+// The parts keeping state somewhere inside the simulated chip, in the same shape as the
+// chips they're parts of, to find the RAM's words in when it's made of `.hdl` chips.
+#[derive(Debug)]
+enum Instance {
+    // A built-in chip with state, and its node.
+    Node(usize),
+    // A chip made of parts, one instance for each.
+    Chip(Vec<Instance>),
+    Stateless,
+}
+
+pub struct Simulator {
+    chip: Rc<ChipDef>,
+    instance: Instance,
+    // The value of every bus of every part, the top-level chip's buses coming first.
+    nets: Vec<u16>,
+    nodes: Vec<Node>,
+    // The nodes reading each net.
+    readers: Vec<Vec<usize>>,
+    // Built-in parts with a clock.
+    clocked: Vec<usize>,
+    queue: VecDeque<usize>,
+    queued: Vec<bool>,
+    cycles: u64,
+}
+
+impl Simulator {
+    pub fn new(chip: Rc<ChipDef>) -> Simulator {
+        let mut sim = Simulator {
+            chip: chip.clone(),
+            instance: Instance::Stateless,
+            nets: vec![0; chip.buses.len()],
+            nodes: Vec::new(),
+            readers: Vec::new(),
+            clocked: Vec::new(),
+            queue: VecDeque::new(),
+            queued: Vec::new(),
+            cycles: 0,
+        };
+        let nets: Vec<usize> = (0..chip.buses.len()).collect();
+        sim.instance = sim.instantiate(&chip, &nets);
+
+        sim.readers = vec![Vec::new(); sim.nets.len()];
+        for (i, node) in sim.nodes.iter().enumerate() {
+            let inputs = match node {
+                Node::Copy { from, .. } => vec![*from],
+                Node::Nand { a, b, .. } => vec![*a, *b],
+                Node::Builtin(part) => part.inputs.clone(),
+            };
+            for net in inputs {
+                sim.readers[net].push(i);
+            }
+            if let Node::Builtin(part) = node {
+                if !part.builtin.clocked.is_empty() {
+                    sim.clocked.push(i);
+                }
+            }
+        }
+        // Everything has to be evaluated once to get the initial outputs.
+        sim.queued = vec![true; sim.nodes.len()];
+        sim.queue = (0..sim.nodes.len()).collect();
+        sim
+    }
+
+    // Adds the nodes making up `chip`, whose buses are the given nets.
+    fn instantiate(&mut self, chip: &ChipDef, nets: &[usize]) -> Instance {
+        if let Implementation::Builtin(builtin) = &chip.implementation {
+            let inputs = chip.inputs().len();
+            if builtin.name == "Nand" {
+                self.nodes.push(Node::Nand {
+                    a: nets[0],
+                    b: nets[1],
+                    out: nets[2],
+                });
+                return Instance::Stateless;
+            }
+            let prim = Prim::from_name(builtin.name);
+            let widths: Vec<u16> = chip.inputs().iter().map(|b| b.width).collect();
+            let state_size = prim.state_size(&widths);
+            self.nodes.push(Node::Builtin(Box::new(BuiltinPart {
+                builtin,
+                prim,
+                inputs: nets[..inputs].to_vec(),
+                outputs: nets[inputs..].to_vec(),
+                state: vec![0; state_size],
+                pending: Vec::new(),
+            })));
+            return match state_size {
+                0 => Instance::Stateless,
+                _ => Instance::Node(self.nodes.len() - 1),
+            };
+        }
+
+        let mut children = Vec::new();
+        for part in chip.parts() {
+            let sub = &part.chip;
+            let mut sub_nets: Vec<Option<usize>> = vec![None; sub.buses.len()];
+
+            // A pin connected to a whole bus of the same width can share its net,
+            // saving a copy.
+            for input in &part.inputs {
+                let width = sub.buses[input.pin].width;
+                if let Source::Bus { bus, lo, hi } = input.source {
+                    if input.lo == 0
+                        && input.hi + 1 == width
+                        && lo == 0
+                        && hi + 1 == chip.buses[bus].width
+                    {
+                        sub_nets[input.pin] = Some(nets[bus]);
+                    }
+                }
+            }
+            let first_output = sub.inputs().len();
+            for output in &part.outputs {
+                let pin = first_output + output.pin;
+                if sub_nets[pin].is_none()
+                    && output.lo == 0
+                    && output.hi + 1 == sub.buses[pin].width
+                    && output.bus_lo == 0
+                    && output.bus_hi + 1 == chip.buses[output.bus].width
+                {
+                    sub_nets[pin] = Some(nets[output.bus]);
+                }
+            }
+            let sub_nets: Vec<usize> = sub_nets
+                .into_iter()
+                .map(|net| {
+                    net.unwrap_or_else(|| {
+                        self.nets.push(0);
+                        self.nets.len() - 1
+                    })
+                })
+                .collect();
+
+            for input in &part.inputs {
+                let to = sub_nets[input.pin];
+                let width = input.hi - input.lo + 1;
+                match input.source {
+                    Source::Bus { bus, lo, .. } if nets[bus] != to => self.nodes.push(Node::Copy {
+                        from: nets[bus],
+                        from_lo: lo,
+                        to,
+                        to_lo: input.lo,
+                        width,
+                    }),
+                    Source::Bus { .. } => {}
+                    // Nothing else drives these bits, so they can be set once and for all.
+                    Source::Const(true) => self.nets[to] |= mask(width) << input.lo,
+                    Source::Const(false) => {}
+                }
+            }
+            for output in &part.outputs {
+                let from = sub_nets[first_output + output.pin];
+                if from != nets[output.bus] {
+                    self.nodes.push(Node::Copy {
+                        from,
+                        from_lo: output.lo,
+                        to: nets[output.bus],
+                        to_lo: output.bus_lo,
+                        width: output.hi - output.lo + 1,
+                    });
+                }
+            }
+            children.push(self.instantiate(sub, &sub_nets));
+        }
+        // Most chips keep no state, and most of a RAM is made of them.
+        if children.iter().all(|c| matches!(c, Instance::Stateless)) {
+            Instance::Stateless
+        } else {
+            Instance::Chip(children)
+        }
+    }
+
+    pub fn chip(&self) -> &ChipDef {
+        &self.chip
+    }
+
+    // The number of full clock cycles simulated so far.
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    // The value of one of the chip's pins, including the internal ones.
+    pub fn get(&self, pin: &str) -> Option<u16> {
+        self.chip.bus(pin).map(|bus| self.nets[bus])
+    }
+
+    // Sets one of the chip's inputs, which takes effect on the next `eval`.
+    pub fn set(&mut self, pin: &str, value: u16) -> Result<(), String> {
+        let bus = self
+            .chip
+            .input(pin)
+            .ok_or_else(|| format!("chip {} has no input pin {}", self.chip.name, pin))?;
+        self.write(bus, value & mask(self.chip.buses[bus].width));
+        Ok(())
+    }
+
+    fn write(&mut self, net: usize, value: u16) {
+        if self.nets[net] == value {
+            return;
+        }
+        self.nets[net] = value;
+        for &node in &self.readers[net] {
+            if !self.queued[node] {
+                self.queued[node] = true;
+                self.queue.push_back(node);
+            }
+        }
+    }
+
+    fn schedule(&mut self, node: usize) {
+        if !self.queued[node] {
+            self.queued[node] = true;
+            self.queue.push_back(node);
+        }
+    }
+
+    // Propagates changed inputs until every pin settles.
+    pub fn eval(&mut self) -> Result<(), String> {
+        // Far more evaluations than any chip without a combinational loop needs.
+        let mut budget = 1000 + 100 * self.nodes.len() as u64;
+        while let Some(node) = self.queue.pop_front() {
+            self.queued[node] = false;
+            if budget == 0 {
+                self.queue.clear();
+                self.queued.iter_mut().for_each(|q| *q = false);
+                return Err(format!(
+                    "chip {} doesn't settle, it probably has a combinational loop",
+                    self.chip.name
+                ));
+            }
+            budget -= 1;
+            match &self.nodes[node] {
+                &Node::Copy {
+                    from,
+                    from_lo,
+                    to,
+                    to_lo,
+                    width,
+                } => {
+                    let bits = (self.nets[from] >> from_lo) & mask(width);
+                    let value = self.nets[to] & !(mask(width) << to_lo) | bits << to_lo;
+                    self.write(to, value);
+                }
+                &Node::Nand { a, b, out } => {
+                    let value = !(self.nets[a] & self.nets[b]) & 1;
+                    self.write(out, value);
+                }
+                Node::Builtin(part) => {
+                    let inputs: Vec<u16> = part.inputs.iter().map(|&n| self.nets[n]).collect();
+                    let mut outputs = [0; 8];
+                    combinational(part.prim, &inputs, &part.state, &mut outputs);
+                    let nets = part.outputs.clone();
+                    for (net, value) in nets.into_iter().zip(outputs) {
+                        self.write(net, value);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // The first half of a clock cycle: clocked chips sample their inputs.
+    pub fn tick(&mut self) -> Result<(), String> {
+        self.eval()?;
+        for &node in &self.clocked {
+            let Node::Builtin(part) = &mut self.nodes[node] else {
+                continue;
+            };
+            let inputs: Vec<u16> = part.inputs.iter().map(|&n| self.nets[n]).collect();
+            part.pending = clock(part.prim, &inputs, &part.state);
+        }
+        Ok(())
+    }
+
+    // The second half of a clock cycle: clocked chips update their outputs.
+    pub fn tock(&mut self) -> Result<(), String> {
+        for i in 0..self.clocked.len() {
+            let node = self.clocked[i];
+            let Node::Builtin(part) = &mut self.nodes[node] else {
+                continue;
+            };
+            if part.pending.is_empty() {
+                continue;
+            }
+            for (addr, value) in std::mem::take(&mut part.pending) {
+                part.state[addr] = value;
+            }
+            self.schedule(node);
+        }
+        self.cycles += 1;
+        self.eval()
+    }
+
+    // A whole clock cycle.
+    pub fn step(&mut self) -> Result<(), String> {
+        self.tick()?;
+        self.tock()
+    }
+
+    fn parts(&self, prim: Prim) -> impl Iterator<Item = usize> + '_ {
+        self.nodes
+            .iter()
+            .enumerate()
+            .filter_map(move |(i, node)| match node {
+                Node::Builtin(part) if part.prim == prim => Some(i),
+                _ => None,
+            })
+    }
+
+    fn state_mut(&mut self, node: usize) -> &mut Vec<u16> {
+        self.schedule(node);
+        match &mut self.nodes[node] {
+            Node::Builtin(part) => &mut part.state,
+            _ => unreachable!(),
+        }
+    }
+
+    // Loads `program` into every ROM32K in the chip, false if there's none.
+    pub fn load_rom(&mut self, program: &[u16]) -> bool {
+        let roms: Vec<usize> = self.parts(Prim::Rom).collect();
+        for &rom in &roms {
+            let state = self.state_mut(rom);
+            state.fill(0);
+            let len = program.len().min(ROM_SIZE);
+            state[..len].copy_from_slice(&program[..len]);
+        }
+        !roms.is_empty()
+    }
+
+    // Where the computer's data memory word at `addr` lives, as the node, word and bit of
+    // state holding each of its bits. The word is either in a built-in Memory chip, or in
+    // the RAM16K, Screen and Keyboard chips making up the Memory.hdl one.
+    fn data_memory(&self, addr: u16) -> Option<Vec<(usize, usize, u16)>> {
+        let word = |node: usize, i: usize| (0..16).map(|bit| (node, i, bit)).collect();
+        if let Some(memory) = self.parts(Prim::Memory).next() {
+            return (addr <= KBD).then(|| word(memory, addr as usize));
+        }
+        if addr < SCREEN {
+            let ram = self.parts(Prim::Ram).find(|&i| match &self.nodes[i] {
+                Node::Builtin(part) => part.state.len() == SCREEN as usize,
+                _ => false,
+            });
+            if let Some(ram) = ram {
+                return Some(word(ram, addr as usize));
+            }
+            let (ram, instance) = find_chip(&self.chip, &self.instance, "RAM16K")?;
+            let out = ram.bus("out")?;
+            (0..16)
+                .map(|bit| self.trace(ram, instance, out, bit, addr))
+                .collect()
+        } else if addr < KBD {
+            Some(word(
+                self.parts(Prim::Screen).next()?,
+                (addr - SCREEN) as usize,
+            ))
+        } else if addr == KBD {
+            Some(word(self.parts(Prim::Keyboard).next()?, 0))
+        } else {
+            None
+        }
+    }
+
+    // Finds the state holding bit `bit` of `bus` in a RAM chip made of parts, when its
+    // address input is `addr`. It's followed back through the Mux chips picking the
+    // output of one part by bits of the address, the way the course builds RAM, down
+    // to the built-in chips keeping state. None if the chip's built differently.
+    fn trace(
+        &self,
+        chip: &ChipDef,
+        instance: &Instance,
+        bus: usize,
+        bit: u16,
+        addr: u16,
+    ) -> Option<(usize, usize, u16)> {
+        let Instance::Chip(children) = instance else {
+            return None;
+        };
+        let address = chip.input("address");
+        for (part, child) in chip.parts().iter().zip(children) {
+            let sub = &part.chip;
+            let Some(output) = part
+                .outputs
+                .iter()
+                .find(|o| o.bus == bus && o.bus_lo <= bit && bit <= o.bus_hi)
+            else {
+                continue;
+            };
+            let pin_bit = output.lo + bit - output.bus_lo;
+            // The value of one of the part's inputs, when it's made of bits of the address.
+            let input = |name: &str| {
+                let pin = sub.input(name)?;
+                let mut value = 0;
+                for input in part.inputs.iter().filter(|i| i.pin == pin) {
+                    let width = input.hi - input.lo + 1;
+                    match input.source {
+                        Source::Bus { bus, lo, .. } if Some(bus) == address => {
+                            value |= (addr >> lo & mask(width)) << input.lo
+                        }
+                        Source::Const(true) => value |= mask(width) << input.lo,
+                        Source::Const(false) => {}
+                        Source::Bus { .. } => return None,
+                    }
+                }
+                Some(value)
+            };
+
+            if sub.input("sel").is_some() {
+                // Inputs `a`, `b`, `c` and so on, picked by `sel`.
+                let sel = input("sel")?;
+                let pin = sub.input(&char::from(b'a' + sel as u8).to_string())?;
+                let from = part
+                    .inputs
+                    .iter()
+                    .find(|i| i.pin == pin && i.lo <= pin_bit && pin_bit <= i.hi)?;
+                let Source::Bus { bus, lo, .. } = from.source else {
+                    return None;
+                };
+                return self.trace(chip, instance, bus, lo + pin_bit - from.lo, addr);
+            }
+            return match child {
+                Instance::Node(node) => match &self.nodes[*node] {
+                    Node::Builtin(builtin) if builtin.prim == Prim::Ram => {
+                        Some((*node, input("address")? as usize, pin_bit))
+                    }
+                    Node::Builtin(builtin)
+                        if matches!(builtin.prim, Prim::Register | Prim::Dff) =>
+                    {
+                        Some((*node, 0, pin_bit))
+                    }
+                    _ => None,
+                },
+                Instance::Chip(_) => {
+                    let sub_addr = match sub.input("address") {
+                        Some(_) => input("address")?,
+                        None => 0,
+                    };
+                    let out = sub.inputs().len() + output.pin;
+                    self.trace(sub, child, out, pin_bit, sub_addr)
+                }
+                Instance::Stateless => None,
+            };
+        }
+        None
+    }
+
+    // A word of the computer's data memory, None if the chip doesn't contain it.
+    pub fn memory(&self, addr: u16) -> Option<u16> {
+        let bits = self.data_memory(addr)?;
+        let mut value = 0;
+        for (i, (node, word, bit)) in bits.into_iter().enumerate() {
+            let Node::Builtin(part) = &self.nodes[node] else {
+                return None;
+            };
+            value |= (part.state[word] >> bit & 1) << i;
+        }
+        Some(value)
+    }
+
+    // Sets a word of data memory, taking effect on the next `eval`.
+    pub fn set_memory(&mut self, addr: u16, value: u16) -> bool {
+        let Some(bits) = self.data_memory(addr) else {
+            return false;
+        };
+        for (i, (node, word, bit)) in bits.into_iter().enumerate() {
+            let state = &mut self.state_mut(node)[word];
+            *state = *state & !(1 << bit) | (value >> i & 1) << bit;
+        }
+        true
+    }
+
+    // Like pressing a key, 0 meaning no key.
+    pub fn set_key(&mut self, key: u16) -> bool {
+        self.set_memory(KBD, key)
+    }
+}
+
+// The chip called `name` made of parts somewhere inside `chip`, and its instance.
+fn find_chip<'a>(
+    chip: &'a ChipDef,
+    instance: &'a Instance,
+    name: &str,
+) -> Option<(&'a ChipDef, &'a Instance)> {
+    let Instance::Chip(children) = instance else {
+        return None;
+    };
+    if chip.name == name {
+        return Some((chip, instance));
+    }
+    chip.parts()
+        .iter()
+        .zip(children)
+        .find_map(|(part, child)| find_chip(&part.chip, child, name))
+}
+
+// The outputs of a built-in chip without any state, which serves as the reference every
+// `.hdl` implementation of the chip is checked against. None for chips with state.
+pub fn eval_builtin(builtin: &Builtin, inputs: &[u16]) -> Option<Vec<u16>> {
+    if builtin.name == "Nand" {
+        return Some(vec![!(inputs[0] & inputs[1]) & 1]);
+    }
+    let prim = Prim::from_name(builtin.name);
+    let widths: Vec<u16> = builtin.inputs.iter().map(|&(_, width)| width).collect();
+    if prim.state_size(&widths) != 0 {
+        return None;
+    }
+    let mut outputs = [0; 8];
+    combinational(prim, inputs, &[], &mut outputs);
+    Some(outputs[..builtin.outputs.len()].to_vec())
+}
+
+// The outputs of a built-in chip given its inputs and the state it has kept since the
+// last clock cycle.
+fn combinational(prim: Prim, i: &[u16], state: &[u16], out: &mut [u16]) {
+    match prim {
+        Prim::Not => out[0] = !i[0] & 1,
+        Prim::And => out[0] = i[0] & i[1],
+        Prim::Or => out[0] = i[0] | i[1],
+        Prim::Xor => out[0] = i[0] ^ i[1],
+        Prim::Mux | Prim::Mux16 => out[0] = if i[2] == 0 { i[0] } else { i[1] },
+        Prim::DMux => {
+            out[0] = if i[1] == 0 { i[0] } else { 0 };
+            out[1] = if i[1] == 0 { 0 } else { i[0] };
+        }
+        Prim::Not16 => out[0] = !i[0],
+        Prim::And16 => out[0] = i[0] & i[1],
+        Prim::Or16 => out[0] = i[0] | i[1],
+        Prim::Or8Way => out[0] = (i[0] != 0) as u16,
+        Prim::Mux4Way16 => out[0] = i[i[4] as usize],
+        Prim::Mux8Way16 => out[0] = i[i[8] as usize],
+        Prim::DMux4Way | Prim::DMux8Way => {
+            let ways = if prim == Prim::DMux4Way { 4 } else { 8 };
+            out[..ways].fill(0);
+            out[i[1] as usize] = i[0];
+        }
+        Prim::HalfAdder => {
+            out[0] = i[0] ^ i[1];
+            out[1] = i[0] & i[1];
+        }
+        Prim::FullAdder => {
+            let sum = i[0] + i[1] + i[2];
+            out[0] = sum & 1;
+            out[1] = sum >> 1;
+        }
+        Prim::Add16 => out[0] = i[0].wrapping_add(i[1]),
+        Prim::Inc16 => out[0] = i[0].wrapping_add(1),
+        Prim::Alu => {
+            let control = i[2..8].iter().fold(0, |c, &bit| c << 1 | bit as u8);
+            let (value, zr, ng) = alu(i[0], i[1], control);
+            out[0] = value;
+            out[1] = zr as u16;
+            out[2] = ng as u16;
+        }
+        Prim::Dff | Prim::Register | Prim::Pc | Prim::Keyboard => out[0] = state[0],
+        // The address is the last input.
+        Prim::Ram | Prim::Rom | Prim::Screen => out[0] = state[i[i.len() - 1] as usize],
+        Prim::Memory => out[0] = state.get(i[2] as usize).copied().unwrap_or(0),
+        Prim::Cpu => {
+            let (inm, instruction) = (i[0], i[1]);
+            let [a, d, pc] = [state[0], state[1], state[2]];
+            if instruction & 0x8000 != 0 {
+                let y = if instruction & 0x1000 != 0 { inm } else { a };
+                out[0] = alu(d, y, (instruction >> 6) as u8 & 0x3f).0;
+                out[1] = (instruction & 0b001_000 != 0) as u16;
+            } else {
+                out[0] = 0;
+                out[1] = 0;
+            }
+            out[2] = a & 0x7fff;
+            out[3] = pc & 0x7fff;
+        }
+    }
+}
+
+// The writes to a clocked chip's state which the next tock makes, given its inputs at the tick.
+fn clock(prim: Prim, i: &[u16], state: &[u16]) -> Vec<(usize, u16)> {
+    match prim {
+        Prim::Dff => vec![(0, i[0])],
+        // `in`, `load` and `address` for all of these.
+        Prim::Register | Prim::Ram | Prim::Screen if i[1] != 0 => {
+            vec![(i.get(2).copied().unwrap_or(0) as usize, i[0])]
+        }
+        Prim::Memory if i[1] != 0 && i[2] < KBD => vec![(i[2] as usize, i[0])],
+        Prim::Pc => {
+            let next = if i[3] != 0 {
+                0
+            } else if i[1] != 0 {
+                i[0]
+            } else if i[2] != 0 {
+                state[0].wrapping_add(1)
+            } else {
+                state[0]
+            };
+            vec![(0, next)]
+        }
+        Prim::Cpu => {
+            let (inm, instruction, reset) = (i[0], i[1], i[2]);
+            let [a, d, pc] = [state[0], state[1], state[2]];
+            if instruction & 0x8000 == 0 {
+                let pc = if reset != 0 { 0 } else { pc.wrapping_add(1) };
+                return vec![(0, instruction), (2, pc)];
+            }
+            let y = if instruction & 0x1000 != 0 { inm } else { a };
+            let (out, zr, ng) = alu(d, y, (instruction >> 6) as u8 & 0x3f);
+            let jump = (instruction & 0b100 != 0 && ng)
+                || (instruction & 0b010 != 0 && zr)
+                || (instruction & 0b001 != 0 && !ng && !zr);
+            let pc = if reset != 0 {
+                0
+            } else if jump {
+                a
+            } else {
+                pc.wrapping_add(1)
+            };
+            let a = if instruction & 0b100_000 != 0 { out } else { a };
+            let d = if instruction & 0b010_000 != 0 { out } else { d };
+            vec![(0, a), (1, d), (2, pc)]
+        }
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::*;
+
+    // A chip of the repo, with the chips of the given projects as its parts.
+    fn load(path: &str, projects: &[&str]) -> Simulator {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("../..");
+        let dirs: Vec<PathBuf> = projects.iter().map(|p| root.join(p)).collect();
+        let (chip, _) = crate::load(&root.join(path), &dirs).unwrap();
+        Simulator::new(chip)
+    }
+
+    #[test]
+    fn alu_matches_the_emulator() {
+        let mut sim = load("project2/ALU.hdl", &["project1"]);
+        let pins = ["no", "f", "ny", "zy", "nx", "zx"];
+        for (x, y) in [
+            (0, 0),
+            (17, 3),
+            (0xffff, 1),
+            (0x8000, 0x7fff),
+            (1234, 0xfb2e),
+        ] {
+            for control in 0..64u8 {
+                sim.set("x", x).unwrap();
+                sim.set("y", y).unwrap();
+                for (i, pin) in pins.iter().enumerate() {
+                    sim.set(pin, (control >> i & 1) as u16).unwrap();
+                }
+                sim.eval().unwrap();
+                let (out, zr, ng) = alu(x, y, control);
+                let actual = (sim.get("out"), sim.get("zr"), sim.get("ng"));
+                let expected = (Some(out), Some(zr as u16), Some(ng as u16));
+                assert_eq!(actual, expected, "x = {}, y = {}, {:06b}", x, y, control);
+            }
+        }
+    }
+
+    // Runs project 4's Mult on the computer of project 5, which takes 14 cycles for each
+    // time round the loop, `r0` times.
+    fn run_mult(sim: &mut Simulator, r0: u16, r1: u16) {
+        let mult = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../project4/Mult.asm");
+        assert!(sim.load_rom(&emulator::load_rom(&mult).unwrap()));
+        assert!(sim.set_memory(0, r0));
+        assert!(sim.set_memory(1, r1));
+        for _ in 0..14 * r0 + 10 {
+            sim.step().unwrap();
+        }
+        assert_eq!(sim.memory(0), Some(r0));
+        assert_eq!(sim.memory(1), Some(r1));
+        assert_eq!(sim.memory(2), Some(r0 * r1));
+    }
+
+    #[test]
+    fn computer_runs_mult() {
+        let mut sim = load("project5/Computer.hdl", &[]);
+        run_mult(&mut sim, 6, 7);
+    }
+
+    // Simulating every DFF of the RAM is slow, so the program runs for less time.
+    #[test]
+    fn computer_runs_mult_with_its_own_ram() {
+        let mut sim = load("project5/Computer.hdl", &["project3"]);
+        run_mult(&mut sim, 2, 21);
+        // Words are found the same way deep inside the RAM.
+        assert!(sim.set_memory(12345, 0x5a5a));
+        assert_eq!(sim.memory(12345), Some(0x5a5a));
+        assert_eq!(sim.memory(12344), Some(0));
+    }
+}
@@ -0,0 +1,70 @@
+use std::process::ExitCode;
+
+use assembler::disasm;
+
+const USAGE: &str = r#"Usage:
+    disassembler <file.hack> [options]
+
+Options:
+  -o <file>, --output <file>            Outputs to <file> instead of standard output
+  -l,        --labels                   Replaces jump targets with synthetic labels
+  -h,        --help                     Prints help message
+"#;
+
+fn main() -> ExitCode {
+    let mut args = std::env::args();
+    args.next();
+    let mut input_file = None;
+    let mut output_file = None;
+    let mut labels = false;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" | "--output" => output_file = args.next(),
+            "-l" | "--labels" => labels = true,
+            "-h" | "--help" => {
+                print!("{}", USAGE);
+                return ExitCode::SUCCESS;
+            }
+            _ => input_file = Some(arg),
+        }
+    }
+
+    let Some(input_file) = input_file else {
+        print!("{}", USAGE);
+        return ExitCode::FAILURE;
+    };
+    let src = match std::fs::read_to_string(&input_file) {
+        Ok(src) => src,
+        Err(_) => {
+            eprintln!("Couldn't open file: {}", input_file);
+            return ExitCode::FAILURE;
+        }
+    };
+    let words = match disasm::parse_hack(&src) {
+        Ok(words) => words,
+        Err(line) => {
+            eprintln!("{}:{}: expected a 16-digit binary word", input_file, line);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let (output, invalid) = disasm::disassemble(&words, labels);
+    for (pc, e) in invalid.iter() {
+        eprintln!("warning: ROM[{}]: {}", pc, e);
+    }
+    match output_file {
+        Some(path) => {
+            if std::fs::write(&path, output).is_err() {
+                eprintln!("Couldn't write file: {}", path);
+                return ExitCode::FAILURE;
+            }
+        }
+        None => print!("{}", output),
+    }
+
+    if invalid.is_empty() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
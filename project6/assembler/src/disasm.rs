@@ -0,0 +1,114 @@
+use std::fmt;
+
+use rustc_hash::FxHashMap;
+
+use crate::instruction::{AInstruction, CInstruction, Comp, Dest, Instruction, Jump};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    // Bits 13-14 of a C-instruction are unused, but the spec says they're always 1.
+    UnsetPaddingBits,
+    UnknownComp(u16),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::UnsetPaddingBits => write!(f, "C-instruction with bits 13-14 not set"),
+            DecodeError::UnknownComp(bits) => write!(f, "unused comp code {:07b}", bits),
+        }
+    }
+}
+
+pub fn decode(word: u16) -> Result<Instruction, DecodeError> {
+    if word & 0x8000 == 0 {
+        return Ok(Instruction::A(AInstruction::Value(word)));
+    }
+    if word & 0x6000 != 0x6000 {
+        return Err(DecodeError::UnsetPaddingBits);
+    }
+    let comp_bits = (word >> 6) & 0x7f;
+    Ok(Instruction::C(CInstruction {
+        comp: Comp::from_bits(comp_bits).ok_or(DecodeError::UnknownComp(comp_bits))?,
+        dest: Dest::from_bits((word >> 3) & 0b111).unwrap(),
+        jump: Jump::from_bits(word & 0b111).unwrap(),
+    }))
+}
+
+// Reads the textual .hack format, one 16-digit binary word per line.
+// On failure returns the 1-based number of the offending line.
+pub fn parse_hack(src: &str) -> Result<Vec<u16>, usize> {
+    src.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            let line = line.trim();
+            if line.len() != 16 {
+                return Err(i + 1);
+            }
+            u16::from_str_radix(line, 2).map_err(|_| i + 1)
+        })
+        .collect()
+}
+
+// Finds jump targets by looking for `@N` immediately followed by a jumping C-instruction.
+// Returns the ROM addresses which get a synthetic label, mapped to the label's name.
+pub fn jump_targets(words: &[u16]) -> FxHashMap<u16, String> {
+    let mut targets: Vec<u16> = words
+        .windows(2)
+        .filter_map(|w| match (decode(w[0]), decode(w[1])) {
+            (Ok(Instruction::A(AInstruction::Value(addr))), Ok(Instruction::C(c)))
+                if c.jump != Jump::Null && addr as usize <= words.len() =>
+            {
+                Some(addr)
+            }
+            _ => None,
+        })
+        .collect();
+    targets.sort_unstable();
+    targets.dedup();
+    targets
+        .into_iter()
+        .enumerate()
+        .map(|(i, addr)| (addr, format!("L{}", i)))
+        .collect()
+}
+
+// Prints the program back as assembly. Words which don't encode a valid instruction become
+// comments and are returned alongside their ROM address, so the caller can report them.
+pub fn disassemble(words: &[u16], reconstruct_labels: bool) -> (String, Vec<(usize, DecodeError)>) {
+    let labels = if reconstruct_labels {
+        jump_targets(words)
+    } else {
+        FxHashMap::default()
+    };
+    let mut out = String::with_capacity(words.len() * 8);
+    let mut invalid = Vec::new();
+
+    for (pc, &word) in words.iter().enumerate() {
+        if let Some(label) = labels.get(&(pc as u16)) {
+            out.push_str(&format!("({})\n", label));
+        }
+        match decode(word) {
+            Ok(Instruction::A(AInstruction::Value(addr))) => {
+                let jumps = words
+                    .get(pc + 1)
+                    .map(|&next| matches!(decode(next), Ok(Instruction::C(c)) if c.jump != Jump::Null))
+                    .unwrap_or(false);
+                match labels.get(&addr) {
+                    Some(label) if jumps => out.push_str(&format!("@{}\n", label)),
+                    _ => out.push_str(&format!("@{}\n", addr)),
+                }
+            }
+            Ok(instruction) => out.push_str(&format!("{}\n", instruction)),
+            Err(e) => {
+                out.push_str(&format!("// invalid: {:016b} ({})\n", word, e));
+                invalid.push((pc, e));
+            }
+        }
+    }
+    if let Some(label) = labels.get(&(words.len() as u16)) {
+        out.push_str(&format!("({})\n", label));
+    }
+    (out, invalid)
+}
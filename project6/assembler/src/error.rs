@@ -0,0 +1,205 @@
+use std::fmt;
+
+use crate::snippet::{self, Snippet};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    InvalidComp,
+    InvalidDest,
+    InvalidJump,
+    InvalidSymbol,
+    InvalidExpression,
+    ValueOutOfRange,
+    DivisionByZero,
+    MissingSymbol,
+    EmptyLabel,
+    UnterminatedLabel,
+    UnknownDirective,
+    MalformedDirective,
+    Redefinition,
+    UnterminatedMacro,
+    UnexpectedEndm,
+    WrongArgumentCount,
+    RecursiveMacro,
+    IncludeNotFound,
+    IncludeCycle,
+    DuplicateLabel,
+    PredefinedLabel,
+    VariableInIo,
+    RomOverflow,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    // Turned into errors by `--strict`.
+    Warning,
+}
+
+// A single problem in the source, pointing at the offending text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub kind: ErrorKind,
+    pub severity: Severity,
+    pub file: Option<String>,
+    // See `Snippet`.
+    pub line: usize,
+    pub column: usize,
+    pub text: String,
+    pub source_line: String,
+    // Extra context printed below the snippet, e.g. the macro the line was expanded from.
+    pub note: Option<String>,
+}
+
+// Every diagnostic found in a file, in source order. May contain warnings next to the errors.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Diagnostic {
+    // `text` has to be a subslice of `source_line`, see `snippet::column`.
+    pub fn new(kind: ErrorKind, line: usize, source_line: &str, text: &str) -> Diagnostic {
+        Diagnostic {
+            kind,
+            severity: Severity::Error,
+            file: None,
+            line,
+            column: snippet::column(source_line, text),
+            text: text.to_owned(),
+            source_line: source_line.to_owned(),
+            note: None,
+        }
+    }
+
+    pub fn with_note(mut self, note: String) -> Diagnostic {
+        self.note = Some(note);
+        self
+    }
+
+    pub fn warning(mut self) -> Diagnostic {
+        self.severity = Severity::Warning;
+        self
+    }
+
+    pub fn message(&self) -> String {
+        match self.kind {
+            ErrorKind::InvalidComp if self.text.is_empty() => "expected a computation".to_owned(),
+            ErrorKind::InvalidDest if self.text.is_empty() => {
+                "expected a destination before `=`".to_owned()
+            }
+            ErrorKind::InvalidJump if self.text.is_empty() => {
+                "expected a jump after `;`".to_owned()
+            }
+            ErrorKind::InvalidComp => format!("unknown computation `{}`", self.text),
+            ErrorKind::InvalidDest => format!("unknown destination `{}`", self.text),
+            ErrorKind::InvalidJump => format!("unknown jump `{}`", self.text),
+            ErrorKind::InvalidSymbol => format!("invalid symbol `{}`", self.text),
+            ErrorKind::InvalidExpression => format!("invalid expression `{}`", self.text),
+            ErrorKind::ValueOutOfRange => {
+                format!("`{}` doesn't fit in a 15-bit A-instruction", self.text)
+            }
+            ErrorKind::DivisionByZero => format!("division by zero in `{}`", self.text),
+            ErrorKind::MissingSymbol => "expected a value or a symbol after `@`".to_owned(),
+            ErrorKind::EmptyLabel => "empty label".to_owned(),
+            ErrorKind::UnterminatedLabel => "label is missing the closing `)`".to_owned(),
+            ErrorKind::UnknownDirective => format!("unknown directive `{}`", self.text),
+            ErrorKind::MalformedDirective => format!("malformed `{}` directive", self.text),
+            ErrorKind::Redefinition => format!("`{}` is already defined", self.text),
+            ErrorKind::UnterminatedMacro => format!("macro `{}` is missing `.endm`", self.text),
+            ErrorKind::UnexpectedEndm => "`.endm` without a matching `.macro`".to_owned(),
+            ErrorKind::WrongArgumentCount => {
+                format!("wrong number of arguments for macro `{}`", self.text)
+            }
+            ErrorKind::RecursiveMacro => format!("macro `{}` expands to itself", self.text),
+            ErrorKind::IncludeNotFound => format!("couldn't read included file {}", self.text),
+            ErrorKind::IncludeCycle => format!("file {} includes itself", self.text),
+            ErrorKind::DuplicateLabel => format!("label `{}` is defined twice", self.text),
+            ErrorKind::PredefinedLabel => {
+                format!("label `{}` redefines a predefined symbol", self.text)
+            }
+            ErrorKind::VariableInIo => {
+                format!(
+                    "variable `{}` is allocated in the I/O memory map",
+                    self.text
+                )
+            }
+            ErrorKind::RomOverflow => "program doesn't fit in the 32K ROM".to_owned(),
+        }
+    }
+
+    pub fn snippet(&self) -> Snippet<'_> {
+        Snippet {
+            file: self.file.as_deref(),
+            line: self.line,
+            column: self.column,
+            text: &self.text,
+            source_line: &self.source_line,
+        }
+    }
+
+    pub fn render(&self) -> String {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        self.snippet()
+            .render(severity, &self.message(), self.note.as_deref())
+    }
+}
+
+impl AsmError {
+    // Attributes every diagnostic which doesn't know its file yet to `file`.
+    pub fn with_file(mut self, file: &str) -> AsmError {
+        for d in self.diagnostics.iter_mut().filter(|d| d.file.is_none()) {
+            d.file = Some(file.to_owned());
+        }
+        self
+    }
+
+    pub fn render(&self) -> String {
+        self.diagnostics
+            .iter()
+            .map(Diagnostic::render)
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+impl From<Diagnostic> for AsmError {
+    fn from(d: Diagnostic) -> Self {
+        AsmError {
+            diagnostics: vec![d],
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {}{}",
+            self.snippet(),
+            if self.severity == Severity::Warning {
+                "warning: "
+            } else {
+                ""
+            },
+            self.message()
+        )
+    }
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, d) in self.diagnostics.iter().enumerate() {
+            if i != 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", d)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for AsmError {}
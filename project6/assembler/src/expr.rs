@@ -0,0 +1,199 @@
+use std::fmt;
+
+use crate::parser::is_symbol;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    Number(i64),
+    Symbol(String),
+    Neg(Box<Expr>),
+    Binary(Op, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EvalError {
+    DivisionByZero,
+    // Intermediate results are kept within i64, anything past that is surely a mistake.
+    Overflow,
+}
+
+impl Expr {
+    // Symbols are looked up through `resolve`, which may allocate variables for unknown ones.
+    pub fn eval(&self, resolve: &mut impl FnMut(&str) -> i64) -> Result<i64, EvalError> {
+        match self {
+            Expr::Number(n) => Ok(*n),
+            Expr::Symbol(s) => Ok(resolve(s)),
+            Expr::Neg(e) => e.eval(resolve)?.checked_neg().ok_or(EvalError::Overflow),
+            Expr::Binary(op, lhs, rhs) => {
+                let (lhs, rhs) = (lhs.eval(resolve)?, rhs.eval(resolve)?);
+                match op {
+                    Op::Add => lhs.checked_add(rhs).ok_or(EvalError::Overflow),
+                    Op::Sub => lhs.checked_sub(rhs).ok_or(EvalError::Overflow),
+                    Op::Mul => lhs.checked_mul(rhs).ok_or(EvalError::Overflow),
+                    Op::Div if rhs == 0 => Err(EvalError::DivisionByZero),
+                    Op::Div => lhs.checked_div(rhs).ok_or(EvalError::Overflow),
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Number(n) => write!(f, "{}", n),
+            Expr::Symbol(s) => write!(f, "{}", s),
+            Expr::Neg(e) => write!(f, "-({})", e),
+            Expr::Binary(op, lhs, rhs) => {
+                let op = match op {
+                    Op::Add => '+',
+                    Op::Sub => '-',
+                    Op::Mul => '*',
+                    Op::Div => '/',
+                };
+                write!(f, "({}{}{})", lhs, op, rhs)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token<'a> {
+    Number(i64),
+    Symbol(&'a str),
+    Op(char),
+}
+
+// Parses expressions like `SCREEN+32*10`, `-(LOOP-1)`, `0x4000`, `0b1010` or `'A'`.
+// On failure returns the slice of `s` which couldn't be parsed.
+pub fn parse(s: &str) -> Result<Expr, &str> {
+    let tokens = tokenize(s)?;
+    let mut parser = Parser {
+        tokens: &tokens,
+        idx: 0,
+        error: None,
+    };
+    let expr = parser
+        .sum()
+        .ok_or_else(|| parser.error.map(|i| tokens[i].1).unwrap_or(s))?;
+    if parser.idx != tokens.len() {
+        return Err(tokens[parser.idx].1);
+    }
+    Ok(expr)
+}
+
+fn tokenize(s: &str) -> Result<Vec<(Token<'_>, &str)>, &str> {
+    let mut tokens = Vec::new();
+    let mut rest = s.trim_start();
+    while let Some(c) = rest.chars().next() {
+        let len = if "+-*/()".contains(c) {
+            tokens.push((Token::Op(c), &rest[..1]));
+            1
+        } else if c == '\'' {
+            let mut chars = rest[1..].chars();
+            match (chars.next(), chars.next()) {
+                (Some(ch), Some('\'')) => {
+                    let len = ch.len_utf8() + 2;
+                    tokens.push((Token::Number(ch as i64), &rest[..len]));
+                    len
+                }
+                _ => return Err(rest),
+            }
+        } else {
+            let len = rest
+                .find(|c: char| c.is_whitespace() || "+-*/()'".contains(c))
+                .unwrap_or(rest.len());
+            let word = &rest[..len];
+            let token = if word.starts_with(|c: char| c.is_ascii_digit()) {
+                Token::Number(parse_number(word).ok_or(word)?)
+            } else if is_symbol(word) {
+                Token::Symbol(word)
+            } else {
+                return Err(word);
+            };
+            tokens.push((token, word));
+            len
+        };
+        rest = rest[len..].trim_start();
+    }
+    Ok(tokens)
+}
+
+fn parse_number(s: &str) -> Option<i64> {
+    if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        i64::from_str_radix(hex, 16).ok()
+    } else if let Some(bin) = s.strip_prefix("0b").or_else(|| s.strip_prefix("0B")) {
+        i64::from_str_radix(bin, 2).ok()
+    } else {
+        s.parse().ok()
+    }
+}
+
+struct Parser<'t, 'a> {
+    tokens: &'t [(Token<'a>, &'a str)],
+    idx: usize,
+    // Index of the token which made parsing fail, if there was one.
+    error: Option<usize>,
+}
+
+impl Parser<'_, '_> {
+    fn peek_op(&self) -> Option<char> {
+        match self.tokens.get(self.idx) {
+            Some((Token::Op(c), _)) => Some(*c),
+            _ => None,
+        }
+    }
+
+    fn sum(&mut self) -> Option<Expr> {
+        let mut lhs = self.product()?;
+        while let Some(c @ ('+' | '-')) = self.peek_op() {
+            self.idx += 1;
+            let op = if c == '+' { Op::Add } else { Op::Sub };
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(self.product()?));
+        }
+        Some(lhs)
+    }
+
+    fn product(&mut self) -> Option<Expr> {
+        let mut lhs = self.unary()?;
+        while let Some(c @ ('*' | '/')) = self.peek_op() {
+            self.idx += 1;
+            let op = if c == '*' { Op::Mul } else { Op::Div };
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(self.unary()?));
+        }
+        Some(lhs)
+    }
+
+    fn unary(&mut self) -> Option<Expr> {
+        let (token, _) = self.tokens.get(self.idx)?;
+        self.idx += 1;
+        match token {
+            Token::Number(n) => Some(Expr::Number(*n)),
+            Token::Symbol(s) => Some(Expr::Symbol((*s).to_owned())),
+            Token::Op('-') => Some(Expr::Neg(Box::new(self.unary()?))),
+            Token::Op('+') => self.unary(),
+            Token::Op('(') => {
+                let e = self.sum()?;
+                match self.peek_op() {
+                    Some(')') => {
+                        self.idx += 1;
+                        Some(e)
+                    }
+                    _ => None,
+                }
+            }
+            _ => {
+                self.error = Some(self.idx - 1);
+                None
+            }
+        }
+    }
+}
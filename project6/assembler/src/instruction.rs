@@ -0,0 +1,173 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::expr::Expr;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Instruction {
+    A(AInstruction),
+    C(CInstruction),
+    Label(Label),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AInstruction {
+    Value(u16),
+    Symbol(String),
+    // Evaluated in the second pass, once every label is known.
+    Expr(Expr),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CInstruction {
+    pub dest: Dest,
+    pub comp: Comp,
+    pub jump: Jump,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Label(pub String);
+
+impl CInstruction {
+    pub fn encode(&self) -> u16 {
+        0b111 << 13 | self.comp.bits() << 6 | self.dest.bits() << 3 | self.jump.bits()
+    }
+}
+
+impl fmt::Display for AInstruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AInstruction::Value(v) => write!(f, "@{}", v),
+            AInstruction::Symbol(s) => write!(f, "@{}", s),
+            AInstruction::Expr(e) => write!(f, "@{}", e),
+        }
+    }
+}
+
+impl fmt::Display for CInstruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.dest != Dest::Null {
+            write!(f, "{}=", self.dest)?;
+        }
+        write!(f, "{}", self.comp)?;
+        if self.jump != Jump::Null {
+            write!(f, ";{}", self.jump)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Instruction::A(a) => a.fmt(f),
+            Instruction::C(c) => c.fmt(f),
+            Instruction::Label(Label(l)) => write!(f, "({})", l),
+        }
+    }
+}
+
+// Generates an enum together with its mnemonic table, so the encoder and the
+// textual form can't drift apart. The first mnemonic in each row is the
+// canonical one, the rest are accepted aliases.
+macro_rules! mnemonic_enum {
+    ($name:ident { $($variant:ident = $bits:literal => [$($mnemonic:literal),+]),+ $(,)? }) => {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum $name {
+            $($variant),+
+        }
+
+        impl $name {
+            pub const ALL: &'static [$name] = &[$($name::$variant),+];
+
+            pub fn bits(&self) -> u16 {
+                match self {
+                    $($name::$variant => $bits),+
+                }
+            }
+
+            pub fn from_bits(bits: u16) -> Option<$name> {
+                match bits {
+                    $($bits => Some($name::$variant),)+
+                    _ => None,
+                }
+            }
+
+            pub fn mnemonic(&self) -> &'static str {
+                match self {
+                    $($name::$variant => [$($mnemonic),+][0]),+
+                }
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = String;
+            fn from_str(s: &str) -> Result<Self, String> {
+                match s {
+                    $($($mnemonic)|+ => Ok($name::$variant),)+
+                    _ => Err(s.to_owned()),
+                }
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str(self.mnemonic())
+            }
+        }
+    };
+}
+
+// Bits are a-c1..c6, i.e. the a bit is the most significant one.
+mnemonic_enum!(Comp {
+    Zero = 0b0101010 => ["0"],
+    One = 0b0111111 => ["1"],
+    MinusOne = 0b0111010 => ["-1"],
+    D = 0b0001100 => ["D"],
+    A = 0b0110000 => ["A"],
+    M = 0b1110000 => ["M"],
+    NotD = 0b0001101 => ["!D"],
+    NotA = 0b0110001 => ["!A"],
+    NotM = 0b1110001 => ["!M"],
+    NegD = 0b0001111 => ["-D"],
+    NegA = 0b0110011 => ["-A"],
+    NegM = 0b1110011 => ["-M"],
+    DPlusOne = 0b0011111 => ["D+1"],
+    APlusOne = 0b0110111 => ["A+1"],
+    MPlusOne = 0b1110111 => ["M+1"],
+    DMinusOne = 0b0001110 => ["D-1"],
+    AMinusOne = 0b0110010 => ["A-1"],
+    MMinusOne = 0b1110010 => ["M-1"],
+    DPlusA = 0b0000010 => ["D+A", "A+D"],
+    DPlusM = 0b1000010 => ["D+M", "M+D"],
+    DMinusA = 0b0010011 => ["D-A"],
+    DMinusM = 0b1010011 => ["D-M"],
+    AMinusD = 0b0000111 => ["A-D"],
+    MMinusD = 0b1000111 => ["M-D"],
+    DAndA = 0b0000000 => ["D&A", "A&D"],
+    DAndM = 0b1000000 => ["D&M", "M&D"],
+    DOrA = 0b0010101 => ["D|A", "A|D"],
+    DOrM = 0b1010101 => ["D|M", "M|D"],
+});
+
+mnemonic_enum!(Dest {
+    Null = 0b000 => [""],
+    M = 0b001 => ["M"],
+    D = 0b010 => ["D"],
+    MD = 0b011 => ["MD", "DM"],
+    A = 0b100 => ["A"],
+    AM = 0b101 => ["AM", "MA"],
+    AD = 0b110 => ["AD", "DA"],
+    AMD = 0b111 => ["AMD", "ADM", "MAD", "MDA", "DAM", "DMA"],
+});
+
+mnemonic_enum!(Jump {
+    Null = 0b000 => [""],
+    JGT = 0b001 => ["JGT"],
+    JEQ = 0b010 => ["JEQ"],
+    JGE = 0b011 => ["JGE"],
+    JLT = 0b100 => ["JLT"],
+    JNE = 0b101 => ["JNE"],
+    JLE = 0b110 => ["JLE"],
+    JMP = 0b111 => ["JMP"],
+});
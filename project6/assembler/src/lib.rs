@@ -0,0 +1,220 @@
+use std::path::Path;
+
+pub use error::{AsmError, Diagnostic, ErrorKind, Severity};
+use expr::EvalError;
+use instruction::{AInstruction, Instruction};
+use rustc_hash::FxHashMap;
+pub use symbol_table::{SymbolKind, SymbolTable};
+
+pub mod disasm;
+pub mod error;
+pub mod expr;
+pub mod instruction;
+pub mod listing;
+pub mod output;
+pub mod parser;
+pub mod preprocess;
+pub mod snippet;
+pub mod symbol_table;
+
+// An instruction together with the source line it came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Statement {
+    pub instruction: Instruction,
+    pub file: Option<String>,
+    pub line: usize,
+    pub source: String,
+}
+
+impl Statement {
+    // Points a diagnostic at the operand of an A-instruction, the name of a label
+    // or the whole of a C-instruction.
+    fn operand_diagnostic(&self, kind: ErrorKind) -> Diagnostic {
+        let command = parser::strip_whitespace(&self.source);
+        let operand = match command.strip_prefix('(') {
+            Some(label) => label.strip_suffix(')').unwrap_or(label),
+            None => command.strip_prefix('@').unwrap_or(command),
+        }
+        .trim();
+        let mut d = Diagnostic::new(kind, self.line, &self.source, operand);
+        d.file = self.file.clone();
+        d
+    }
+}
+
+pub fn parse(src: &str) -> Result<Vec<Statement>, AsmError> {
+    parse_source(src, None)
+}
+
+// Preprocesses and parses the whole source, collecting every error instead of stopping at
+// the first one. `path` is where `.include`s are resolved from.
+pub fn parse_source(src: &str, path: Option<&Path>) -> Result<Vec<Statement>, AsmError> {
+    let lines = preprocess::preprocess(src, path)?;
+    let mut program = Vec::new();
+    let mut diagnostics = Vec::new();
+    for line in lines {
+        match parser::parse_line(&line.text, line.line) {
+            Ok(Some(instruction)) => program.push(Statement {
+                instruction,
+                file: line.file,
+                line: line.line,
+                source: line.text,
+            }),
+            Ok(None) => {}
+            Err(mut d) => {
+                d.file = line.file;
+                if let Some(m) = line.expanded_from {
+                    d.note = Some(format!("in this expansion of macro `{}`", m));
+                }
+                diagnostics.push(d);
+            }
+        }
+    }
+    if diagnostics.is_empty() {
+        Ok(program)
+    } else {
+        Err(AsmError { diagnostics })
+    }
+}
+
+pub fn assemble(src: &str) -> Result<Vec<u16>, AsmError> {
+    let program = parse(src)?;
+    Ok(assemble_program(&program, &mut SymbolTable::new(), false)?.words)
+}
+
+// The output of a successful assembly, along with anything suspicious found on the way.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Assembly {
+    pub words: Vec<u16>,
+    pub warnings: Vec<Diagnostic>,
+}
+
+// RAM addresses from here on are the screen and keyboard memory maps.
+const IO_START: u16 = 0x4000;
+const ROM_SIZE: usize = 0x8000;
+
+// Runs both passes over an already parsed program. Labels and variables end up in `symbol_table`.
+// With `strict` every warning is treated as an error.
+pub fn assemble_program(
+    program: &[Statement],
+    symbol_table: &mut SymbolTable,
+    strict: bool,
+) -> Result<Assembly, AsmError> {
+    let mut diagnostics = Vec::new();
+
+    // First pass; Save label locations.
+    let mut pc: usize = 0;
+    let mut labels: FxHashMap<&str, &Statement> = FxHashMap::default();
+    for statement in program {
+        match &statement.instruction {
+            Instruction::Label(label) => {
+                if let Some(first) = labels.get(label.0.as_str()) {
+                    // The first definition may come from an included file.
+                    let at = match &first.file {
+                        Some(file) => format!("at {}:{}", file, first.line),
+                        None => format!("on line {}", first.line),
+                    };
+                    diagnostics.push(
+                        statement
+                            .operand_diagnostic(ErrorKind::DuplicateLabel)
+                            .with_note(format!("first defined {}", at)),
+                    );
+                    continue;
+                }
+                if symbol_table.kind(&label.0) == Some(SymbolKind::Predefined) {
+                    diagnostics.push(statement.operand_diagnostic(ErrorKind::PredefinedLabel));
+                    continue;
+                }
+                labels.insert(&label.0, statement);
+                symbol_table.add_label(&label.0, pc as u16);
+            }
+            _ => {
+                if pc == ROM_SIZE {
+                    diagnostics.push(
+                        statement
+                            .operand_diagnostic(ErrorKind::RomOverflow)
+                            .with_note(format!("this is instruction number {}", pc))
+                            .warning(),
+                    );
+                }
+                pc += 1
+            }
+        }
+    }
+
+    // Second pass; Encode A and C instructions.
+    let mut output = Vec::with_capacity(pc);
+    for statement in program {
+        let mut resolve = |s: &str| {
+            let is_new = symbol_table.get(s).is_none();
+            let val = symbol_table.resolve(s);
+            if is_new && val >= IO_START {
+                diagnostics.push(
+                    statement
+                        .operand_diagnostic(ErrorKind::VariableInIo)
+                        .with_note(format!("`{}` is at {:#06x}", s, val))
+                        .warning(),
+                );
+            }
+            val
+        };
+        let val = match &statement.instruction {
+            Instruction::A(AInstruction::Value(val)) => Ok(*val as i64),
+            Instruction::A(AInstruction::Symbol(s)) => Ok(resolve(s) as i64),
+            Instruction::A(AInstruction::Expr(e)) => e.eval(&mut |s| resolve(s) as i64),
+            Instruction::C(c) => {
+                output.push(c.encode());
+                continue;
+            }
+            Instruction::Label(_) => continue,
+        };
+        match val {
+            Ok(val) => match encode_value(val) {
+                Some(word) => output.push(word),
+                None if val < 0 => diagnostics.push(
+                    statement
+                        .operand_diagnostic(ErrorKind::ValueOutOfRange)
+                        .with_note(format!(
+                            "evaluates to {}, A-instructions can only load 0..=32767, \
+                             so load {} and negate it with `-A` instead",
+                            val,
+                            val.unsigned_abs()
+                        )),
+                ),
+                None => diagnostics.push(
+                    statement
+                        .operand_diagnostic(ErrorKind::ValueOutOfRange)
+                        .with_note(format!("evaluates to {}, expected 0..=32767", val)),
+                ),
+            },
+            Err(EvalError::DivisionByZero) => {
+                diagnostics.push(statement.operand_diagnostic(ErrorKind::DivisionByZero))
+            }
+            Err(EvalError::Overflow) => {
+                diagnostics.push(statement.operand_diagnostic(ErrorKind::ValueOutOfRange))
+            }
+        }
+    }
+
+    if strict {
+        for d in diagnostics.iter_mut() {
+            d.severity = Severity::Error;
+        }
+    }
+    if diagnostics.iter().any(|d| d.severity == Severity::Error) {
+        return Err(AsmError { diagnostics });
+    }
+    Ok(Assembly {
+        words: output,
+        warnings: diagnostics,
+    })
+}
+
+// A-instructions carry 15 bits, with the top bit of A always 0, so no negative value can
+// be loaded: `@-1` would end up as 32767.
+fn encode_value(val: i64) -> Option<u16> {
+    match val {
+        0..=0x7fff => Some(val as u16),
+        _ => None,
+    }
+}
@@ -0,0 +1,52 @@
+use std::io::{self, Write};
+
+use crate::instruction::Instruction;
+use crate::symbol_table::{SymbolKind, SymbolTable};
+use crate::Statement;
+
+// Puts every statement next to its ROM address and encoding, e.g.
+//
+// ROM   HEX   BINARY            LINE  SOURCE
+// 0000  0002  0000000000000010    13      @R2
+// 0004                            18  (LOOP)
+pub fn write_listing(
+    program: &[Statement],
+    words: &[u16],
+    writer: &mut impl Write,
+) -> io::Result<()> {
+    writeln!(writer, "ROM   HEX   BINARY            LINE  SOURCE")?;
+    let mut pc = 0;
+    for statement in program {
+        let source = statement.source.trim_end();
+        match &statement.instruction {
+            Instruction::Label(_) => writeln!(
+                writer,
+                "{:04X}  {:4}  {:16}  {:4}  {}",
+                pc, "", "", statement.line, source
+            )?,
+            _ => {
+                writeln!(
+                    writer,
+                    "{:04X}  {:04X}  {:016b}  {:4}  {}",
+                    pc, words[pc], words[pc], statement.line, source
+                )?;
+                pc += 1;
+            }
+        }
+    }
+    Ok(())
+}
+
+// One symbol per line: address, kind and name. Predefined symbols are left out,
+// since every Hack program has the same ones.
+pub fn write_symbols(symbol_table: &SymbolTable, writer: &mut impl Write) -> io::Result<()> {
+    for (name, addr, kind) in symbol_table.entries() {
+        let kind = match kind {
+            SymbolKind::Predefined => continue,
+            SymbolKind::Label => "label",
+            SymbolKind::Variable => "var",
+        };
+        writeln!(writer, "{:04X} {:5} {}", addr, kind, name)?;
+    }
+    Ok(())
+}
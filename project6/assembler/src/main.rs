@@ -1,183 +1,172 @@
-use rustc_hash::FxHashMap;
-use std::io::{BufRead, BufReader};
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::ExitCode;
 
-fn main() {
-    let mut args = std::env::args();
-    args.next();
-    let filename = args.next();
-    let reader = filename
-        .and_then(|x| std::fs::File::open(x).ok())
-        .expect("Error reading file. Make sure to provide filename as first argument.");
-    let mut lines = BufReader::new(reader);
-    lines.fill_buf().unwrap();
-    let mut lines = lines.lines().map(|x| x.unwrap());
-
-    // First pass; Strip whitespace and save label locations.
-    let mut pc = 0;
-    let mut symbol_table: FxHashMap<String, u16> = new_symbol_table();
-    let mut file: Vec<String> = Vec::new();
-    while let Some(line) = lines.next() {
-        let command = strip_whitespace(&line);
-        if command.is_empty() {
-            continue;
-        }
-        match &command[..1] {
-            "(" => label_command(&command, pc, &mut symbol_table),
-            _ => {
-                file.push(command.to_owned());
-                pc += 1
-            }
-        }
-    }
+use assembler::listing;
+use assembler::output::{self, Format};
+use assembler::{Severity, SymbolTable};
 
-    // Second pass; Parse A and C instructions
-    let mut output = String::with_capacity(file.len() * 17);
-    let mut variable_counter = 16;
-    for line in file {
-        output.push_str(&match &line[..1] {
-            "@" => a_command(&line, &mut variable_counter, &mut symbol_table),
-            _ => c_command(&line),
-        });
-        output.push('\n');
-    }
-    output.pop();
+const USAGE: &str = r#"Usage:
+    assembler <file.asm> [options]
 
-    println!("{}", output);
-}
+Options:
+  -o <file>, --output <file>            Outputs to <file> instead of standard output
+  -f <fmt>,  --format <fmt>             Output format, one of:
+                                          hack     ASCII binary, one word per line (default)
+                                          raw-le   raw little-endian words
+                                          raw-be   raw big-endian words
+                                          ihex     Intel HEX
+                                          logisim  Logisim "v2.0 raw" memory image
+                                          rust     `const ROM: [u16; N]` array
+                                          c        `const uint16_t ROM[N]` array
+  -l <file>, --listing <file>           Writes a listing with addresses, encodings and source
+             --symbols <file>           Writes the final labels and variables with their addresses
+             --strict                   Treats warnings as errors
+  -h,        --help                     Prints help message
+"#;
 
-fn strip_whitespace(s: &str) -> &str {
-    let comment_start = s.find('/').unwrap_or(s.len());
-    let s = &s[..comment_start];
-    s.trim()
+struct Args {
+    input_file: String,
+    output_file: Option<String>,
+    listing_file: Option<String>,
+    symbols_file: Option<String>,
+    format: Format,
+    strict: bool,
 }
 
-fn label_command(s: &str, pc: usize, symbol_table: &mut FxHashMap<String, u16>) {
-    let s = &s[1..s.len() - 1];
-    symbol_table.insert(s.to_owned(), pc as u16);
-}
-
-fn a_command(
-    s: &str,
-    variable_counter: &mut u16,
-    symbol_table: &mut FxHashMap<String, u16>,
-) -> String {
-    let s = &s[1..];
-    let val = s.parse::<u16>().ok().unwrap_or_else(|| {
-        symbol_table.get(s).copied().unwrap_or_else(|| {
-            symbol_table.insert(s.to_owned(), *variable_counter);
-            *variable_counter += 1;
-            symbol_table.get(s).copied().unwrap()
-        })
-    });
-
-    let mut out = String::with_capacity(16);
-    out.push('0');
-    out.push_str(&format!("{:015b}", val));
-    out
+fn parse_args() -> Result<Option<Args>, String> {
+    let mut args = std::env::args();
+    args.next();
+    let mut input_file = None;
+    let mut output_file = None;
+    let mut listing_file = None;
+    let mut symbols_file = None;
+    let mut format = Format::Hack;
+    let mut strict = false;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" | "--output" => {
+                output_file = Some(args.next().ok_or("missing file name after -o")?);
+            }
+            "-l" | "--listing" => {
+                listing_file = Some(args.next().ok_or("missing file name after -l")?);
+            }
+            "--symbols" => {
+                symbols_file = Some(args.next().ok_or("missing file name after --symbols")?);
+            }
+            "-f" | "--format" => {
+                let f = args.next().ok_or("missing format after -f")?;
+                format = f.parse().map_err(|f| format!("unknown format `{}`", f))?;
+            }
+            "--strict" => strict = true,
+            "-h" | "--help" => return Ok(None),
+            _ => input_file = Some(arg),
+        }
+    }
+    let input_file = input_file.ok_or("missing input file")?;
+    Ok(Some(Args {
+        input_file,
+        output_file,
+        listing_file,
+        symbols_file,
+        format,
+        strict,
+    }))
 }
 
-fn c_command(s: &str) -> String {
-    let mut out = String::with_capacity(16);
-    out.push_str("111");
-    let (dest_s, rest) = s
-        .find("=")
-        .map(|i| s.split_at(i))
-        .map(|(dest, rest)| (dest, &rest[1..]))
-        .unwrap_or(("", &s));
-    let (comp_s, jmp_s) = rest
-        .find(";")
-        .map(|i| rest.split_at(i))
-        .map(|(comp, jmp)| (comp, &jmp[1..]))
-        .unwrap_or((rest, ""));
-
-    out.push_str(&comp_segment(comp_s));
-    out.push_str(&dest_segment(dest_s));
-    out.push_str(&jmp_segment(jmp_s));
+fn main() -> ExitCode {
+    let args = match parse_args() {
+        Ok(Some(args)) => args,
+        Ok(None) => {
+            print!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(e) => {
+            eprintln!("error: {}\n", e);
+            eprint!("{}", USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
 
-    out
-}
+    let src = match fs::read_to_string(&args.input_file) {
+        Ok(src) => src,
+        Err(_) => {
+            eprintln!("Couldn't open file: {}", args.input_file);
+            return ExitCode::FAILURE;
+        }
+    };
 
-fn comp_segment(s: &str) -> String {
-    let mut out = String::with_capacity(7);
-    let a_param = s.contains('M');
-    out.push(if a_param { '1' } else { '0' });
-    // Not the most clever way to do it, but it's fast and quite readable.
-    out.push_str(match s {
-        "0" => "101010",
-        "1" => "111111",
-        "-1" => "111010",
-        "D" => "001100",
-        "A" | "M" => "110000",
-        "!D" => "001101",
-        "!A" | "!M" => "110001",
-        "-D" => "001111",
-        "-A" | "-M" => "110011",
-        "D+1" => "011111",
-        "A+1" | "M+1" => "110111",
-        "D-1" => "001110",
-        "A-1" | "M-1" => "110010",
-        "D+A" | "A+D" | "M+D" | "D+M" => "000010",
-        "D-A" | "D-M" => "010011",
-        "A-D" | "M-D" => "000111",
-        "D&A" | "A&D" | "D&M" | "M&D" => "000000",
-        "D|A" | "A|D" | "D|M" | "M|D" => "010101",
-        _ => unimplemented!("{}", s),
-    });
-    out
-}
+    let mut symbol_table = SymbolTable::new();
+    let result =
+        assembler::parse_source(&src, Some(Path::new(&args.input_file))).and_then(|program| {
+            let assembly = assembler::assemble_program(&program, &mut symbol_table, args.strict)?;
+            Ok((program, assembly))
+        });
+    let (program, words) = match result {
+        Ok((program, assembly)) => {
+            for mut w in assembly.warnings {
+                w.file.get_or_insert_with(|| args.input_file.clone());
+                eprintln!("{}", w.render());
+            }
+            (program, assembly.words)
+        }
+        Err(e) => {
+            let e = e.with_file(&args.input_file);
+            eprintln!("{}", e.render());
+            let errors = e
+                .diagnostics
+                .iter()
+                .filter(|d| d.severity == Severity::Error)
+                .count();
+            eprintln!(
+                "error: could not assemble due to {} previous error{}",
+                errors,
+                if errors == 1 { "" } else { "s" }
+            );
+            return ExitCode::FAILURE;
+        }
+    };
 
-fn dest_segment(s: &str) -> String {
-    let mut out: [u8; 3] = *b"000";
-    if s.contains('A') {
-        out[0] = b'1';
+    let mut writer: Box<dyn Write> = match &args.output_file {
+        Some(path) => match fs::File::create(path) {
+            Ok(f) => Box::new(io::BufWriter::new(f)),
+            Err(_) => {
+                eprintln!("Couldn't create file: {}", path);
+                return ExitCode::FAILURE;
+            }
+        },
+        None => Box::new(io::stdout().lock()),
+    };
+    if output::write_words(&words, args.format, &mut writer)
+        .and_then(|_| writer.flush())
+        .is_err()
+    {
+        eprintln!("Couldn't write output");
+        return ExitCode::FAILURE;
     }
-    if s.contains('D') {
-        out[1] = b'1';
+
+    if let Some(path) = &args.listing_file {
+        if write_file(path, |w| listing::write_listing(&program, &words, w)).is_err() {
+            eprintln!("Couldn't write listing: {}", path);
+            return ExitCode::FAILURE;
+        }
     }
-    if s.contains('M') {
-        out[2] = b'1';
+    if let Some(path) = &args.symbols_file {
+        if write_file(path, |w| listing::write_symbols(&symbol_table, w)).is_err() {
+            eprintln!("Couldn't write symbols: {}", path);
+            return ExitCode::FAILURE;
+        }
     }
-    String::from_utf8(Vec::from(out)).unwrap()
-}
 
-fn jmp_segment(s: &str) -> String {
-    match s {
-        "JGT" => "001",
-        "JEQ" => "010",
-        "JGE" => "011",
-        "JLT" => "100",
-        "JNE" => "101",
-        "JLE" => "110",
-        "JMP" => "111",
-        _ => "000",
-    }
-    .to_owned()
+    ExitCode::SUCCESS
 }
 
-fn new_symbol_table() -> FxHashMap<String, u16> {
-    let mut map = FxHashMap::default();
-    map.insert("R0".to_owned(), 0);
-    map.insert("R1".to_owned(), 1);
-    map.insert("R2".to_owned(), 2);
-    map.insert("R3".to_owned(), 3);
-    map.insert("R4".to_owned(), 4);
-    map.insert("R5".to_owned(), 5);
-    map.insert("R6".to_owned(), 6);
-    map.insert("R7".to_owned(), 7);
-    map.insert("R8".to_owned(), 8);
-    map.insert("R9".to_owned(), 9);
-    map.insert("R10".to_owned(), 10);
-    map.insert("R11".to_owned(), 11);
-    map.insert("R12".to_owned(), 12);
-    map.insert("R13".to_owned(), 13);
-    map.insert("R14".to_owned(), 14);
-    map.insert("R15".to_owned(), 15);
-    map.insert("SP".to_owned(), 0);
-    map.insert("LCL".to_owned(), 1);
-    map.insert("ARG".to_owned(), 2);
-    map.insert("THIS".to_owned(), 3);
-    map.insert("THAT".to_owned(), 4);
-    map.insert("SCREEN".to_owned(), 0x4000);
-    map.insert("KBD".to_owned(), 0x6000);
-    map
+fn write_file(
+    path: &str,
+    f: impl FnOnce(&mut io::BufWriter<fs::File>) -> io::Result<()>,
+) -> io::Result<()> {
+    let mut writer = io::BufWriter::new(fs::File::create(path)?);
+    f(&mut writer)?;
+    writer.flush()
 }
@@ -0,0 +1,113 @@
+use std::io::{self, Write};
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    // ASCII '0'/'1' lines, as read by the course's CPU emulator.
+    Hack,
+    RawLe,
+    RawBe,
+    IntelHex,
+    Logisim,
+    Rust,
+    C,
+}
+
+impl FromStr for Format {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "hack" => Ok(Format::Hack),
+            "raw-le" => Ok(Format::RawLe),
+            "raw-be" => Ok(Format::RawBe),
+            "ihex" => Ok(Format::IntelHex),
+            "logisim" => Ok(Format::Logisim),
+            "rust" => Ok(Format::Rust),
+            "c" => Ok(Format::C),
+            _ => Err(s.to_owned()),
+        }
+    }
+}
+
+pub fn write_words(words: &[u16], format: Format, writer: &mut impl Write) -> io::Result<()> {
+    match format {
+        Format::Hack => {
+            for w in words {
+                writeln!(writer, "{:016b}", w)?;
+            }
+            Ok(())
+        }
+        Format::RawLe => {
+            let bytes: Vec<u8> = words.iter().flat_map(|w| w.to_le_bytes()).collect();
+            writer.write_all(&bytes)
+        }
+        Format::RawBe => {
+            let bytes: Vec<u8> = words.iter().flat_map(|w| w.to_be_bytes()).collect();
+            writer.write_all(&bytes)
+        }
+        Format::IntelHex => intel_hex(words, writer),
+        Format::Logisim => logisim(words, writer),
+        Format::Rust => array(words, writer, "const ROM: [u16; {}] = [", "];"),
+        Format::C => array(words, writer, "const uint16_t ROM[{}] = {", "};"),
+    }
+}
+
+// Byte addressed, words stored big-endian, 16 bytes per data record.
+fn intel_hex(words: &[u16], writer: &mut impl Write) -> io::Result<()> {
+    let bytes: Vec<u8> = words.iter().flat_map(|w| w.to_be_bytes()).collect();
+    let mut upper = 0;
+    for (i, chunk) in bytes.chunks(16).enumerate() {
+        let addr = i * 16;
+        if addr >> 16 != upper {
+            upper = addr >> 16;
+            hex_record(writer, 0, 0x04, &(upper as u16).to_be_bytes())?;
+        }
+        hex_record(writer, addr as u16, 0x00, chunk)?;
+    }
+    hex_record(writer, 0, 0x01, &[])
+}
+
+fn hex_record(writer: &mut impl Write, addr: u16, ty: u8, data: &[u8]) -> io::Result<()> {
+    let mut record = vec![data.len() as u8];
+    record.extend_from_slice(&addr.to_be_bytes());
+    record.push(ty);
+    record.extend_from_slice(data);
+    let checksum = record
+        .iter()
+        .fold(0u8, |acc, b| acc.wrapping_add(*b))
+        .wrapping_neg();
+    write!(writer, ":")?;
+    for b in record {
+        write!(writer, "{:02X}", b)?;
+    }
+    writeln!(writer, "{:02X}", checksum)
+}
+
+// Logisim's "v2.0 raw" image. Runs of the same word are written as `count*value`.
+fn logisim(words: &[u16], writer: &mut impl Write) -> io::Result<()> {
+    writeln!(writer, "v2.0 raw")?;
+    let mut entries = Vec::new();
+    let mut i = 0;
+    while i < words.len() {
+        let run = words[i..].iter().take_while(|&&w| w == words[i]).count();
+        if run >= 4 {
+            entries.push(format!("{}*{:x}", run, words[i]));
+        } else {
+            entries.extend(words[i..i + run].iter().map(|w| format!("{:x}", w)));
+        }
+        i += run;
+    }
+    for line in entries.chunks(8) {
+        writeln!(writer, "{}", line.join(" "))?;
+    }
+    Ok(())
+}
+
+fn array(words: &[u16], writer: &mut impl Write, header: &str, footer: &str) -> io::Result<()> {
+    writeln!(writer, "{}", header.replace("{}", &words.len().to_string()))?;
+    for line in words.chunks(8) {
+        let line: Vec<String> = line.iter().map(|w| format!("0x{:04x},", w)).collect();
+        writeln!(writer, "    {}", line.join(" "))?;
+    }
+    writeln!(writer, "{}", footer)
+}
@@ -0,0 +1,102 @@
+use crate::error::{Diagnostic, ErrorKind};
+use crate::expr::{self, Expr};
+use crate::instruction::{AInstruction, CInstruction, Comp, Dest, Instruction, Jump, Label};
+
+pub fn strip_whitespace(s: &str) -> &str {
+    let comment_start = s.find("//").unwrap_or(s.len());
+    let s = &s[..comment_start];
+    s.trim()
+}
+
+// Returns None for lines which contain only whitespace and comments.
+pub fn parse_line(line: &str, line_no: usize) -> Result<Option<Instruction>, Diagnostic> {
+    let command = strip_whitespace(line);
+    if command.is_empty() {
+        return Ok(None);
+    }
+    let instruction = if let Some(rest) = command.strip_prefix('(') {
+        label_command(rest).map(Instruction::Label)
+    } else if let Some(rest) = command.strip_prefix('@') {
+        a_command(rest).map(Instruction::A)
+    } else {
+        c_command(command).map(Instruction::C)
+    };
+    instruction
+        .map(Some)
+        .map_err(|(kind, text)| Diagnostic::new(kind, line_no, line, text))
+}
+
+// Errors carry the slice of the line they refer to.
+type ParseResult<'a, T> = Result<T, (ErrorKind, &'a str)>;
+
+fn label_command(s: &str) -> ParseResult<'_, Label> {
+    match s.strip_suffix(')') {
+        Some(name) if name.trim().is_empty() => Err((ErrorKind::EmptyLabel, s)),
+        Some(name) if is_symbol(name.trim()) => Ok(Label(name.trim().to_owned())),
+        Some(name) => Err((ErrorKind::InvalidSymbol, name.trim())),
+        None => Err((ErrorKind::UnterminatedLabel, s)),
+    }
+}
+
+fn a_command(s: &str) -> ParseResult<'_, AInstruction> {
+    let s = s.trim();
+    if s.is_empty() {
+        Err((ErrorKind::MissingSymbol, s))
+    } else if let Ok(val) = s.parse::<u16>() {
+        Ok(AInstruction::Value(val))
+    } else if is_symbol(s) {
+        Ok(AInstruction::Symbol(s.to_owned()))
+    } else {
+        match expr::parse(s) {
+            Ok(Expr::Number(n)) if (0..=u16::MAX as i64).contains(&n) => {
+                Ok(AInstruction::Value(n as u16))
+            }
+            Ok(e) => Ok(AInstruction::Expr(e)),
+            Err(text) => Err((ErrorKind::InvalidExpression, text)),
+        }
+    }
+}
+
+fn c_command(s: &str) -> ParseResult<'_, CInstruction> {
+    let (dest_s, rest) = s
+        .find('=')
+        .map(|i| s.split_at(i))
+        .map(|(dest, rest)| (dest, &rest[1..]))
+        .unwrap_or(("", s));
+    let (comp_s, jmp_s) = rest
+        .find(';')
+        .map(|i| rest.split_at(i))
+        .map(|(comp, jmp)| (comp, &jmp[1..]))
+        .unwrap_or((rest, ""));
+    let (dest_s, comp_s, jmp_s) = (dest_s.trim(), comp_s.trim(), jmp_s.trim());
+
+    // An explicit but empty dest or jump (`=D`, `D;`) is as wrong as a misspelled one.
+    let dest = match dest_s.parse::<Dest>() {
+        Ok(Dest::Null) if s.contains('=') => Err((ErrorKind::InvalidDest, dest_s)),
+        Ok(dest) => Ok(dest),
+        Err(_) => Err((ErrorKind::InvalidDest, dest_s)),
+    }?;
+    let comp = comp_s
+        .parse::<Comp>()
+        .map_err(|_| (ErrorKind::InvalidComp, comp_s))?;
+    let jump = match jmp_s.parse::<Jump>() {
+        Ok(Jump::Null) if rest.contains(';') => Err((ErrorKind::InvalidJump, jmp_s)),
+        Ok(jump) => Ok(jump),
+        Err(_) => Err((ErrorKind::InvalidJump, jmp_s)),
+    }?;
+
+    Ok(CInstruction { dest, comp, jump })
+}
+
+// Symbols are sequences of letters, digits, '_', '.', '$' and ':' not starting with a digit.
+pub fn is_symbol(s: &str) -> bool {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(c) if !c.is_ascii_digit() && is_symbol_char(c) => chars.all(is_symbol_char),
+        _ => false,
+    }
+}
+
+fn is_symbol_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '$' | ':')
+}
@@ -0,0 +1,310 @@
+use std::path::{Path, PathBuf};
+
+use rustc_hash::FxHashMap;
+
+use crate::error::{AsmError, Diagnostic, ErrorKind};
+use crate::parser::{is_symbol, strip_whitespace};
+
+// A line of source after preprocessing, remembering where it came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLine {
+    // None for the file that was passed in, the path for included ones.
+    pub file: Option<String>,
+    pub line: usize,
+    pub text: String,
+    // Set when the line was produced by expanding a macro.
+    pub expanded_from: Option<String>,
+}
+
+struct Macro {
+    params: Vec<String>,
+    body: Vec<String>,
+}
+
+// Where the lines currently being processed come from.
+struct Origin<'a> {
+    file: Option<&'a str>,
+    dir: &'a Path,
+    line: usize,
+}
+
+#[derive(Default)]
+struct Preprocessor {
+    defines: FxHashMap<String, String>,
+    macros: FxHashMap<String, Macro>,
+    expansion_count: usize,
+    expansion_stack: Vec<String>,
+    include_stack: Vec<PathBuf>,
+    output: Vec<SourceLine>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+// Handles the directives ahead of the first pass:
+//
+// .define NAME value         replaces every later NAME with value
+// .macro NAME a, b ... .endm defines a macro, invoked as `NAME x, y`; labels written
+//                            as %name inside the body are unique to each expansion
+// .include "file.asm"        pastes the file in, relative to the including file
+pub fn preprocess(src: &str, path: Option<&Path>) -> Result<Vec<SourceLine>, AsmError> {
+    let mut preprocessor = Preprocessor::default();
+    let dir = path
+        .and_then(Path::parent)
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
+    if let Some(path) = path.and_then(|p| p.canonicalize().ok()) {
+        preprocessor.include_stack.push(path);
+    }
+    preprocessor.process_file(src, None, &dir);
+
+    if preprocessor.diagnostics.is_empty() {
+        Ok(preprocessor.output)
+    } else {
+        Err(AsmError {
+            diagnostics: preprocessor.diagnostics,
+        })
+    }
+}
+
+impl Preprocessor {
+    fn process_file(&mut self, src: &str, file: Option<&str>, dir: &Path) {
+        let lines: Vec<&str> = src.lines().collect();
+        let mut i = 0;
+        while i < lines.len() {
+            let line = lines[i];
+            let origin = Origin {
+                file,
+                dir,
+                line: i + 1,
+            };
+            i += 1;
+
+            let (directive, rest) = split_word(strip_whitespace(line));
+            if directive == ".macro" {
+                let end = lines[i..]
+                    .iter()
+                    .position(|l| strip_whitespace(l) == ".endm");
+                let body_end = end.map(|e| i + e).unwrap_or(lines.len());
+                self.define_macro(
+                    &origin,
+                    line,
+                    directive,
+                    rest,
+                    &lines[i..body_end],
+                    end.is_none(),
+                );
+                i = (body_end + 1).min(lines.len());
+            } else {
+                self.process_line(&origin, line, None);
+            }
+        }
+    }
+
+    fn process_line(&mut self, origin: &Origin, line: &str, expanded_from: Option<&str>) {
+        let command = strip_whitespace(line);
+        if let Some(directive) = command.strip_prefix('.') {
+            let (name, rest) = split_word(directive);
+            match name {
+                "define" => self.define(origin, line, name, rest),
+                "include" => self.include(origin, line, name, rest),
+                "endm" => self.error(origin, ErrorKind::UnexpectedEndm, line, name),
+                // Only reachable from within a macro body.
+                "macro" => self.error(origin, ErrorKind::MalformedDirective, line, name),
+                _ => self.error(origin, ErrorKind::UnknownDirective, line, name),
+            }
+            return;
+        }
+
+        let substituted = substitute(command, |token| self.defines.get(token).cloned());
+        let (name, args) = split_word(&substituted);
+        if self.macros.contains_key(name) {
+            let name = name.to_owned();
+            let args = args.to_owned();
+            self.expand(origin, line, &name, &args);
+            return;
+        }
+
+        // Keep the line as written, comments and all, unless something was substituted.
+        let text = if substituted == command && expanded_from.is_none() {
+            line.to_owned()
+        } else {
+            substituted
+        };
+        self.output.push(SourceLine {
+            file: origin.file.map(str::to_owned),
+            line: origin.line,
+            text,
+            expanded_from: expanded_from.map(str::to_owned),
+        });
+    }
+
+    fn define(&mut self, origin: &Origin, line: &str, directive: &str, rest: &str) {
+        let (name, value) = split_word(rest);
+        if !is_symbol(name) || value.is_empty() {
+            return self.error(origin, ErrorKind::MalformedDirective, line, directive);
+        }
+        if self.defines.contains_key(name) || self.macros.contains_key(name) {
+            return self.error(origin, ErrorKind::Redefinition, line, name);
+        }
+        let value = substitute(value, |token| self.defines.get(token).cloned());
+        self.defines.insert(name.to_owned(), value);
+    }
+
+    fn define_macro(
+        &mut self,
+        origin: &Origin,
+        line: &str,
+        directive: &str,
+        header: &str,
+        body: &[&str],
+        unterminated: bool,
+    ) {
+        let (name, params) = split_word(header);
+        let params = split_args(params);
+        if !is_symbol(name) || !params.iter().all(|p| is_symbol(p)) {
+            return self.error(origin, ErrorKind::MalformedDirective, line, &directive[1..]);
+        }
+        if unterminated {
+            return self.error(origin, ErrorKind::UnterminatedMacro, line, name);
+        }
+        if self.defines.contains_key(name) || self.macros.contains_key(name) {
+            return self.error(origin, ErrorKind::Redefinition, line, name);
+        }
+        let body = body
+            .iter()
+            .map(|l| strip_whitespace(l))
+            .filter(|l| !l.is_empty())
+            .map(str::to_owned)
+            .collect();
+        let params = params.into_iter().map(str::to_owned).collect();
+        self.macros.insert(name.to_owned(), Macro { params, body });
+    }
+
+    fn expand(&mut self, origin: &Origin, line: &str, name: &str, args: &str) {
+        let text = split_word(strip_whitespace(line)).0;
+        if self.expansion_stack.iter().any(|m| m == name) {
+            return self.error(origin, ErrorKind::RecursiveMacro, line, text);
+        }
+        let args = split_args(args);
+        let Macro { params, body } = &self.macros[name];
+        if args.len() != params.len() {
+            let d = Diagnostic::new(ErrorKind::WrongArgumentCount, origin.line, line, text)
+                .with_note(format!("expected {}, found {}", params.len(), args.len()));
+            return self.push_diagnostic(origin, d);
+        }
+
+        let expansion = self.expansion_count;
+        self.expansion_count += 1;
+        let body: Vec<String> = body
+            .iter()
+            .map(|l| {
+                substitute(l, |token| {
+                    if let Some(local) = token.strip_prefix('%') {
+                        return Some(format!("__{}.{}.{}", name, expansion, local));
+                    }
+                    let i = params.iter().position(|p| p == token)?;
+                    Some(args[i].to_owned())
+                })
+            })
+            .collect();
+
+        self.expansion_stack.push(name.to_owned());
+        for l in body {
+            self.process_line(origin, &l, Some(name));
+        }
+        self.expansion_stack.pop();
+    }
+
+    fn include(&mut self, origin: &Origin, line: &str, directive: &str, rest: &str) {
+        let Some(name) = rest.strip_prefix('"').and_then(|r| r.strip_suffix('"')) else {
+            return self.error(origin, ErrorKind::MalformedDirective, line, directive);
+        };
+        let path = origin.dir.join(name);
+        let (canonical, src) = match path
+            .canonicalize()
+            .and_then(|c| std::fs::read_to_string(&c).map(|src| (c, src)))
+        {
+            Ok(x) => x,
+            Err(_) => return self.error(origin, ErrorKind::IncludeNotFound, line, rest),
+        };
+        if self.include_stack.contains(&canonical) {
+            let chain: Vec<String> = self
+                .include_stack
+                .iter()
+                .chain(std::iter::once(&canonical))
+                .map(|p| p.display().to_string())
+                .collect();
+            let d = Diagnostic::new(ErrorKind::IncludeCycle, origin.line, line, rest)
+                .with_note(format!("include chain: {}", chain.join(" -> ")));
+            return self.push_diagnostic(origin, d);
+        }
+
+        let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        let file = path.display().to_string();
+        self.include_stack.push(canonical);
+        self.process_file(&src, Some(&file), &dir);
+        self.include_stack.pop();
+    }
+
+    // `text` has to be a subslice of `line`.
+    fn error(&mut self, origin: &Origin, kind: ErrorKind, line: &str, text: &str) {
+        let d = Diagnostic::new(kind, origin.line, line, text);
+        self.push_diagnostic(origin, d);
+    }
+
+    fn push_diagnostic(&mut self, origin: &Origin, mut d: Diagnostic) {
+        d.file = origin.file.map(str::to_owned);
+        if let Some(m) = self.expansion_stack.last() {
+            d.note
+                .get_or_insert(format!("in this expansion of macro `{}`", m));
+        }
+        self.diagnostics.push(d);
+    }
+}
+
+fn split_word(s: &str) -> (&str, &str) {
+    let s = s.trim();
+    match s.find(char::is_whitespace) {
+        Some(i) => (&s[..i], s[i..].trim()),
+        None => (s, ""),
+    }
+}
+
+fn split_args(s: &str) -> Vec<&str> {
+    if s.trim().is_empty() {
+        return Vec::new();
+    }
+    s.split(',').map(str::trim).collect()
+}
+
+// Replaces every symbol-like token (optionally prefixed with '%') for which `f` returns Some.
+fn substitute(s: &str, f: impl Fn(&str) -> Option<String>) -> String {
+    let is_token_char = |c: char| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '$' | ':');
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while !rest.is_empty() {
+        let start = rest
+            .char_indices()
+            .find(|&(i, c)| {
+                is_token_char(c) || (c == '%' && rest[i + 1..].starts_with(is_token_char))
+            })
+            .map(|(i, _)| i)
+            .unwrap_or(rest.len());
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        if rest.is_empty() {
+            break;
+        }
+        let prefix = usize::from(rest.starts_with('%'));
+        let end = rest[prefix..]
+            .find(|c: char| !is_token_char(c))
+            .map(|e| e + prefix)
+            .unwrap_or(rest.len());
+        let token = &rest[..end];
+        match f(token) {
+            Some(replacement) => out.push_str(&replacement),
+            None => out.push_str(token),
+        }
+        rest = &rest[end..];
+    }
+    out
+}
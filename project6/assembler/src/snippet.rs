@@ -0,0 +1,65 @@
+// Pointing at the offending text of a source line, shared by the assembler's diagnostics
+// and the ones of the other course tools, so they all report problems the same way.
+
+use std::fmt;
+
+// A piece of a source line. `line` and `column` are both 1-based, like the positions
+// rustc reports.
+pub struct Snippet<'a> {
+    pub file: Option<&'a str>,
+    pub line: usize,
+    pub column: usize,
+    pub text: &'a str,
+    pub source_line: &'a str,
+}
+
+// The column `text` starts at. It has to be a subslice of `source_line`, that's how the
+// column is found.
+pub fn column(source_line: &str, text: &str) -> usize {
+    let offset = text.as_ptr() as usize - source_line.as_ptr() as usize;
+    source_line[..offset].chars().count() + 1
+}
+
+impl Snippet<'_> {
+    // Renders the message like rustc does:
+    //
+    // error: unknown jump `JPM`
+    //  --> Mult.asm:34:7
+    //    |
+    // 34 |     0;JPM
+    //    |       ^^^
+    pub fn render(&self, severity: &str, message: &str, note: Option<&str>) -> String {
+        let line_no = self.line.to_string();
+        let gutter = " ".repeat(line_no.len());
+        let source_line = self.source_line.replace('\t', " ");
+        let underline = format!(
+            "{}{}",
+            " ".repeat(self.column - 1),
+            "^".repeat(self.text.chars().count().max(1))
+        );
+        let mut out = format!(
+            "{severity}: {}\n{gutter}--> {}\n{gutter} |\n{line_no} | {}\n{gutter} | {}\n",
+            message,
+            self,
+            source_line.trim_end(),
+            underline,
+        );
+        if let Some(note) = note {
+            out.push_str(&format!("{gutter} = note: {}\n", note));
+        }
+        out
+    }
+}
+
+// The position as `file:line:column`.
+impl fmt::Display for Snippet<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}",
+            self.file.unwrap_or("<input>"),
+            self.line,
+            self.column
+        )
+    }
+}
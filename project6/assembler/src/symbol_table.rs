@@ -0,0 +1,96 @@
+use rustc_hash::FxHashMap;
+
+// Variables are allocated from RAM[16] upwards, right after R0..R15.
+const FIRST_VARIABLE: u16 = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SymbolKind {
+    Predefined,
+    Label,
+    Variable,
+}
+
+#[derive(Debug, Clone)]
+pub struct SymbolTable {
+    symbols: FxHashMap<String, (u16, SymbolKind)>,
+    variable_counter: u16,
+}
+
+impl SymbolTable {
+    pub fn new() -> SymbolTable {
+        SymbolTable {
+            symbols: new_symbol_table(),
+            variable_counter: FIRST_VARIABLE,
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<u16> {
+        self.symbols.get(name).map(|(val, _)| *val)
+    }
+
+    pub fn kind(&self, name: &str) -> Option<SymbolKind> {
+        self.symbols.get(name).map(|(_, kind)| *kind)
+    }
+
+    pub fn add_label(&mut self, name: &str, pc: u16) {
+        self.symbols
+            .insert(name.to_owned(), (pc, SymbolKind::Label));
+    }
+
+    // All symbols sorted by kind, then by address.
+    pub fn entries(&self) -> Vec<(&str, u16, SymbolKind)> {
+        let mut entries: Vec<_> = self
+            .symbols
+            .iter()
+            .map(|(name, (val, kind))| (name.as_str(), *val, *kind))
+            .collect();
+        entries.sort_by(|a, b| (a.2, a.1, a.0).cmp(&(b.2, b.1, b.0)));
+        entries
+    }
+
+    // Looks the symbol up, allocating a new variable for it if it's unknown.
+    pub fn resolve(&mut self, name: &str) -> u16 {
+        if let Some(val) = self.get(name) {
+            return val;
+        }
+        let val = self.variable_counter;
+        self.symbols
+            .insert(name.to_owned(), (val, SymbolKind::Variable));
+        self.variable_counter += 1;
+        val
+    }
+}
+
+impl Default for SymbolTable {
+    fn default() -> Self {
+        SymbolTable::new()
+    }
+}
+
+fn new_symbol_table() -> FxHashMap<String, (u16, SymbolKind)> {
+    let mut map = FxHashMap::default();
+    map.insert("R0".to_owned(), (0, SymbolKind::Predefined));
+    map.insert("R1".to_owned(), (1, SymbolKind::Predefined));
+    map.insert("R2".to_owned(), (2, SymbolKind::Predefined));
+    map.insert("R3".to_owned(), (3, SymbolKind::Predefined));
+    map.insert("R4".to_owned(), (4, SymbolKind::Predefined));
+    map.insert("R5".to_owned(), (5, SymbolKind::Predefined));
+    map.insert("R6".to_owned(), (6, SymbolKind::Predefined));
+    map.insert("R7".to_owned(), (7, SymbolKind::Predefined));
+    map.insert("R8".to_owned(), (8, SymbolKind::Predefined));
+    map.insert("R9".to_owned(), (9, SymbolKind::Predefined));
+    map.insert("R10".to_owned(), (10, SymbolKind::Predefined));
+    map.insert("R11".to_owned(), (11, SymbolKind::Predefined));
+    map.insert("R12".to_owned(), (12, SymbolKind::Predefined));
+    map.insert("R13".to_owned(), (13, SymbolKind::Predefined));
+    map.insert("R14".to_owned(), (14, SymbolKind::Predefined));
+    map.insert("R15".to_owned(), (15, SymbolKind::Predefined));
+    map.insert("SP".to_owned(), (0, SymbolKind::Predefined));
+    map.insert("LCL".to_owned(), (1, SymbolKind::Predefined));
+    map.insert("ARG".to_owned(), (2, SymbolKind::Predefined));
+    map.insert("THIS".to_owned(), (3, SymbolKind::Predefined));
+    map.insert("THAT".to_owned(), (4, SymbolKind::Predefined));
+    map.insert("SCREEN".to_owned(), (0x4000, SymbolKind::Predefined));
+    map.insert("KBD".to_owned(), (0x6000, SymbolKind::Predefined));
+    map
+}
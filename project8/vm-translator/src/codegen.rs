@@ -3,6 +3,9 @@ pub struct FileData {
     static_index_label_gen: StaticIndexLabelGen,
     file_label_gen: FileLabelGen,
     function_label_gen: FunctionLabelGen,
+    // Whether calls, returns and comparisons jump to the routines of `shared_routines`
+    // instead of being inlined.
+    shared_routines: bool,
 }
 
 impl FileData {
@@ -11,8 +14,14 @@ impl FileData {
             static_index_label_gen: StaticIndexLabelGen::new(filename.to_owned()),
             file_label_gen: FileLabelGen::new(filename.to_owned()),
             function_label_gen: FunctionLabelGen::new(format!("{filename}.")),
+            shared_routines: false,
         }
     }
+
+    pub fn with_shared_routines(mut self, shared_routines: bool) -> FileData {
+        self.shared_routines = shared_routines;
+        self
+    }
 }
 
 pub fn codegen_instruction(i: VMInstruction, file_data: &mut FileData) -> String {
@@ -20,6 +29,7 @@ pub fn codegen_instruction(i: VMInstruction, file_data: &mut FileData) -> String
         static_index_label_gen,
         file_label_gen,
         function_label_gen,
+        shared_routines,
     } = file_data;
     let mut s = format!("// {:?}\n", i);
     s.push_str(&match i {
@@ -58,10 +68,17 @@ pub fn codegen_instruction(i: VMInstruction, file_data: &mut FileData) -> String
         VMInstruction::Function(name, locals_count) => {
             function_instruction(name, locals_count, function_label_gen)
         }
+        VMInstruction::Call(name, args_count) if *shared_routines => {
+            shared_call_instruction(args_count, name, function_label_gen)
+        }
         VMInstruction::Call(name, args_count) => {
             call_instruction(args_count, name, function_label_gen)
         }
+        VMInstruction::Return if *shared_routines => "@$$RETURN\n0;JMP\n".to_owned(),
         VMInstruction::Return => return_instruction(),
+        c @ (VMInstruction::Eq | VMInstruction::Gt | VMInstruction::Lt) if *shared_routines => {
+            shared_cmp_instruction(c, file_label_gen)
+        }
         c @ (VMInstruction::Eq | VMInstruction::Gt | VMInstruction::Lt) => {
             cmp_instruction(c, file_label_gen)
         }
@@ -71,6 +88,53 @@ pub fn codegen_instruction(i: VMInstruction, file_data: &mut FileData) -> String
     s
 }
 
+// Shorter code for two instructions which are translated together, or None if they aren't
+// one of the pairs this knows about: a push followed by a pop, which skips the stack, and
+// a constant followed by `add`, `sub`, `and` or `or`, which uses the constant directly.
+pub fn codegen_pair(
+    first: &VMInstruction,
+    second: &VMInstruction,
+    file_data: &mut FileData,
+) -> Option<String> {
+    let statics = &file_data.static_index_label_gen;
+    let code = match (first, second) {
+        (VMInstruction::Push(from, from_idx), VMInstruction::Pop(to, to_idx)) => {
+            let value = from.set_d_to_value_at_index(*from_idx, statics);
+            match to.direct_address(*to_idx, statics) {
+                Some(address) => format!("{value}\n@{address}\nM=D\n"),
+                None => format!(
+                    "{}\n\
+                     @R13\n\
+                     M=D\n\
+                     {value}\n\
+                     @R13\n\
+                     A=M\n\
+                     M=D\n",
+                    to.set_d_to_target_address(*to_idx, statics)
+                ),
+            }
+        }
+        (VMInstruction::Push(Segment::Constant, c), op) => {
+            let with_constant = |operation: &str| format!("@{c}\nD=A\n@SP\nA=M-1\n{operation}\n");
+            // Adding 1 and the like don't need the constant in D.
+            let in_place = |operation: &str| format!("@SP\nA=M-1\n{operation}\n");
+            match (op, c) {
+                (VMInstruction::Add | VMInstruction::Sub | VMInstruction::Or, 0) => String::new(),
+                (VMInstruction::Add, 1) => in_place("M=M+1"),
+                (VMInstruction::Sub, 1) => in_place("M=M-1"),
+                (VMInstruction::And, 0) => in_place("M=0"),
+                (VMInstruction::Add, _) => with_constant("M=D+M"),
+                (VMInstruction::Sub, _) => with_constant("M=M-D"),
+                (VMInstruction::And, _) => with_constant("M=D&M"),
+                (VMInstruction::Or, _) => with_constant("M=D|M"),
+                _ => return None,
+            }
+        }
+        _ => return None,
+    };
+    Some(format!("// {:?}\n// {:?}\n{}", first, second, code))
+}
+
 fn return_instruction() -> String {
     "@LCL\n\
      D=M\n\
@@ -119,9 +183,92 @@ fn return_instruction() -> String {
 }
 
 pub fn init_code(file_data: &mut FileData) -> String {
+    let call = match file_data.shared_routines {
+        true => {
+            shared_call_instruction(0, "Sys.init".to_owned(), &mut file_data.function_label_gen)
+        }
+        false => call_instruction(0, "Sys.init".to_owned(), &mut file_data.function_label_gen),
+    };
+    format!("@256\nD=A\n@SP\nM=D\n{}", call)
+}
+
+// The routines which calls, returns and comparisons jump to when translating for size, so
+// that each of them takes a handful of instructions instead of up to 45. They're emitted
+// once per program, right after the bootstrap code.
+//
+// `$$CALL` takes the function's address in R13, the number of arguments plus 5 in R14, and
+// the return address in D. `$$EQ`, `$$GT` and `$$LT` take the return address in D, which
+// they keep in R15. `$$RETURN` is the usual return, using R13 and R14.
+pub fn shared_routines() -> String {
+    let mut out = "($$CALL)\n\
+                   @SP\n\
+                   A=M\n\
+                   M=D\n"
+        .to_owned();
+    for pointer in ["LCL", "ARG", "THIS", "THAT"] {
+        out.push_str(&format!("@{pointer}\nD=M\n@SP\nAM=M+1\nM=D\n"));
+    }
+    out.push_str(
+        "@SP\n\
+         MD=M+1\n\
+         @LCL\n\
+         M=D\n\
+         @R14\n\
+         D=D-M\n\
+         @ARG\n\
+         M=D\n\
+         @R13\n\
+         A=M\n\
+         0;JMP\n",
+    );
+    out.push_str("($$RETURN)\n");
+    out.push_str(&return_instruction());
+    for (name, jump) in [("EQ", "JEQ"), ("GT", "JGT"), ("LT", "JLT")] {
+        out.push_str(&format!(
+            "($${name})\n\
+             @R15\n\
+             M=D\n\
+             @SP\n\
+             AM=M-1\n\
+             D=M\n\
+             A=A-1\n\
+             D=M-D\n\
+             M=-1\n\
+             @$${name}.TRUE\n\
+             D;{jump}\n\
+             @SP\n\
+             A=M-1\n\
+             M=0\n\
+             ($${name}.TRUE)\n\
+             @R15\n\
+             A=M\n\
+             0;JMP\n"
+        ));
+    }
+    out
+}
+
+fn shared_call_instruction(
+    args_count: u16,
+    fn_name: String,
+    fn_lg: &mut FunctionLabelGen,
+) -> String {
+    let ret_label = fn_lg.next_return();
+    let offset = args_count + 5;
     format!(
-        "@256\nD=A\n@SP\nM=D\n{}",
-        call_instruction(0, "Sys.init".to_owned(), &mut file_data.function_label_gen)
+        "@{fn_name}\n\
+         D=A\n\
+         @R13\n\
+         M=D\n\
+         @{offset}\n\
+         D=A\n\
+         @R14\n\
+         M=D\n\
+         @{ret_label}\n\
+         D=A\n\
+         @$$CALL\n\
+         0;JMP\n\
+         ({ret_label})\n"
     )
 }
 
@@ -222,6 +369,23 @@ fn cmp_instruction(op: VMInstruction, label_gen: &mut FileLabelGen) -> String {
     )
 }
 
+fn shared_cmp_instruction(op: VMInstruction, label_gen: &mut FileLabelGen) -> String {
+    let ret_label = label_gen.next_cmp_label();
+    let routine = match op {
+        VMInstruction::Eq => "$$EQ",
+        VMInstruction::Lt => "$$LT",
+        VMInstruction::Gt => "$$GT",
+        _ => unreachable!(),
+    };
+    format!(
+        "@{ret_label}\n\
+         D=A\n\
+         @{routine}\n\
+         0;JMP\n\
+         ({ret_label})\n"
+    )
+}
+
 fn two_arg_arith_logic_instruction(op: VMInstruction) -> String {
     let operation = match op {
         VMInstruction::Add => "M=D+M",
@@ -297,7 +461,7 @@ impl Segment {
 
     fn set_d_to_target_address(&self, idx: u16, static_indexing: &StaticIndexLabelGen) -> String {
         match self {
-            Segment::Constant => unimplemented!(),
+            Segment::Constant => unreachable!("the parser rejects `pop constant`"),
             Segment::Local => indirect_address_get("LCL", idx),
             Segment::Argument => indirect_address_get("ARG", idx),
             Segment::This => indirect_address_get("THIS", idx),
@@ -316,6 +480,16 @@ impl Segment {
             }
         }
     }
+
+    // The address of a segment which doesn't go through a base pointer.
+    fn direct_address(&self, idx: u16, static_indexing: &StaticIndexLabelGen) -> Option<String> {
+        match self {
+            Segment::Temp => Some((idx + 5).to_string()),
+            Segment::Pointer => Some((idx + 3).to_string()),
+            Segment::Static => Some(static_indexing.nth(idx as usize)),
+            _ => None,
+        }
+    }
 }
 
 fn indirect_address_get(base: &str, offset: u16) -> String {
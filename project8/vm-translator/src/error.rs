@@ -0,0 +1,144 @@
+use std::fmt;
+
+use assembler::snippet::{self, Snippet};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    UnknownCommand,
+    WrongArgumentCount,
+    UnknownSegment,
+    InvalidIndex,
+    IndexOutOfRange,
+    PopConstant,
+    InvalidName,
+    DuplicateFunction,
+    UnreadableFile,
+    // Only for whole programs with a bootstrap, which calls `Sys.init`.
+    MissingSysInit,
+    // Found by the `verify` module, following every path through a function.
+    StackUnderflow,
+    InconsistentStackHeight,
+    ValuesLeftOnStack,
+    UndefinedLabel,
+}
+
+// A single problem in a `.vm` file, pointing at the offending text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VmError {
+    pub kind: ErrorKind,
+    pub file: Option<String>,
+    // See `Snippet`. The line is 0 for errors about the whole program.
+    pub line: usize,
+    pub column: usize,
+    pub text: String,
+    pub source_line: String,
+    pub note: Option<String>,
+}
+
+impl VmError {
+    // `text` has to be a subslice of `source_line`, see `snippet::column`.
+    pub fn new(kind: ErrorKind, line: usize, source_line: &str, text: &str) -> VmError {
+        VmError {
+            kind,
+            file: None,
+            line,
+            column: snippet::column(source_line, text),
+            text: text.to_owned(),
+            source_line: source_line.to_owned(),
+            note: None,
+        }
+    }
+
+    // An error about the program as a whole rather than about any line of it.
+    pub fn without_location(kind: ErrorKind, text: &str) -> VmError {
+        VmError {
+            kind,
+            file: None,
+            line: 0,
+            column: 0,
+            text: text.to_owned(),
+            source_line: String::new(),
+            note: None,
+        }
+    }
+
+    pub fn with_note(mut self, note: String) -> VmError {
+        self.note = Some(note);
+        self
+    }
+
+    pub fn with_file(mut self, file: &str) -> VmError {
+        self.file = Some(file.to_owned());
+        self
+    }
+
+    pub fn message(&self) -> String {
+        match self.kind {
+            ErrorKind::UnknownCommand => format!("unknown command `{}`", self.text),
+            ErrorKind::WrongArgumentCount if self.text.is_empty() => {
+                "missing an argument".to_owned()
+            }
+            ErrorKind::WrongArgumentCount => format!("unexpected argument `{}`", self.text),
+            ErrorKind::UnknownSegment => format!("unknown segment `{}`", self.text),
+            ErrorKind::InvalidIndex => format!("`{}` isn't a valid number", self.text),
+            ErrorKind::IndexOutOfRange => format!("index `{}` is out of range", self.text),
+            ErrorKind::PopConstant => "can't pop into the constant segment".to_owned(),
+            ErrorKind::InvalidName => format!("invalid name `{}`", self.text),
+            ErrorKind::DuplicateFunction => format!("function `{}` is defined twice", self.text),
+            ErrorKind::UnreadableFile => format!("couldn't read file {}", self.text),
+            ErrorKind::MissingSysInit => {
+                "there's no `Sys.init` for the bootstrap code to call".to_owned()
+            }
+            ErrorKind::StackUnderflow => format!("stack underflow at `{}`", self.text),
+            ErrorKind::InconsistentStackHeight => {
+                format!(
+                    "the stack height at label `{}` depends on the path",
+                    self.text
+                )
+            }
+            ErrorKind::ValuesLeftOnStack => "values are left on the stack at `return`".to_owned(),
+            ErrorKind::UndefinedLabel => format!("undefined label `{}`", self.text),
+        }
+    }
+
+    pub fn snippet(&self) -> Snippet<'_> {
+        Snippet {
+            file: self.file.as_deref(),
+            line: self.line,
+            column: self.column,
+            text: &self.text,
+            source_line: &self.source_line,
+        }
+    }
+
+    // Renders the error like rustc does, see `Snippet::render`. Errors about the whole
+    // program only get the file, if any.
+    pub fn render(&self) -> String {
+        if self.line == 0 {
+            let mut out = format!("error: {}\n", self.message());
+            if let Some(file) = &self.file {
+                out.push_str(&format!(" --> {}\n", file));
+            }
+            if let Some(note) = &self.note {
+                out.push_str(&format!("  = note: {}\n", note));
+            }
+            return out;
+        }
+        self.snippet()
+            .render("error", &self.message(), self.note.as_deref())
+    }
+}
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.line == 0 {
+            return match &self.file {
+                Some(file) => write!(f, "{}: {}", file, self.message()),
+                None => write!(f, "{}", self.message()),
+            };
+        }
+        write!(f, "{}: {}", self.snippet(), self.message())
+    }
+}
+
+impl std::error::Error for VmError {}
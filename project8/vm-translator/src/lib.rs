@@ -31,47 +31,233 @@ pub enum Segment {
     Temp,
 }
 
+pub use error::{ErrorKind, VmError};
+
 pub mod codegen;
+pub mod error;
+pub mod optimize;
 pub mod parser;
+pub mod verify;
+
+// A `.vm` file of a program translated as a whole.
+pub struct SourceFile {
+    // The file name without the extension, which scopes its static variables.
+    pub name: String,
+    // Where the file came from, for error messages.
+    pub path: String,
+    pub src: String,
+}
+
+// How a program gets translated by `compile_program`.
+#[derive(Debug, Clone, Copy)]
+pub struct Options {
+    // Starts the output with code which sets SP up and calls `Sys.init`.
+    pub bootstrap: bool,
+    // See the `optimize` module.
+    pub optimize: bool,
+    // See `codegen::shared_routines`.
+    pub shared_routines: bool,
+    // Runs `verify::verify_program` on the program first.
+    pub verify: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            bootstrap: true,
+            optimize: false,
+            shared_routines: false,
+            verify: true,
+        }
+    }
+}
 
-pub fn compile_file(
-    reader: impl io::BufRead,
+// Translates every file of a program into a single assembly file. All the files are parsed
+// before anything is written, so that the bootstrap code is only emitted for a program
+// which defines exactly one `Sys.init`.
+pub fn compile_program(
+    files: &[SourceFile],
     writer: &mut impl io::Write,
-    filename: &str,
-    generate_bootstrap: bool,
-) {
-    let mut file_data = codegen::FileData::new(filename);
-    let mut lines = reader.lines();
+    options: Options,
+) -> Result<(), Vec<VmError>> {
+    let mut errors = Vec::new();
+    let mut parsed = Vec::new();
+    for file in files {
+        match parser::parse_source(&file.src) {
+            Ok(instructions) => parsed.push(instructions),
+            Err(e) => {
+                errors.extend(e.into_iter().map(|e| e.with_file(&file.path)));
+                parsed.push(Vec::new());
+            }
+        }
+    }
 
-    if generate_bootstrap {
-        write!(writer, "{}", codegen::init_code(&mut file_data)).unwrap();
+    if options.bootstrap {
+        let mut sys_init: Option<(&str, usize)> = None;
+        for (file, instructions) in files.iter().zip(&parsed) {
+            for (line, instruction) in instructions {
+                if !matches!(instruction, VMInstruction::Function(name, _) if name == "Sys.init") {
+                    continue;
+                }
+                match sys_init {
+                    None => sys_init = Some((&file.path, *line)),
+                    Some((first_file, first_line)) => {
+                        let source_line = file.src.lines().nth(line - 1).unwrap_or_default();
+                        let start = source_line.find("Sys.init").unwrap_or(0);
+                        errors.push(
+                            VmError::new(
+                                ErrorKind::DuplicateFunction,
+                                *line,
+                                source_line,
+                                &source_line[start..start + "Sys.init".len()],
+                            )
+                            .with_file(&file.path)
+                            .with_note(format!("first defined at {}:{}", first_file, first_line)),
+                        );
+                    }
+                }
+            }
+        }
+        if sys_init.is_none() && errors.is_empty() {
+            errors.push(
+                VmError::without_location(ErrorKind::MissingSysInit, "Sys.init").with_note(
+                    "use --no-bootstrap to translate code which doesn't start at Sys.init"
+                        .to_owned(),
+                ),
+            );
+        }
+    }
+    if options.verify && errors.is_empty() {
+        let program: Vec<_> = files
+            .iter()
+            .zip(&parsed)
+            .map(|(f, i)| (f, &i[..]))
+            .collect();
+        errors.extend(verify::verify_program(&program, options.bootstrap));
+    }
+    if !errors.is_empty() {
+        return Err(errors);
     }
 
-    while let Some(Ok(line)) = lines.next() {
-        let line = line.trim();
-        let line = &line[..line.find('/').unwrap_or(line.len())].trim();
-        if line.is_empty() {
+    let mut out = String::new();
+    let mut bootstrap = options.bootstrap;
+    for (file, instructions) in files.iter().zip(parsed) {
+        let mut file_data =
+            codegen::FileData::new(&file.name).with_shared_routines(options.shared_routines);
+        if bootstrap {
+            out.push_str(&codegen::init_code(&mut file_data));
+            if options.shared_routines {
+                out.push_str(&codegen::shared_routines());
+            }
+            bootstrap = false;
+        }
+        let instructions = instructions.into_iter().map(|(_, i)| i);
+        if options.optimize {
+            out.push_str(&optimize::codegen_instructions(
+                instructions.collect(),
+                &mut file_data,
+            ));
+        } else {
+            for instruction in instructions {
+                out.push_str(&codegen::codegen_instruction(instruction, &mut file_data));
+            }
+        }
+    }
+    // Without the bootstrap, the code may start running at the first instruction, so the
+    // routines go at the end instead.
+    if options.shared_routines && !options.bootstrap {
+        out.push_str(&codegen::shared_routines());
+    }
+    if options.optimize {
+        out = optimize::peephole(&out);
+    }
+    write!(writer, "{}", out).unwrap();
+    Ok(())
+}
+
+// Translates a single `.vm` file, without bootstrap code like the course's translator does
+// for one file, returning the assembly or every error found in it.
+pub fn compile_file(path: &path::Path) -> Result<String, Vec<VmError>> {
+    let display = path.display().to_string();
+    let src = fs::read_to_string(path).map_err(|_| {
+        vec![VmError::without_location(
+            ErrorKind::UnreadableFile,
+            &display,
+        )]
+    })?;
+    let file = SourceFile {
+        name: path
+            .file_stem()
+            .map_or("noname".into(), |s| s.to_string_lossy().into_owned()),
+        path: display,
+        src,
+    };
+    let options = Options {
+        bootstrap: false,
+        ..Options::default()
+    };
+    let mut out = Vec::new();
+    compile_program(&[file], &mut out, options)?;
+    Ok(String::from_utf8(out).unwrap())
+}
+
+// Expands directories among the inputs into the `.vm` files in them, sorted by name so the
+// output doesn't depend on the order the file system lists them in.
+pub fn expand_inputs(inputs: &[path::PathBuf]) -> Result<Vec<path::PathBuf>, String> {
+    let mut files = Vec::new();
+    for input in inputs {
+        if !input.is_dir() {
+            files.push(input.clone());
             continue;
         }
-        let parsed = parser::parse_instruction(line);
-        write!(
-            writer,
-            "{}",
-            codegen::codegen_instruction(parsed, &mut file_data,)
-        )
-        .unwrap();
+        let entries = fs::read_dir(input)
+            .map_err(|_| format!("Couldn't read directory: {}", input.display()))?;
+        let mut vm_files: Vec<path::PathBuf> = entries
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| p.is_file() && p.extension().is_some_and(|e| e == "vm"))
+            .collect();
+        if vm_files.is_empty() {
+            return Err(format!("No .vm files in directory: {}", input.display()));
+        }
+        vm_files.sort();
+        files.extend(vm_files);
+    }
+    Ok(files)
+}
+
+// Where the output goes without `-o`: `Dir/Dir.asm` for a directory, `Xxx.asm` for `Xxx.vm`.
+pub fn default_output(input: &path::Path) -> path::PathBuf {
+    if input.is_dir() {
+        let name = input
+            .canonicalize()
+            .ok()
+            .and_then(|p| p.file_name().map(|n| n.to_owned()))
+            .unwrap_or_else(|| "out".into());
+        let mut file = path::PathBuf::from(name);
+        file.set_extension("asm");
+        input.join(file)
+    } else {
+        input.with_extension("asm")
     }
 }
 
 use std::fs;
 use std::io;
 use std::path;
-pub fn parse_args() -> (Vec<path::PathBuf>, Box<dyn io::Write>, bool, bool, bool) {
+// The inputs as given, the output file (None for standard output), whether to read from
+// standard input, the translation options, and whether to stop right away, like after `-h`.
+pub fn parse_args() -> (
+    Vec<path::PathBuf>,
+    Option<path::PathBuf>,
+    bool,
+    Options,
+    bool,
+) {
     let mut args = std::env::args();
     let mut output_file: Option<path::PathBuf> = None;
     let mut input_file_paths: Vec<path::PathBuf> = Vec::new();
     let mut read_from_stdin = false;
-    let mut no_bootstrap = false;
+    let mut options = Options::default();
     let mut terminate_immiediately = false;
     args.next();
     while let Some(arg) = args.next() {
@@ -88,7 +274,16 @@ pub fn parse_args() -> (Vec<path::PathBuf>, Box<dyn io::Write>, bool, bool, bool
                 read_from_stdin = true;
             }
             "-nb" | "--no-bootstrap" => {
-                no_bootstrap = true;
+                options.bootstrap = false;
+            }
+            "-O" | "--optimize" => {
+                options.optimize = true;
+            }
+            "-Os" | "--optimize-size" => {
+                options.shared_routines = true;
+            }
+            "--no-verify" => {
+                options.verify = false;
             }
             _ => {
                 input_file_paths.push(path::PathBuf::from(arg));
@@ -96,28 +291,142 @@ pub fn parse_args() -> (Vec<path::PathBuf>, Box<dyn io::Write>, bool, bool, bool
         }
     }
 
-    let output_writer: Box<dyn io::Write> = match output_file {
-        Some(x) => Box::new(io::BufWriter::new(fs::File::create(x).unwrap())),
-        None => Box::new(io::stdout().lock()),
+    // Without `-o`, the output is named after the first input, as the course's translator does.
+    let output_file = match output_file {
+        Some(x) if x.as_os_str() == "-" => None,
+        Some(x) => Some(x),
+        None if read_from_stdin && input_file_paths.is_empty() => None,
+        None => input_file_paths.first().map(|p| default_output(p)),
     };
 
     (
         input_file_paths,
-        output_writer,
+        output_file,
         read_from_stdin,
-        no_bootstrap,
+        options,
         terminate_immiediately,
     )
 }
 
 pub const USAGE: &str = r#"Usage:
-    vm-translator <file> [options]
+    vm-translator <file.vm|dir>... [options]
+
+Translates the given files, and every .vm file in the given directories, into
+a single program. Without -o, `Dir/` is translated into `Dir/Dir.asm` and
+`Xxx.vm` into `Xxx.asm`.
 
 Options:
-  -o <file>, --output <file>            Outputs to [fn] instead of standard output
+  -o <file>, --output <file>            Outputs to <file>, or to standard output for `-`
   -si,       --stdin                    Reads from standard input instead of the given file.
                                         The <file> argument can be omitted.
   -nb,       --no-bootstrap             Stops the translator from emitting bootstrap code
                                         at the beginning of output.
+  -O,        --optimize                 Translates pushes followed by pops and constants
+                                        followed by arithmetic together, and removes
+                                        redundant stack pointer updates.
+  -Os,       --optimize-size            Makes calls, returns and comparisons jump to
+                                        routines shared by the whole program instead of
+                                        inlining them, for programs which don't fit in ROM.
+                                        Can be combined with -O. Either prints the ROM
+                                        usage before and after.
+             --no-verify                Translates functions even if their stack usage or
+                                        segment indices don't check out, for code which
+                                        relies on them working anyway.
   -h,        --help                     Prints help message
 "#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Compiles `src` as `name.vm` with `compile_file`.
+    fn compile(name: &str, src: &str) -> Result<String, Vec<VmError>> {
+        let dir = std::env::temp_dir().join(format!("vm-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(format!("{}.vm", name));
+        fs::write(&path, src).unwrap();
+        let compiled = compile_file(&path);
+        fs::remove_dir_all(&dir).unwrap();
+        compiled
+    }
+
+    #[test]
+    fn compiles_a_file_without_bootstrap() {
+        let asm = compile("Ok", "push constant 7\npush constant 8\nadd\n").unwrap();
+        assert!(asm.starts_with("// Push(Constant, 7)\n@7\n"), "{}", asm);
+        assert!(!asm.contains("Sys.init"));
+    }
+
+    #[test]
+    fn reports_every_bad_line() {
+        let errors = compile("Bad", "push constant 1\npop constant 0\npush temp 8\n").unwrap_err();
+        let found: Vec<(ErrorKind, usize, bool)> = errors
+            .iter()
+            .map(|e| (e.kind, e.line, e.file.as_ref().unwrap().ends_with("Bad.vm")))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (ErrorKind::PopConstant, 2, true),
+                (ErrorKind::IndexOutOfRange, 3, true)
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_unreadable_files() {
+        let errors = compile_file(path::Path::new("/nonexistent/Missing.vm")).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].kind, ErrorKind::UnreadableFile);
+    }
+
+    #[test]
+    fn shared_routines_shrink_calls_and_comparisons() {
+        let src = "\
+function Sys.init 0
+push constant 1
+push constant 2
+call Main.max 2
+push constant 3
+push constant 4
+call Main.max 2
+label HALT
+goto HALT
+function Main.max 0
+push argument 0
+push argument 1
+gt
+if-goto FIRST
+push argument 1
+return
+label FIRST
+push argument 0
+return
+";
+        let compile = |shared_routines| {
+            let files = [SourceFile {
+                name: "Main".to_owned(),
+                path: "Main.vm".to_owned(),
+                src: src.to_owned(),
+            }];
+            let mut out = Vec::new();
+            let options = Options {
+                shared_routines,
+                ..Options::default()
+            };
+            compile_program(&files, &mut out, options).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let (plain, shared) = (compile(false), compile(true));
+        for routine in ["($$CALL)", "($$RETURN)", "($$EQ)", "($$GT)", "($$LT)"] {
+            assert!(!plain.contains(routine));
+            assert_eq!(shared.matches(routine).count(), 1, "{}", routine);
+        }
+        assert!(
+            optimize::count_instructions(&shared) < optimize::count_instructions(&plain),
+            "{} instructions with -Os, {} without",
+            optimize::count_instructions(&shared),
+            optimize::count_instructions(&plain)
+        );
+    }
+}
@@ -1,49 +1,105 @@
 use std::{
     fs,
-    io::{self, BufReader},
+    io::{self, Read},
     process::ExitCode,
 };
-use vm_translator::{compile_file, parse_args, USAGE};
+use vm_translator::{
+    compile_program, expand_inputs, optimize::count_instructions, parse_args, Options, SourceFile,
+    USAGE,
+};
 
 fn main() -> ExitCode {
-    let (
-        input_file_paths,
-        mut output_writer,
-        read_from_stdin,
-        no_bootstrap,
-        terminate_immiediately,
-    ) = parse_args();
+    let (input_paths, output_file, read_from_stdin, options, terminate_immiediately) = parse_args();
 
     if terminate_immiediately {
         return ExitCode::SUCCESS;
     }
 
-    let mut is_first = !no_bootstrap;
+    let input_file_paths = match expand_inputs(&input_paths) {
+        Ok(paths) => paths,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    };
 
+    let mut files = Vec::new();
     for file_path in input_file_paths.iter() {
         let filename = file_path.file_name().unwrap().to_str().unwrap();
         let filename = filename.split('.').next().unwrap();
 
-        let mut input_reader = BufReader::new(match fs::File::open(file_path) {
-            Err(_) => {
-                println!("Couldn't open file: {}", file_path.display());
-                return ExitCode::FAILURE;
-            }
-            Ok(x) => x,
+        let Ok(src) = fs::read_to_string(file_path) else {
+            eprintln!("Couldn't open file: {}", file_path.display());
+            return ExitCode::FAILURE;
+        };
+        files.push(SourceFile {
+            name: filename.to_owned(),
+            path: file_path.display().to_string(),
+            src,
         });
-
-        compile_file(&mut input_reader, &mut output_writer, filename, is_first);
-        is_first = false;
     }
 
     if input_file_paths.is_empty() {
         if read_from_stdin {
-            compile_file(io::stdin().lock(), &mut output_writer, "noname", is_first);
+            let mut src = String::new();
+            io::stdin().read_to_string(&mut src).unwrap();
+            files.push(SourceFile {
+                name: "noname".to_owned(),
+                path: "<stdin>".to_owned(),
+                src,
+            });
         } else {
             print!("{}", USAGE);
             return ExitCode::FAILURE;
         }
     }
 
+    // Only created once everything translated, so a failed translation doesn't leave
+    // an empty file behind.
+    let mut output = Vec::new();
+    if let Err(errors) = compile_program(&files, &mut output, options) {
+        for e in &errors {
+            eprintln!("{}", e.render());
+        }
+        eprintln!(
+            "error: could not translate due to {} previous error{}",
+            errors.len(),
+            if errors.len() == 1 { "" } else { "s" }
+        );
+        return ExitCode::FAILURE;
+    }
+
+    if options.optimize || options.shared_routines {
+        let mut unoptimized = Vec::new();
+        let plain = Options {
+            optimize: false,
+            shared_routines: false,
+            ..options
+        };
+        if compile_program(&files, &mut unoptimized, plain).is_ok() {
+            let before = count_instructions(&String::from_utf8_lossy(&unoptimized));
+            let after = count_instructions(&String::from_utf8_lossy(&output));
+            eprintln!(
+                "ROM usage: {} instructions down to {} ({:.1}% fewer), {} of 32768",
+                before,
+                after,
+                100.0 * before.saturating_sub(after) as f64 / before.max(1) as f64,
+                after
+            );
+        }
+    }
+
+    let written = match &output_file {
+        Some(path) => fs::write(path, &output),
+        None => io::Write::write_all(&mut io::stdout().lock(), &output),
+    };
+    if written.is_err() {
+        eprintln!(
+            "Couldn't write output: {}",
+            output_file.map_or("<stdout>".to_owned(), |p| p.display().to_string())
+        );
+        return ExitCode::FAILURE;
+    }
+
     ExitCode::SUCCESS
 }
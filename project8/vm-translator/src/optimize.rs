@@ -0,0 +1,105 @@
+// The `-O` translation: pairs of VM instructions get translated together where that's
+// shorter, and the assembly is then cleaned up by a peephole pass.
+
+use crate::codegen::{self, FileData};
+use crate::VMInstruction;
+
+pub fn codegen_instructions(instructions: Vec<VMInstruction>, file_data: &mut FileData) -> String {
+    let mut out = String::new();
+    let mut instructions = instructions.into_iter().peekable();
+    while let Some(instruction) = instructions.next() {
+        if let Some(next) = instructions.peek() {
+            if let Some(code) = codegen::codegen_pair(&instruction, next, file_data) {
+                out.push_str(&code);
+                instructions.next();
+                continue;
+            }
+        }
+        out.push_str(&codegen::codegen_instruction(instruction, file_data));
+    }
+    out
+}
+
+// Sequences of instructions and what they're replaced with, applied whenever the last
+// instructions written match one of them. Labels are kept, so nothing jumps into the middle.
+const RULES: &[(&[&str], &[&str])] = &[
+    // A push followed by a pop, which leaves SP as it was and A pointing at the pushed value.
+    (&["@SP", "M=M+1", "@SP", "AM=M-1"], &["@SP", "A=M"]),
+    // Reading back the value which was just written.
+    (
+        &["@SP", "A=M", "M=D", "@SP", "A=M", "D=M"],
+        &["@SP", "A=M", "M=D"],
+    ),
+];
+
+pub fn peephole(asm: &str) -> String {
+    // Comments are kept in place, but don't keep instructions around them from matching.
+    let mut lines: Vec<Option<&str>> = Vec::new();
+    let mut instructions: Vec<usize> = Vec::new();
+    for line in asm.lines() {
+        let line = line.trim();
+        lines.push(Some(line));
+        if line.is_empty() || line.starts_with("//") {
+            continue;
+        }
+        instructions.push(lines.len() - 1);
+        while let Some(&(pattern, replacement)) = RULES.iter().find(|(pattern, _)| {
+            instructions.len() >= pattern.len()
+                && instructions[instructions.len() - pattern.len()..]
+                    .iter()
+                    .zip(pattern.iter())
+                    .all(|(&i, p)| lines[i] == Some(p))
+        }) {
+            for i in instructions.split_off(instructions.len() - pattern.len()) {
+                lines[i] = None;
+            }
+            for &line in replacement {
+                lines.push(Some(line));
+                instructions.push(lines.len() - 1);
+            }
+        }
+    }
+    let mut out = String::new();
+    for line in lines.into_iter().flatten() {
+        out.push_str(line);
+        out.push('\n');
+    }
+    out
+}
+
+// Hack instructions in the assembly, without labels and comments.
+pub fn count_instructions(asm: &str) -> usize {
+    asm.lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with("//") && !l.starts_with('('))
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn push_then_pop_keeps_sp() {
+        assert_eq!(peephole("@SP\nM=M+1\n@SP\nAM=M-1\n"), "@SP\nA=M\n");
+    }
+
+    #[test]
+    fn replacements_can_match_again() {
+        let asm = "@SP\nA=M\nM=D\n@SP\nM=M+1\n@SP\nAM=M-1\nD=M\n";
+        assert_eq!(peephole(asm), "@SP\nA=M\nM=D\n");
+    }
+
+    #[test]
+    fn comments_stay_and_labels_block_rules() {
+        let asm = "@SP\nM=M+1\n// Pop(Local, 0)\n@SP\nAM=M-1\n";
+        assert_eq!(peephole(asm), "// Pop(Local, 0)\n@SP\nA=M\n");
+        let asm = "@SP\nM=M+1\n(LOOP)\n@SP\nAM=M-1\n";
+        assert_eq!(peephole(asm), asm);
+    }
+
+    #[test]
+    fn counts_instructions_only() {
+        assert_eq!(count_instructions("// Add\n(LOOP)\n@SP\n\n  AM=M-1\n"), 2);
+    }
+}
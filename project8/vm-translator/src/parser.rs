@@ -1,4 +1,5 @@
 use super::{Segment, VMInstruction};
+use crate::error::{ErrorKind, VmError};
 use std::str::FromStr;
 
 impl FromStr for Segment {
@@ -18,29 +19,109 @@ impl FromStr for Segment {
     }
 }
 
-pub fn parse_instruction(line: &str) -> VMInstruction {
-    let instruction_elements = line.trim().split(' ').collect::<Vec<&str>>();
-
-    match instruction_elements[0] {
-        "push" => VMInstruction::Push(
-            instruction_elements[1].parse().unwrap(),
-            instruction_elements[2].parse().unwrap(),
-        ),
-        "pop" => VMInstruction::Pop(
-            instruction_elements[1].parse().unwrap(),
-            instruction_elements[2].parse().unwrap(),
-        ),
-        "label" => VMInstruction::Label(instruction_elements[1].to_owned()),
-        "if-goto" => VMInstruction::IfGoto(instruction_elements[1].to_owned()),
-        "goto" => VMInstruction::Goto(instruction_elements[1].to_owned()),
-        "function" => VMInstruction::Function(
-            instruction_elements[1].to_owned(),
-            instruction_elements[2].parse().unwrap(),
-        ),
-        "call" => VMInstruction::Call(
-            instruction_elements[1].to_owned(),
-            instruction_elements[2].parse().unwrap(),
-        ),
+fn usage(command: &str) -> String {
+    let args = match command {
+        "push" | "pop" => " <segment> <index>",
+        "function" => " <name> <locals count>",
+        "call" => " <name> <arguments count>",
+        "label" | "goto" | "if-goto" => " <label>",
+        _ => "",
+    };
+    format!("usage: `{}{}`", command, args)
+}
+
+// Labels and function names are made of letters, digits, `_`, `.`, `:` and `$`, and
+// don't start with a digit.
+fn is_valid_name(name: &str) -> bool {
+    !name.starts_with(|c: char| c.is_ascii_digit())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "_.:$".contains(c))
+}
+
+// Parses one line of a `.vm` file, None if there's nothing but whitespace and comments.
+// `line_no` is only used for errors.
+pub fn parse_instruction(line: &str, line_no: usize) -> Result<Option<VMInstruction>, VmError> {
+    let error = |kind, text| VmError::new(kind, line_no, line, text);
+    let code = &line[..line.find("//").unwrap_or(line.len())];
+    let mut words = code.split_whitespace();
+    let Some(command) = words.next() else {
+        return Ok(None);
+    };
+    let args: Vec<&str> = words.collect();
+
+    let arg_count = match command {
+        "push" | "pop" | "function" | "call" => 2,
+        "label" | "goto" | "if-goto" => 1,
+        "return" | "add" | "sub" | "neg" | "eq" | "gt" | "lt" | "and" | "or" | "not" => 0,
+        _ => return Err(error(ErrorKind::UnknownCommand, command)),
+    };
+    if args.len() != arg_count {
+        let text = match args.get(arg_count) {
+            Some(extra) => extra,
+            None => &code[code.trim_end().len()..],
+        };
+        return Err(error(ErrorKind::WrongArgumentCount, text).with_note(usage(command)));
+    }
+
+    let name = |i: usize| -> Result<String, VmError> {
+        if is_valid_name(args[i]) {
+            Ok(args[i].to_owned())
+        } else {
+            Err(error(ErrorKind::InvalidName, args[i]))
+        }
+    };
+    // Indices and counts end up in A-instructions, which only hold 15 bits.
+    let number = |i: usize| -> Result<u16, VmError> {
+        let text = args[i];
+        if !text.chars().all(|c| c.is_ascii_digit()) {
+            return Err(error(ErrorKind::InvalidIndex, text));
+        }
+        match text.parse::<u16>() {
+            Ok(n) if n <= 32767 => Ok(n),
+            _ => Err(error(ErrorKind::IndexOutOfRange, text)
+                .with_note("the largest index is 32767".to_owned())),
+        }
+    };
+    let segment_index = || -> Result<(Segment, u16), VmError> {
+        let segment: Segment = args[0]
+            .parse()
+            .map_err(|_| error(ErrorKind::UnknownSegment, args[0]))?;
+        let index = number(1)?;
+        let size = match segment {
+            Segment::Temp => Some("temp is 8 words long, R5 to R12"),
+            Segment::Pointer => Some("pointer is 2 words long, THIS and THAT"),
+            _ => None,
+        };
+        let max = match segment {
+            Segment::Temp => 7,
+            Segment::Pointer => 1,
+            _ => 32767,
+        };
+        if index > max {
+            let note = size.unwrap_or_default().to_owned();
+            return Err(error(ErrorKind::IndexOutOfRange, args[1]).with_note(note));
+        }
+        Ok((segment, index))
+    };
+
+    Ok(Some(match command {
+        "push" => {
+            let (segment, index) = segment_index()?;
+            VMInstruction::Push(segment, index)
+        }
+        "pop" => {
+            let (segment, index) = segment_index()?;
+            if matches!(segment, Segment::Constant) {
+                return Err(error(ErrorKind::PopConstant, args[0]));
+            }
+            VMInstruction::Pop(segment, index)
+        }
+        "label" => VMInstruction::Label(name(0)?),
+        "if-goto" => VMInstruction::IfGoto(name(0)?),
+        "goto" => VMInstruction::Goto(name(0)?),
+        "function" => VMInstruction::Function(name(0)?, number(1)?),
+        "call" => VMInstruction::Call(name(0)?, number(1)?),
         "return" => VMInstruction::Return,
         "add" => VMInstruction::Add,
         "sub" => VMInstruction::Sub,
@@ -51,6 +132,107 @@ pub fn parse_instruction(line: &str) -> VMInstruction {
         "and" => VMInstruction::And,
         "or" => VMInstruction::Or,
         "not" => VMInstruction::Not,
-        _ => unimplemented!(),
+        _ => unreachable!(),
+    }))
+}
+
+// Parses a whole `.vm` file into its instructions, each with its line number, or every
+// error found in it.
+pub fn parse_source(src: &str) -> Result<Vec<(usize, VMInstruction)>, Vec<VmError>> {
+    let mut instructions = Vec::new();
+    let mut errors = Vec::new();
+    for (i, line) in src.lines().enumerate() {
+        match parse_instruction(line, i + 1) {
+            Ok(Some(instruction)) => instructions.push((i + 1, instruction)),
+            Ok(None) => {}
+            Err(e) => errors.push(e),
+        }
+    }
+    if errors.is_empty() {
+        Ok(instructions)
+    } else {
+        Err(errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The error parsing `line`, as (kind, offending text, column, note).
+    fn error(line: &str) -> (ErrorKind, String, usize, Option<String>) {
+        let e = parse_instruction(line, 1).unwrap_err();
+        (e.kind, e.text, e.column, e.note)
+    }
+
+    #[test]
+    fn pop_constant() {
+        assert_eq!(
+            error("pop constant 3"),
+            (ErrorKind::PopConstant, "constant".to_owned(), 5, None)
+        );
+    }
+
+    #[test]
+    fn indices_past_fixed_segments() {
+        let note = |note: &str| Some(note.to_owned());
+        assert_eq!(
+            error("push temp 8"),
+            (
+                ErrorKind::IndexOutOfRange,
+                "8".to_owned(),
+                11,
+                note("temp is 8 words long, R5 to R12")
+            )
+        );
+        assert_eq!(
+            error("pop pointer 2"),
+            (
+                ErrorKind::IndexOutOfRange,
+                "2".to_owned(),
+                13,
+                note("pointer is 2 words long, THIS and THAT")
+            )
+        );
+        assert!(parse_instruction("pop temp 7", 1).is_ok());
+        assert!(parse_instruction("push pointer 1", 1).is_ok());
+    }
+
+    #[test]
+    fn indices_past_15_bits() {
+        assert_eq!(
+            error("push local 32768"),
+            (
+                ErrorKind::IndexOutOfRange,
+                "32768".to_owned(),
+                12,
+                Some("the largest index is 32767".to_owned())
+            )
+        );
+        assert_eq!(
+            error("call Main.f 99999"),
+            (
+                ErrorKind::IndexOutOfRange,
+                "99999".to_owned(),
+                13,
+                Some("the largest index is 32767".to_owned())
+            )
+        );
+        assert!(parse_instruction("push local 32767", 1).is_ok());
+    }
+
+    #[test]
+    fn errors_render_like_rustc() {
+        let e = parse_instruction("    pop constant 3 // oops", 12).unwrap_err();
+        assert_eq!(
+            e.with_file("Main.vm").render(),
+            "\
+error: can't pop into the constant segment
+  --> Main.vm:12:9
+   |
+12 |     pop constant 3 // oops
+   |         ^^^^^^^^
+"
+        );
     }
 }
@@ -0,0 +1,353 @@
+// Checks the stack discipline of every function before it's translated, since the
+// generated code would otherwise happily pop below the frame or return with values left
+// behind. Code outside of functions, like the course's early test files, isn't checked.
+
+use std::collections::HashMap;
+
+use crate::error::{ErrorKind, VmError};
+use crate::{Segment, SourceFile, VMInstruction};
+
+// How many values an instruction needs on the stack, and how many it leaves instead.
+fn stack_effect(instruction: &VMInstruction) -> (u16, u16) {
+    match instruction {
+        VMInstruction::Push(..) => (0, 1),
+        VMInstruction::Pop(..) | VMInstruction::IfGoto(_) => (1, 0),
+        VMInstruction::Add
+        | VMInstruction::Sub
+        | VMInstruction::Eq
+        | VMInstruction::Gt
+        | VMInstruction::Lt
+        | VMInstruction::And
+        | VMInstruction::Or => (2, 1),
+        VMInstruction::Neg | VMInstruction::Not => (1, 1),
+        VMInstruction::Call(_, args_count) => (*args_count, 1),
+        VMInstruction::Return => (1, 0),
+        VMInstruction::Function(..) | VMInstruction::Label(_) | VMInstruction::Goto(_) => (0, 0),
+    }
+}
+
+fn command(instruction: &VMInstruction) -> &'static str {
+    match instruction {
+        VMInstruction::Push(..) => "push",
+        VMInstruction::Pop(..) => "pop",
+        VMInstruction::Function(..) => "function",
+        VMInstruction::Call(..) => "call",
+        VMInstruction::Return => "return",
+        VMInstruction::Goto(_) => "goto",
+        VMInstruction::IfGoto(_) => "if-goto",
+        VMInstruction::Label(_) => "label",
+        VMInstruction::Add => "add",
+        VMInstruction::Sub => "sub",
+        VMInstruction::Neg => "neg",
+        VMInstruction::Eq => "eq",
+        VMInstruction::Gt => "gt",
+        VMInstruction::Lt => "lt",
+        VMInstruction::And => "and",
+        VMInstruction::Or => "or",
+        VMInstruction::Not => "not",
+    }
+}
+
+// The n-th word of a line's code, which the errors point at.
+fn word(line: &str, n: usize) -> &str {
+    let code = &line[..line.find("//").unwrap_or(line.len())];
+    code.split_whitespace().nth(n).unwrap_or(code.trim())
+}
+
+struct Function<'a> {
+    name: &'a str,
+    line: usize,
+    locals_count: u16,
+    // The instructions after `function`, up to the next one.
+    body: &'a [(usize, VMInstruction)],
+}
+
+struct Verifier<'a> {
+    src: &'a str,
+    // The most arguments each function is called with anywhere in the program.
+    args_counts: &'a HashMap<&'a str, u16>,
+    errors: Vec<VmError>,
+}
+
+impl Verifier<'_> {
+    fn error(&mut self, kind: ErrorKind, line: usize, word_index: usize, note: String) {
+        let source_line = self.src.lines().nth(line - 1).unwrap_or_default();
+        let text = word(source_line, word_index);
+        self.errors
+            .push(VmError::new(kind, line, source_line, text).with_note(note));
+    }
+
+    fn check_indices(&mut self, function: &Function) {
+        for (line, instruction) in function.body {
+            let (VMInstruction::Push(segment, index) | VMInstruction::Pop(segment, index)) =
+                instruction
+            else {
+                continue;
+            };
+            let note = match segment {
+                Segment::Local if *index >= function.locals_count => format!(
+                    "`{}` declares {} local{}",
+                    function.name,
+                    function.locals_count,
+                    if function.locals_count == 1 { "" } else { "s" }
+                ),
+                Segment::Argument => match self.args_counts.get(function.name) {
+                    Some(&args_count) if *index >= args_count => format!(
+                        "`{}` is never called with more than {} argument{}",
+                        function.name,
+                        args_count,
+                        if args_count == 1 { "" } else { "s" }
+                    ),
+                    _ => continue,
+                },
+                _ => continue,
+            };
+            self.error(ErrorKind::IndexOutOfRange, *line, 2, note);
+        }
+    }
+
+    // Follows every path through the function, the stack height at its start being 0,
+    // as the locals are below it.
+    fn check_stack(&mut self, function: &Function) {
+        let body = function.body;
+        let mut labels = HashMap::new();
+        for (i, (_, instruction)) in body.iter().enumerate() {
+            if let VMInstruction::Label(label) = instruction {
+                labels.insert(label.as_str(), i);
+            }
+        }
+
+        // The height before each instruction, and the line of the instruction it came from.
+        let mut heights: Vec<Option<(u16, usize)>> = vec![None; body.len()];
+        let mut reported_joins = Vec::new();
+        let mut worklist = Vec::new();
+        if !body.is_empty() {
+            heights[0] = Some((0, function.line));
+            worklist.push(0);
+        }
+        while let Some(i) = worklist.pop() {
+            let (line, instruction) = &body[i];
+            let (height, _) = heights[i].unwrap();
+            let (pops, pushes) = stack_effect(instruction);
+            let after = if height < pops {
+                let note = format!(
+                    "`{}` takes {} value{} off the stack, but there {} only {} by here",
+                    command(instruction),
+                    pops,
+                    if pops == 1 { "" } else { "s" },
+                    if height == 1 { "is" } else { "are" },
+                    height
+                );
+                self.error(ErrorKind::StackUnderflow, *line, 0, note);
+                pushes
+            } else {
+                height - pops + pushes
+            };
+            if matches!(instruction, VMInstruction::Return) && after > 0 {
+                let note = format!(
+                    "{} value{} below the return value {} thrown away",
+                    after,
+                    if after == 1 { "" } else { "s" },
+                    if after == 1 { "is" } else { "are" }
+                );
+                self.error(ErrorKind::ValuesLeftOnStack, *line, 0, note);
+            }
+
+            let mut successors = Vec::new();
+            match instruction {
+                VMInstruction::Goto(label) | VMInstruction::IfGoto(label) => {
+                    match labels.get(label.as_str()) {
+                        Some(&target) => successors.push(target),
+                        None => {
+                            let note = format!("labels are local to `{}`", function.name);
+                            self.error(ErrorKind::UndefinedLabel, *line, 1, note);
+                        }
+                    }
+                    if matches!(instruction, VMInstruction::IfGoto(_)) {
+                        successors.push(i + 1);
+                    }
+                }
+                VMInstruction::Return => {}
+                _ => successors.push(i + 1),
+            }
+            for next in successors.into_iter().filter(|&n| n < body.len()) {
+                match heights[next] {
+                    None => {
+                        heights[next] = Some((after, *line));
+                        worklist.push(next);
+                    }
+                    Some((h, from)) if h != after && !reported_joins.contains(&next) => {
+                        reported_joins.push(next);
+                        let note = format!(
+                            "the stack holds {} value{} when coming from line {}, but {} from line {}",
+                            h,
+                            if h == 1 { "" } else { "s" },
+                            from,
+                            after,
+                            line
+                        );
+                        self.error(ErrorKind::InconsistentStackHeight, body[next].0, 1, note);
+                    }
+                    Some(_) => {}
+                }
+            }
+        }
+    }
+}
+
+fn functions(instructions: &[(usize, VMInstruction)]) -> Vec<Function<'_>> {
+    let starts: Vec<usize> = (0..instructions.len())
+        .filter(|&i| matches!(instructions[i].1, VMInstruction::Function(..)))
+        .collect();
+    starts
+        .iter()
+        .enumerate()
+        .map(|(n, &start)| {
+            let end = starts.get(n + 1).copied().unwrap_or(instructions.len());
+            let VMInstruction::Function(name, locals_count) = &instructions[start].1 else {
+                unreachable!()
+            };
+            Function {
+                name,
+                line: instructions[start].0,
+                locals_count: *locals_count,
+                body: &instructions[start + 1..end],
+            }
+        })
+        .collect()
+}
+
+// Verifies the functions of every file of a program. `bootstrap` counts the bootstrap's
+// call of `Sys.init` as a call site.
+pub fn verify_program(
+    files: &[(&SourceFile, &[(usize, VMInstruction)])],
+    bootstrap: bool,
+) -> Vec<VmError> {
+    let mut args_counts: HashMap<&str, u16> = HashMap::new();
+    if bootstrap {
+        args_counts.insert("Sys.init", 0);
+    }
+    for (_, instructions) in files {
+        for (_, instruction) in instructions.iter() {
+            if let VMInstruction::Call(name, args_count) = instruction {
+                let max = args_counts.entry(name).or_insert(0);
+                *max = (*max).max(*args_count);
+            }
+        }
+    }
+
+    let mut errors = Vec::new();
+    for (file, instructions) in files {
+        let mut verifier = Verifier {
+            src: &file.src,
+            args_counts: &args_counts,
+            errors: Vec::new(),
+        };
+        for function in functions(instructions) {
+            verifier.check_indices(&function);
+            verifier.check_stack(&function);
+        }
+        verifier.errors.sort_by_key(|e| (e.line, e.column));
+        errors.extend(verifier.errors.into_iter().map(|e| e.with_file(&file.path)));
+    }
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_source;
+
+    // The errors verifying `src` as the whole program, as (kind, line, offending text).
+    fn verify(src: &str) -> Vec<(ErrorKind, usize, String)> {
+        let file = SourceFile {
+            name: "Main".to_owned(),
+            path: "Main.vm".to_owned(),
+            src: src.to_owned(),
+        };
+        let instructions = parse_source(src).unwrap();
+        verify_program(&[(&file, &instructions)], true)
+            .into_iter()
+            .map(|e| (e.kind, e.line, e.text))
+            .collect()
+    }
+
+    #[test]
+    fn accepts_balanced_functions() {
+        let src = "\
+function Sys.init 1
+push constant 2
+call Main.double 1
+pop local 0
+label HALT
+goto HALT
+function Main.double 0
+push argument 0
+push argument 0
+add
+return
+";
+        assert_eq!(verify(src), []);
+    }
+
+    #[test]
+    fn stack_underflow() {
+        let src = "function Sys.init 0\npush constant 1\nadd\nreturn\n";
+        assert_eq!(
+            verify(src),
+            [(ErrorKind::StackUnderflow, 3, "add".to_owned())]
+        );
+    }
+
+    #[test]
+    fn values_left_at_return() {
+        let src = "function Sys.init 0\npush constant 1\npush constant 2\nreturn\n";
+        assert_eq!(
+            verify(src),
+            [(ErrorKind::ValuesLeftOnStack, 4, "return".to_owned())]
+        );
+    }
+
+    #[test]
+    fn inconsistent_join_heights() {
+        let src = "\
+function Sys.init 0
+push constant 0
+if-goto SKIP
+push constant 1
+label SKIP
+push constant 2
+return
+";
+        let errors = verify(src);
+        assert_eq!(
+            errors[0].0,
+            ErrorKind::InconsistentStackHeight,
+            "{:?}",
+            errors
+        );
+        assert_eq!(errors[0].1, 5);
+    }
+
+    #[test]
+    fn local_and_argument_indices() {
+        let src = "\
+function Sys.init 0
+push constant 1
+call Main.f 1
+return
+function Main.f 2
+push local 2
+push argument 1
+add
+return
+";
+        assert_eq!(
+            verify(src),
+            [
+                (ErrorKind::IndexOutOfRange, 6, "2".to_owned()),
+                (ErrorKind::IndexOutOfRange, 7, "1".to_owned())
+            ]
+        );
+    }
+}